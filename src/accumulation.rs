@@ -0,0 +1,260 @@
+use std::io::{self, Read, Write};
+
+use crate::{color::Color, filter::Filter};
+
+const MAGIC: &[u8; 8] = b"RTACCUM1";
+
+/// Unnormalized radiance sums and sample weights for every pixel of a frame.
+///
+/// Buffers rendered with different seeds can be merged, the result being the same as a single
+/// render using all of their samples.
+pub struct AccumulationBuffer {
+    pub width: usize,
+    pub height: usize,
+    pub scene_hash: u64,
+    sums: Vec<Color>,
+    weights: Vec<f64>,
+}
+
+impl AccumulationBuffer {
+    pub fn new(width: usize, height: usize, scene_hash: u64) -> AccumulationBuffer {
+        AccumulationBuffer {
+            width,
+            height,
+            scene_hash,
+            sums: vec![Color::new(0.0, 0.0, 0.0); width * height],
+            weights: vec![0.0; width * height],
+        }
+    }
+
+    /// Adds a sample to the pixel at column `x` and row `y`, rows starting from the top.
+    pub fn add_sample(&mut self, x: usize, y: usize, color: Color, weight: f64) {
+        let index = y * self.width + x;
+        self.sums[index] = self.sums[index] + weight * color;
+        self.weights[index] += weight;
+    }
+
+    /// Adds a sample at the film position `x`, `y` in pixels from the top left corner to the pixels
+    /// within the radius of `filter`, weighted by the filter.
+    pub fn splat(&mut self, x: f64, y: f64, color: Color, filter: &Filter) {
+        let radius = filter.radius();
+        let first_column = ((x - 0.5 - radius).ceil() as isize).max(0);
+        let last_column = ((x - 0.5 + radius).floor() as isize).min(self.width as isize - 1);
+        let first_row = ((y - 0.5 - radius).ceil() as isize).max(0);
+        let last_row = ((y - 0.5 + radius).floor() as isize).min(self.height as isize - 1);
+
+        for row in first_row..=last_row {
+            for column in first_column..=last_column {
+                let weight = filter.weight(column as f64 + 0.5 - x, row as f64 + 0.5 - y);
+                if weight != 0.0 {
+                    self.add_sample(column as usize, row as usize, color, weight);
+                }
+            }
+        }
+    }
+
+    /// Returns the weighted average of the samples of a pixel.
+    pub fn pixel(&self, x: usize, y: usize) -> Color {
+        let index = y * self.width + x;
+        if self.weights[index] > 0.0 {
+            self.sums[index] / self.weights[index]
+        } else {
+            Color::new(0.0, 0.0, 0.0)
+        }
+    }
+
+    pub fn merge(&mut self, other: &AccumulationBuffer) -> io::Result<()> {
+        if self.width != other.width || self.height != other.height {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "cannot merge a {}x{} buffer into a {}x{} buffer",
+                    other.width, other.height, self.width, self.height
+                ),
+            ));
+        }
+        if self.scene_hash != other.scene_hash {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "cannot merge buffers of different scenes ({:016x} and {:016x})",
+                    self.scene_hash, other.scene_hash
+                ),
+            ));
+        }
+
+        for index in 0..self.sums.len() {
+            self.sums[index] = self.sums[index] + other.sums[index];
+            self.weights[index] += other.weights[index];
+        }
+
+        Ok(())
+    }
+
+    pub fn write_ppm(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "P3\n{} {}\n255", self.width, self.height)?;
+        for y in 0..self.height {
+            for x in 0..self.width {
+                self.pixel(x, y).write_color(out, 1.0)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Serializes the buffer: a magic number, the resolution and scene hash, then for every pixel
+    /// its red, green and blue sums followed by its weight, all little-endian.
+    pub fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(MAGIC)?;
+        out.write_all(&(self.width as u32).to_le_bytes())?;
+        out.write_all(&(self.height as u32).to_le_bytes())?;
+        out.write_all(&self.scene_hash.to_le_bytes())?;
+
+        for (sum, weight) in self.sums.iter().zip(&self.weights) {
+            for value in [sum.x, sum.y, sum.z, *weight] {
+                out.write_all(&value.to_le_bytes())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn read_from(input: &mut impl Read) -> io::Result<AccumulationBuffer> {
+        let mut magic = [0u8; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not an accumulation buffer",
+            ));
+        }
+
+        let width = read_u32(input)? as usize;
+        let height = read_u32(input)? as usize;
+        let mut hash = [0u8; 8];
+        input.read_exact(&mut hash)?;
+
+        let mut buffer = AccumulationBuffer::new(width, height, u64::from_le_bytes(hash));
+        for index in 0..width * height {
+            let r = read_f64(input)?;
+            let g = read_f64(input)?;
+            let b = read_f64(input)?;
+            buffer.sums[index] = Color::new(r, g, b);
+            buffer.weights[index] = read_f64(input)?;
+        }
+
+        Ok(buffer)
+    }
+}
+
+fn read_u32(input: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_f64(input: &mut impl Read) -> io::Result<f64> {
+    let mut bytes = [0u8; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(f64::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::*;
+
+    macro_rules! assert_vec3_equal {
+        ($expected:expr, $actual:expr) => {
+            let tolerance = 0.0001;
+            assert_relative_eq!($expected.x, $actual.x, epsilon = tolerance);
+            assert_relative_eq!($expected.y, $actual.y, epsilon = tolerance);
+            assert_relative_eq!($expected.z, $actual.z, epsilon = tolerance);
+        };
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut buffer = AccumulationBuffer::new(2, 1, 0xdead_beef);
+        buffer.add_sample(0, 0, Color::new(0.1, 0.2, 0.3), 1.0);
+        buffer.add_sample(1, 0, Color::new(1.0, 0.5, 0.25), 2.0);
+
+        let mut bytes = Vec::new();
+        buffer.write_to(&mut bytes).unwrap();
+        let result = AccumulationBuffer::read_from(&mut bytes.as_slice()).unwrap();
+
+        assert_eq!(2, result.width);
+        assert_eq!(1, result.height);
+        assert_eq!(0xdead_beef, result.scene_hash);
+        assert_vec3_equal!(Color::new(0.1, 0.2, 0.3), result.pixel(0, 0));
+        assert_vec3_equal!(Color::new(1.0, 0.5, 0.25), result.pixel(1, 0));
+        assert_eq!(2.0, result.weights[1]);
+    }
+
+    #[test]
+    fn splat_filtered_samples() {
+        // The box filter of half a pixel only adds a sample to its pixel
+        let mut buffer = AccumulationBuffer::new(3, 2, 0);
+        buffer.splat(
+            1.2,
+            0.7,
+            Color::new(1.0, 0.0, 0.0),
+            &Filter::Box { radius: 0.5 },
+        );
+        assert_eq!(1.0, buffer.weights[1]);
+        assert_eq!(1.0, buffer.weights.iter().sum::<f64>());
+
+        // Wider filters spread it to its neighbors, clipped at the edges
+        let mut buffer = AccumulationBuffer::new(3, 2, 0);
+        buffer.splat(
+            1.5,
+            0.5,
+            Color::new(1.0, 0.0, 0.0),
+            &Filter::Tent { radius: 1.5 },
+        );
+        assert_eq!(vec![0.75, 2.25, 0.75, 0.25, 0.75, 0.25], buffer.weights);
+
+        // Filtered samples of a constant image keep it constant, despite negative lobes
+        let mut buffer = AccumulationBuffer::new(4, 4, 0);
+        for y in 0..16 {
+            for x in 0..16 {
+                let (x, y) = ((x as f64 + 0.5) / 4.0, (y as f64 + 0.5) / 4.0);
+                buffer.splat(
+                    x,
+                    y,
+                    Color::new(0.5, 0.25, 1.0),
+                    &Filter::Lanczos { radius: 3.0 },
+                );
+            }
+        }
+        assert_vec3_equal!(Color::new(0.5, 0.25, 1.0), buffer.pixel(1, 2));
+    }
+
+    #[test]
+    fn read_invalid_magic() {
+        let bytes = b"NOTABUFFER".to_vec();
+        assert!(AccumulationBuffer::read_from(&mut bytes.as_slice()).is_err());
+    }
+
+    #[test]
+    fn merge_weights_by_samples() {
+        let mut first = AccumulationBuffer::new(1, 1, 1);
+        first.add_sample(0, 0, Color::new(1.0, 1.0, 1.0), 1.0);
+        let mut second = AccumulationBuffer::new(1, 1, 1);
+        for _ in 0..3 {
+            second.add_sample(0, 0, Color::new(0.0, 0.0, 0.0), 1.0);
+        }
+
+        first.merge(&second).unwrap();
+
+        assert_eq!(4.0, first.weights[0]);
+        assert_vec3_equal!(Color::new(0.25, 0.25, 0.25), first.pixel(0, 0));
+    }
+
+    #[test]
+    fn merge_mismatch() {
+        let mut buffer = AccumulationBuffer::new(2, 2, 1);
+
+        assert!(buffer.merge(&AccumulationBuffer::new(2, 1, 1)).is_err());
+        assert!(buffer.merge(&AccumulationBuffer::new(2, 2, 2)).is_err());
+    }
+}
@@ -0,0 +1,198 @@
+use std::io::{self, BufRead, Write};
+
+use crate::{
+    accumulation::AccumulationBuffer,
+    hittable::Hittable,
+    ray::Ray,
+    vec3::{Point3, Vec3},
+};
+
+/// Fraction of the cosine weighted hemisphere around `normal` from which the point `p` sees no
+/// geometry closer than `max_distance`, estimated with `samples` rays: 1 is fully open, 0 fully
+/// occluded.
+pub fn ambient_occlusion(
+    world: &dyn Hittable,
+    p: Point3,
+    normal: Vec3,
+    max_distance: f64,
+    samples: u32,
+) -> f64 {
+    let mut visible = 0;
+    for _ in 0..samples {
+        if !occluded(world, p, normal, max_distance) {
+            visible += 1;
+        }
+    }
+
+    visible as f64 / samples.max(1) as f64
+}
+
+/// Casts a single cosine weighted ray and tells whether it hits something within `max_distance`.
+pub fn occluded(world: &dyn Hittable, p: Point3, normal: Vec3, max_distance: f64) -> bool {
+    // Offsetting the unit normal by a point of the unit sphere gives a cosine distribution, the
+    // way the Lambertian material scatters
+    let mut direction = normal + Vec3::random_unit_vector();
+    if direction.near_zero() {
+        direction = normal;
+    }
+    let ray = Ray::new(p, direction.unit_vector());
+
+    world.occluded(&ray, max_distance, 0.001)
+}
+
+/// Bakes the occlusion of a list of vertices given by their position and normal.
+pub fn bake_vertices(
+    world: &dyn Hittable,
+    vertices: &[(Point3, Vec3)],
+    max_distance: f64,
+    samples: u32,
+) -> Vec<f64> {
+    vertices
+        .iter()
+        .map(|(p, normal)| {
+            ambient_occlusion(world, *p, normal.unit_vector(), max_distance, samples)
+        })
+        .collect()
+}
+
+/// Bakes the occlusion of `object` into a texture of its (u, v) parametrization, v = 1 being the
+/// top row. None when the object cannot map texture coordinates back to its surface.
+pub fn bake_texture(
+    world: &dyn Hittable,
+    object: &dyn Hittable,
+    width: usize,
+    height: usize,
+    max_distance: f64,
+    samples: u32,
+) -> Option<AccumulationBuffer> {
+    let mut texture = AccumulationBuffer::new(width, height, 0);
+
+    for y in 0..height {
+        for x in 0..width {
+            let u = (x as f64 + 0.5) / width as f64;
+            let v = 1.0 - (y as f64 + 0.5) / height as f64;
+            let (p, normal) = object.surface_point(u, v)?;
+
+            let visibility = ambient_occlusion(world, p, normal, max_distance, samples);
+            texture.add_sample(x, y, Vec3::new(visibility, visibility, visibility), 1.0);
+        }
+    }
+
+    Some(texture)
+}
+
+/// Reads vertices as lines of `x y z nx ny nz`, skipping empty lines and `#` comments.
+pub fn read_vertices(input: &mut impl BufRead) -> io::Result<Vec<(Point3, Vec3)>> {
+    let mut vertices = Vec::new();
+
+    for (number, line) in input.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let values: Vec<f64> = line
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map_err(|_| invalid_vertex(number))?;
+        match values.as_slice() {
+            [x, y, z, nx, ny, nz] => {
+                vertices.push((Point3::new(*x, *y, *z), Vec3::new(*nx, *ny, *nz)))
+            }
+            _ => return Err(invalid_vertex(number)),
+        }
+    }
+
+    Ok(vertices)
+}
+
+/// Writes one occlusion value per line, in the order of the vertices.
+pub fn write_values(out: &mut impl Write, values: &[f64]) -> io::Result<()> {
+    for value in values {
+        writeln!(out, "{}", value)?;
+    }
+    Ok(())
+}
+
+fn invalid_vertex(number: usize) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("line {}: expected 'x y z nx ny nz'", number + 1),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        color::Color, hittable_list::HittableList, material::Lambertian, random, sphere::Sphere,
+    };
+    use approx::*;
+
+    fn ground_and_ball() -> HittableList {
+        let mut world = HittableList::new();
+        world.add(Sphere::new(
+            Point3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+        ));
+        world.add(Sphere::new(
+            Point3::new(0.0, 1.0, 0.0),
+            1.0,
+            Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+        ));
+        world
+    }
+
+    #[test]
+    fn open_and_occluded_points() {
+        let world = ground_and_ball();
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        random::seed(3);
+
+        // Far from the ball the ground sees the whole sky
+        let open = ambient_occlusion(&world, Point3::new(50.0, 0.0, 0.0), up, 10.0, 64);
+        assert_relative_eq!(1.0, open);
+
+        // Next to the contact point most directions are blocked by the ball
+        let contact = ambient_occlusion(&world, Point3::new(0.3, 0.0, 0.0), up, 10.0, 512);
+        assert!(contact < 0.5);
+
+        // Occluders beyond the maximum distance are ignored
+        let short = ambient_occlusion(&world, Point3::new(1.5, 0.0, 0.0), up, 0.01, 64);
+        assert_relative_eq!(1.0, short);
+    }
+
+    #[test]
+    fn bake_sphere_texture() {
+        let world = ground_and_ball();
+        random::seed(4);
+
+        let texture = bake_texture(&world, world.objects[1].as_ref(), 8, 4, 10.0, 64).unwrap();
+
+        // The top of the ball is open while its bottom touches the ground
+        let top = texture.pixel(0, 0).x;
+        let bottom = texture.pixel(0, 3).x;
+        assert!(top > 0.95);
+        assert!(bottom < top);
+    }
+
+    #[test]
+    fn vertices() {
+        let text = "# x y z nx ny nz\n50 0 0 0 1 0\n\n0.3 0 0 0 2 0\n";
+        let vertices = read_vertices(&mut text.as_bytes()).unwrap();
+        assert_eq!(2, vertices.len());
+
+        random::seed(5);
+        let values = bake_vertices(&ground_and_ball(), &vertices, 10.0, 256);
+        assert!(values[0] > values[1]);
+
+        let mut out = Vec::new();
+        write_values(&mut out, &values).unwrap();
+        assert_eq!(2, String::from_utf8(out).unwrap().lines().count());
+
+        assert!(read_vertices(&mut "1 2 3".as_bytes()).is_err());
+    }
+}
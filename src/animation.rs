@@ -0,0 +1,340 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader},
+};
+
+use crate::{camera::View, vec3::Point3};
+
+/// How the camera moves between keyframes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interpolation {
+    Linear,
+    /// Smooth curve through every keyframe
+    CatmullRom,
+    /// Smooth curve through the first and last keyframes, the others being its control points
+    Bezier,
+}
+
+impl Interpolation {
+    pub const ALL: [Interpolation; 3] = [
+        Interpolation::Linear,
+        Interpolation::CatmullRom,
+        Interpolation::Bezier,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Interpolation::Linear => "linear",
+            Interpolation::CatmullRom => "catmull-rom",
+            Interpolation::Bezier => "bezier",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Interpolation> {
+        Interpolation::ALL
+            .into_iter()
+            .find(|interpolation| interpolation.name() == name)
+    }
+}
+
+/// Camera of a frame, the vertical field of view being in degrees.
+#[derive(Clone, Copy)]
+pub struct Keyframe {
+    pub frame: f64,
+    pub lookfrom: Point3,
+    pub lookat: Point3,
+    pub vfov: f64,
+    pub focus_dist: f64,
+}
+
+impl Keyframe {
+    fn values(&self) -> [f64; 8] {
+        [
+            self.lookfrom.x,
+            self.lookfrom.y,
+            self.lookfrom.z,
+            self.lookat.x,
+            self.lookat.y,
+            self.lookat.z,
+            self.vfov,
+            self.focus_dist,
+        ]
+    }
+
+    fn from_values(frame: f64, values: [f64; 8]) -> Keyframe {
+        Keyframe {
+            frame,
+            lookfrom: Point3::new(values[0], values[1], values[2]),
+            lookat: Point3::new(values[3], values[4], values[5]),
+            vfov: values[6],
+            focus_dist: values[7],
+        }
+    }
+}
+
+/// Keyframes of the camera, sorted by frame.
+pub struct CameraAnimation {
+    keyframes: Vec<Keyframe>,
+    interpolation: Interpolation,
+}
+
+impl CameraAnimation {
+    pub fn new(mut keyframes: Vec<Keyframe>, interpolation: Interpolation) -> CameraAnimation {
+        keyframes.sort_by(|a, b| a.frame.total_cmp(&b.frame));
+        CameraAnimation {
+            keyframes,
+            interpolation,
+        }
+    }
+
+    /// Reads keyframes as lines of `frame lookfrom_x lookfrom_y lookfrom_z lookat_x lookat_y
+    /// lookat_z vfov focus_dist`, skipping empty lines and `#` comments.
+    pub fn read(
+        input: &mut impl BufRead,
+        interpolation: Interpolation,
+    ) -> io::Result<CameraAnimation> {
+        let mut keyframes = Vec::new();
+
+        for (number, line) in input.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let values: Vec<f64> = line
+                .split_whitespace()
+                .map(str::parse)
+                .collect::<Result<_, _>>()
+                .map_err(|_| invalid_keyframe(number))?;
+            match values.as_slice() {
+                [frame, rest @ ..] if rest.len() == 8 => {
+                    keyframes.push(Keyframe::from_values(*frame, rest.try_into().unwrap()))
+                }
+                _ => return Err(invalid_keyframe(number)),
+            }
+        }
+
+        if keyframes.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the animation has no keyframe",
+            ));
+        }
+        Ok(CameraAnimation::new(keyframes, interpolation))
+    }
+
+    pub fn load(path: &str, interpolation: Interpolation) -> io::Result<CameraAnimation> {
+        CameraAnimation::read(&mut BufReader::new(File::open(path)?), interpolation)
+            .map_err(|error| io::Error::new(error.kind(), format!("{}: {}", path, error)))
+    }
+
+    /// Camera at `frame`, holding the first and last keyframes outside of the animation.
+    pub fn keyframe_at(&self, frame: f64) -> Keyframe {
+        let keyframes = &self.keyframes;
+        let (first, last) = (keyframes[0], keyframes[keyframes.len() - 1]);
+        if frame <= first.frame || keyframes.len() == 1 {
+            return Keyframe { frame, ..first };
+        }
+        if frame >= last.frame {
+            return Keyframe { frame, ..last };
+        }
+
+        let values = match self.interpolation {
+            Interpolation::Bezier => {
+                let u = (frame - first.frame) / (last.frame - first.frame);
+                let points: Vec<[f64; 8]> = keyframes.iter().map(Keyframe::values).collect();
+                de_casteljau(points, u)
+            }
+            Interpolation::Linear | Interpolation::CatmullRom => {
+                let next = keyframes.iter().position(|key| key.frame > frame).unwrap();
+                let (start, end) = (&keyframes[next - 1], &keyframes[next]);
+                let u = (frame - start.frame) / (end.frame - start.frame);
+
+                if self.interpolation == Interpolation::Linear {
+                    lerp(start.values(), end.values(), u)
+                } else {
+                    // The ends are repeated to get tangents there
+                    let before = &keyframes[next.saturating_sub(2)];
+                    let after = &keyframes[(next + 1).min(keyframes.len() - 1)];
+                    catmull_rom(
+                        [
+                            before.values(),
+                            start.values(),
+                            end.values(),
+                            after.values(),
+                        ],
+                        u,
+                    )
+                }
+            }
+        };
+
+        Keyframe::from_values(frame, values)
+    }
+
+    /// Moves `view` to the camera at `frame`.
+    pub fn place(&self, frame: f64, view: &mut View) {
+        let keyframe = self.keyframe_at(frame);
+        view.lookfrom = keyframe.lookfrom;
+        view.lookat = keyframe.lookat;
+        view.vfov = keyframe.vfov;
+        view.focus_dist = keyframe.focus_dist;
+    }
+}
+
+fn lerp(a: [f64; 8], b: [f64; 8], u: f64) -> [f64; 8] {
+    std::array::from_fn(|i| a[i] + u * (b[i] - a[i]))
+}
+
+/// Uniform Catmull-Rom spline between the middle two of four points.
+fn catmull_rom(points: [[f64; 8]; 4], u: f64) -> [f64; 8] {
+    let [p0, p1, p2, p3] = points;
+    let (u2, u3) = (u * u, u * u * u);
+    std::array::from_fn(|i| {
+        0.5 * (2.0 * p1[i]
+            + (p2[i] - p0[i]) * u
+            + (2.0 * p0[i] - 5.0 * p1[i] + 4.0 * p2[i] - p3[i]) * u2
+            + (3.0 * p1[i] - p0[i] - 3.0 * p2[i] + p3[i]) * u3)
+    })
+}
+
+fn de_casteljau(mut points: Vec<[f64; 8]>, u: f64) -> [f64; 8] {
+    while points.len() > 1 {
+        points = points
+            .windows(2)
+            .map(|pair| lerp(pair[0], pair[1], u))
+            .collect();
+    }
+    points[0]
+}
+
+fn invalid_keyframe(number: usize) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "line {}: expected 'frame lookfrom_x lookfrom_y lookfrom_z lookat_x lookat_y \
+             lookat_z vfov focus_dist'",
+            number + 1
+        ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::*;
+
+    macro_rules! assert_vec3_equal {
+        ($expected:expr, $actual:expr) => {
+            let tolerance = 0.0001;
+            assert_relative_eq!($expected.x, $actual.x, epsilon = tolerance);
+            assert_relative_eq!($expected.y, $actual.y, epsilon = tolerance);
+            assert_relative_eq!($expected.z, $actual.z, epsilon = tolerance);
+        };
+    }
+
+    const KEYFRAMES: &str = "# frame lookfrom lookat vfov focus
+1   0 0 10   0 0 0   40 10
+11  10 0 0   0 0 0   20 10
+
+21  0 0 -10  0 1 0   40 12";
+
+    fn animation(interpolation: Interpolation) -> CameraAnimation {
+        CameraAnimation::read(&mut KEYFRAMES.as_bytes(), interpolation).unwrap()
+    }
+
+    #[test]
+    fn linear() {
+        let animation = animation(Interpolation::Linear);
+        let middle = animation.keyframe_at(6.0);
+        assert_vec3_equal!(Point3::new(5.0, 0.0, 5.0), middle.lookfrom);
+        assert_relative_eq!(30.0, middle.vfov);
+
+        let later = animation.keyframe_at(16.0);
+        assert_vec3_equal!(Point3::new(0.0, 0.5, 0.0), later.lookat);
+        assert_relative_eq!(11.0, later.focus_dist);
+
+        // The ends hold
+        assert_vec3_equal!(
+            Point3::new(0.0, 0.0, 10.0),
+            animation.keyframe_at(-5.0).lookfrom
+        );
+        assert_vec3_equal!(
+            Point3::new(0.0, 0.0, -10.0),
+            animation.keyframe_at(30.0).lookfrom
+        );
+    }
+
+    #[test]
+    fn catmull_rom_through_keyframes() {
+        let linear = animation(Interpolation::Linear).keyframe_at(6.0);
+        let animation = animation(Interpolation::CatmullRom);
+        for (frame, lookfrom) in [
+            (1.0, Point3::new(0.0, 0.0, 10.0)),
+            (11.0, Point3::new(10.0, 0.0, 0.0)),
+            (21.0, Point3::new(0.0, 0.0, -10.0)),
+        ] {
+            assert_vec3_equal!(lookfrom, animation.keyframe_at(frame).lookfrom);
+        }
+
+        // The path bulges out around the middle keyframe instead of cutting the corner
+        let smooth = animation.keyframe_at(6.0);
+        assert!(smooth.lookfrom.length() > linear.lookfrom.length());
+
+        // Without kinks at the keyframes
+        let before = animation.keyframe_at(10.999).lookfrom;
+        let at = animation.keyframe_at(11.0).lookfrom;
+        let after = animation.keyframe_at(11.001).lookfrom;
+        assert_vec3_equal!(at - before, after - at);
+    }
+
+    #[test]
+    fn bezier_control_points() {
+        let animation = animation(Interpolation::Bezier);
+        assert_vec3_equal!(
+            Point3::new(0.0, 0.0, 10.0),
+            animation.keyframe_at(1.0).lookfrom
+        );
+        assert_vec3_equal!(
+            Point3::new(0.0, 0.0, -10.0),
+            animation.keyframe_at(21.0).lookfrom
+        );
+
+        // A quadratic curve is halfway at (p0 + 2 p1 + p2) / 4
+        let middle = animation.keyframe_at(11.0);
+        assert_vec3_equal!(Point3::new(5.0, 0.0, 0.0), middle.lookfrom);
+        assert_relative_eq!(30.0, middle.vfov);
+    }
+
+    #[test]
+    fn read_keyframes() {
+        assert!(
+            CameraAnimation::read(&mut "1 0 0 10 0 0 0 40".as_bytes(), Interpolation::Linear)
+                .is_err()
+        );
+        assert!(CameraAnimation::read(&mut "# none".as_bytes(), Interpolation::Linear).is_err());
+
+        // Keyframes are sorted by frame
+        let animation = CameraAnimation::read(
+            &mut "5 1 1 1 0 0 0 40 1\n0 0 0 0 0 0 -1 40 1".as_bytes(),
+            Interpolation::Linear,
+        )
+        .unwrap();
+        assert_vec3_equal!(
+            Point3::new(0.5, 0.5, 0.5),
+            animation.keyframe_at(2.5).lookfrom
+        );
+    }
+
+    #[test]
+    fn parse_names() {
+        for interpolation in Interpolation::ALL {
+            assert_eq!(
+                Some(interpolation),
+                Interpolation::parse(interpolation.name())
+            );
+        }
+        assert_eq!(None, Interpolation::parse("cubic"));
+    }
+}
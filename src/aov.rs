@@ -0,0 +1,197 @@
+use crate::{color::Color, hittable::HitRecord, ray::Ray, vec3::Vec3};
+
+/// Auxiliary passes recorded from the first hit of every camera ray.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Aov {
+    Albedo,
+    Normal,
+    Depth,
+    Position,
+    ObjectId,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 5] = [
+        Aov::Albedo,
+        Aov::Normal,
+        Aov::Depth,
+        Aov::Position,
+        Aov::ObjectId,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Albedo => "albedo",
+            Aov::Normal => "normal",
+            Aov::Depth => "depth",
+            Aov::Position => "position",
+            Aov::ObjectId => "object_id",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Aov> {
+        Aov::ALL.into_iter().find(|aov| aov.name() == name)
+    }
+
+    pub fn channels(&self) -> &'static [&'static str] {
+        match self {
+            Aov::Albedo => &["R", "G", "B"],
+            Aov::Normal | Aov::Position => &["X", "Y", "Z"],
+            Aov::Depth => &["Z"],
+            Aov::ObjectId => &["id"],
+        }
+    }
+}
+
+/// Per pixel averages of the first hit data, misses leaving a pixel at zero and its object id
+/// at -1.
+pub struct AovBuffer {
+    pub width: usize,
+    pub height: usize,
+    albedo: Vec<Color>,
+    normal: Vec<Vec3>,
+    position: Vec<Vec3>,
+    depth: Vec<f64>,
+    hits: Vec<f64>,
+    object_id: Vec<Option<usize>>,
+}
+
+impl AovBuffer {
+    pub fn new(width: usize, height: usize) -> AovBuffer {
+        let size = width * height;
+        AovBuffer {
+            width,
+            height,
+            albedo: vec![Color::new(0.0, 0.0, 0.0); size],
+            normal: vec![Vec3::new(0.0, 0.0, 0.0); size],
+            position: vec![Vec3::new(0.0, 0.0, 0.0); size],
+            depth: vec![0.0; size],
+            hits: vec![0.0; size],
+            object_id: vec![None; size],
+        }
+    }
+
+    /// Records the first hit of a camera ray going through the pixel at column `x` and row `y`,
+    /// rows starting from the top. The object id is the one of the first sample that hit.
+    pub fn add_sample(&mut self, x: usize, y: usize, ray: &Ray, record: Option<&HitRecord>) {
+        let index = y * self.width + x;
+
+        if let Some(record) = record {
+            self.albedo[index] = self.albedo[index] + record.material.albedo();
+            self.normal[index] = self.normal[index] + record.normal;
+            self.position[index] = self.position[index] + record.p;
+            self.depth[index] += record.t * ray.direction.length();
+            self.hits[index] += 1.0;
+            if self.object_id[index].is_none() {
+                self.object_id[index] = Some(record.object_id);
+            }
+        }
+    }
+
+    /// Returns the values of an AOV at a pixel, one per channel.
+    pub fn pixel(&self, aov: Aov, x: usize, y: usize) -> Vec<f64> {
+        let index = y * self.width + x;
+        let hits = self.hits[index].max(1.0);
+
+        match aov {
+            Aov::Albedo => vec3_values(self.albedo[index] / hits),
+            Aov::Normal => {
+                let normal = self.normal[index];
+                if normal.near_zero() {
+                    vec3_values(normal)
+                } else {
+                    vec3_values(normal.unit_vector())
+                }
+            }
+            Aov::Position => vec3_values(self.position[index] / hits),
+            Aov::Depth => vec![self.depth[index] / hits],
+            Aov::ObjectId => vec![self.object_id[index].map_or(-1.0, |id| id as f64)],
+        }
+    }
+
+    /// Returns every channel of an AOV as a plane of values, rows starting from the top.
+    pub fn planes(&self, aov: Aov) -> Vec<Vec<f32>> {
+        let mut planes = vec![Vec::new(); aov.channels().len()];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                for (plane, value) in planes.iter_mut().zip(self.pixel(aov, x, y)) {
+                    plane.push(value as f32);
+                }
+            }
+        }
+        planes
+    }
+}
+
+fn vec3_values(vector: Vec3) -> Vec<f64> {
+    vec![vector.x, vector.y, vector.z]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{material::Lambertian, vec3::Point3};
+
+    #[test]
+    fn parse_names() {
+        for aov in Aov::ALL {
+            assert_eq!(Some(aov), Aov::parse(aov.name()));
+        }
+        assert_eq!(None, Aov::parse("beauty"));
+    }
+
+    #[test]
+    fn averages_hits() {
+        let material = Lambertian::new(Color::new(0.5, 0.25, 1.0));
+        let mut record = HitRecord::new(
+            Point3::new(0.0, 0.0, -2.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            2.0,
+            true,
+            &material,
+        );
+        record.object_id = 3;
+
+        let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut buffer = AovBuffer::new(1, 1);
+        buffer.add_sample(0, 0, &ray, Some(&record));
+        record.t = 4.0;
+        buffer.add_sample(0, 0, &ray, Some(&record));
+        buffer.add_sample(0, 0, &ray, None);
+
+        assert_eq!(vec![3.0], buffer.pixel(Aov::Depth, 0, 0));
+        assert_eq!(vec![0.5, 0.25, 1.0], buffer.pixel(Aov::Albedo, 0, 0));
+        assert_eq!(vec![0.0, 0.0, 1.0], buffer.pixel(Aov::Normal, 0, 0));
+        assert_eq!(vec![3.0], buffer.pixel(Aov::ObjectId, 0, 0));
+    }
+
+    #[test]
+    fn depth_is_a_distance() {
+        let material = Lambertian::new(Color::new(1.0, 1.0, 1.0));
+        let record = HitRecord::new(
+            Point3::new(0.0, 0.0, -4.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            2.0,
+            true,
+            &material,
+        );
+        // The hit is reached at t = 2 along a direction twice as long as a unit vector
+        let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -2.0));
+        let mut buffer = AovBuffer::new(1, 1);
+        buffer.add_sample(0, 0, &ray, Some(&record));
+
+        assert_eq!(vec![4.0], buffer.pixel(Aov::Depth, 0, 0));
+    }
+
+    #[test]
+    fn misses() {
+        let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut buffer = AovBuffer::new(1, 1);
+        buffer.add_sample(0, 0, &ray, None);
+
+        assert_eq!(vec![0.0], buffer.pixel(Aov::Depth, 0, 0));
+        assert_eq!(vec![-1.0], buffer.pixel(Aov::ObjectId, 0, 0));
+        assert_eq!(1, buffer.planes(Aov::ObjectId).len());
+        assert_eq!(3, buffer.planes(Aov::Normal).len());
+    }
+}
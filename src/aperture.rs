@@ -0,0 +1,183 @@
+use std::{f64::consts::PI, rc::Rc};
+
+use crate::{distribution::Distribution2D, environment::luminance, image::Image, random};
+
+/// Shape of the opening of a lens, which gives its shape to the out of focus highlights.
+#[derive(Clone)]
+pub enum ApertureShape {
+    Circular,
+    /// Regular polygon of `blades` sides inscribed in the lens, turned by `rotation` degrees
+    Polygon {
+        blades: u32,
+        rotation: f64,
+    },
+    /// Image stretched over the square around the lens, its luminance telling how much light
+    /// goes through
+    Mask(Rc<ApertureMask>),
+}
+
+impl ApertureShape {
+    /// Samples a point of the aperture, within the unit disk for the circular and polygonal
+    /// shapes and the square around it for masks.
+    pub fn sample(&self) -> (f64, f64) {
+        match self {
+            ApertureShape::Circular => {
+                concentric_disk(random::random_double(), random::random_double())
+            }
+            ApertureShape::Polygon { blades, rotation } => polygon(
+                *blades,
+                rotation.to_radians(),
+                random::random_double(),
+                random::random_double(),
+                random::random_double(),
+            ),
+            ApertureShape::Mask(mask) => mask.sample(),
+        }
+    }
+}
+
+/// Maps the unit square to the unit disk with little distortion, keeping stratified samples well
+/// distributed, as described by Shirley and Chiu.
+pub fn concentric_disk(u0: f64, u1: f64) -> (f64, f64) {
+    let (a, b) = (2.0 * u0 - 1.0, 2.0 * u1 - 1.0);
+    if a == 0.0 && b == 0.0 {
+        return (0.0, 0.0);
+    }
+
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, PI / 4.0 * (b / a))
+    } else {
+        (b, PI / 2.0 - PI / 4.0 * (a / b))
+    };
+    (r * theta.cos(), r * theta.sin())
+}
+
+/// Uniform point of a regular polygon inscribed in the unit circle, picking one of the triangles
+/// between its center and sides with `u0`.
+fn polygon(blades: u32, rotation: f64, u0: f64, u1: f64, u2: f64) -> (f64, f64) {
+    let side = ((u0 * blades as f64) as u32).min(blades - 1);
+    let angle = 2.0 * PI / blades as f64;
+    let first = rotation + side as f64 * angle;
+    let (x0, y0) = (first.cos(), first.sin());
+    let (x1, y1) = ((first + angle).cos(), (first + angle).sin());
+
+    // Uniform barycentric coordinates of the triangle
+    let root = u1.sqrt();
+    let (b0, b1) = (root * (1.0 - u2), root * u2);
+    (b0 * x0 + b1 * x1, b0 * y0 + b1 * y1)
+}
+
+pub struct ApertureMask {
+    distribution: Distribution2D,
+}
+
+impl ApertureMask {
+    pub fn new(image: &Image) -> ApertureMask {
+        let weights: Vec<f64> = image.pixels.iter().map(|pixel| luminance(*pixel)).collect();
+
+        ApertureMask {
+            distribution: Distribution2D::new(&weights, image.width, image.height),
+        }
+    }
+
+    fn sample(&self) -> (f64, f64) {
+        let ((x, y), _) = self
+            .distribution
+            .sample_continuous(random::random_double(), random::random_double());
+
+        // The first row of the image is the top of the lens
+        (2.0 * x - 1.0, 1.0 - 2.0 * y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use approx::*;
+
+    #[test]
+    fn concentric_mapping() {
+        assert_eq!((0.0, 0.0), concentric_disk(0.5, 0.5));
+
+        let (x, y) = concentric_disk(1.0, 0.5);
+        assert_relative_eq!(1.0, x);
+        assert_relative_eq!(0.0, y);
+
+        // Corners of the square go to the diagonals of the circle
+        let (x, y) = concentric_disk(1.0, 1.0);
+        assert_relative_eq!(1.0, x * x + y * y, epsilon = 1e-12);
+        assert_relative_eq!(x, y, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn uniform_disk() {
+        random::seed(8);
+        let samples = 20000;
+        let mut inner = 0;
+        for _ in 0..samples {
+            let (x, y) = ApertureShape::Circular.sample();
+            let r2 = x * x + y * y;
+            assert!(r2 <= 1.0 + 1e-12);
+            if r2 < 0.25 {
+                inner += 1;
+            }
+        }
+
+        // The inner disk of half the radius covers a quarter of the area
+        assert_relative_eq!(0.25, inner as f64 / samples as f64, epsilon = 0.01);
+    }
+
+    #[test]
+    fn polygon_blades() {
+        random::seed(9);
+        let shape = ApertureShape::Polygon {
+            blades: 6,
+            rotation: 0.0,
+        };
+        // Every point is within the hexagon, whose sides are sqrt(3) / 2 from its center
+        let apothem = 3.0_f64.sqrt() / 2.0;
+        let mut farthest_on_y: f64 = 0.0;
+        for _ in 0..5000 {
+            let (x, y) = shape.sample();
+            for side in 0..6 {
+                let normal = (side as f64 + 0.5) * PI / 3.0;
+                assert!(x * normal.cos() + y * normal.sin() <= apothem + 1e-12);
+            }
+            farthest_on_y = farthest_on_y.max(y.abs());
+        }
+        assert!(farthest_on_y > 0.85 && farthest_on_y <= apothem + 1e-12);
+
+        // Turning the polygon brings a vertex to the vertical axis
+        let turned = ApertureShape::Polygon {
+            blades: 6,
+            rotation: 90.0,
+        };
+        let farthest = (0..5000)
+            .map(|_| turned.sample().1.abs())
+            .fold(0.0, f64::max);
+        assert!(farthest > apothem);
+    }
+
+    #[test]
+    fn image_mask() {
+        // Light only goes through the top left quarter
+        let mut pixels = vec![Color::new(0.0, 0.0, 0.0); 4 * 4];
+        for (x, y) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            pixels[y * 4 + x] = Color::new(1.0, 1.0, 1.0);
+        }
+        let image = Image {
+            width: 4,
+            height: 4,
+            pixels,
+        };
+        let shape = ApertureShape::Mask(Rc::new(ApertureMask::new(&image)));
+
+        random::seed(10);
+        for _ in 0..1000 {
+            let (x, y) = shape.sample();
+            assert!((-1.0..=0.0).contains(&x));
+            assert!((0.0..=1.0).contains(&y));
+        }
+    }
+}
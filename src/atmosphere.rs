@@ -0,0 +1,318 @@
+use std::f64::consts::PI;
+
+use crate::{
+    color::Color,
+    environment::Environment,
+    hittable::Hittable,
+    material::HenyeyGreenstein,
+    random,
+    ray::Ray,
+    vec3::{Point3, Vec3},
+};
+
+/// Radius of the earth, in meters.
+const PLANET_RADIUS: f64 = 6.36e6;
+/// Rayleigh scattering coefficients of the air at sea level for the red, green and blue
+/// channels, per meter.
+const RAYLEIGH_SCATTERING: [f64; 3] = [5.802e-6, 13.558e-6, 33.1e-6];
+/// Altitude over which the density of the air falls by e, in meters.
+const RAYLEIGH_SCALE_HEIGHT: f64 = 8000.0;
+/// Altitude over which the density of the aerosols falls by e, in meters.
+const MIE_SCALE_HEIGHT: f64 = 1200.0;
+/// Fraction of the light scattered rather than absorbed by the aerosols.
+const MIE_ALBEDO: f64 = 0.9;
+/// Henyey-Greenstein anisotropy of the aerosols.
+const MIE_ANISOTROPY: f64 = 0.76;
+/// Optical depth beyond which the fog is considered opaque.
+const OPAQUE_DEPTH: f64 = 7.0;
+/// Number of steps along each ray.
+const STEPS: usize = 8;
+
+/// Air of the earth, scattering by its molecules (Rayleigh) and aerosols (Mie) with densities
+/// falling exponentially with the altitude above a spherical planet.
+pub struct Air {
+    meters_per_unit: f64,
+    altitude: f64,
+    mie_scattering: f64,
+}
+
+impl Air {
+    /// `altitude` is that of the scene origin in meters, the turbidity from 2 (clear) to 10
+    /// (hazy) sets the density of the aerosols like that of the sky model.
+    pub fn new(meters_per_unit: f64, altitude: f64, turbidity: f64) -> Air {
+        Air {
+            meters_per_unit,
+            altitude,
+            mie_scattering: 2.0e-6 * (turbidity - 1.0).max(0.0),
+        }
+    }
+
+    /// Altitude of a point of the scene in meters, following the curvature of the planet.
+    fn altitude(&self, p: Point3) -> f64 {
+        let p = self.meters_per_unit * p + Vec3::new(0.0, PLANET_RADIUS + self.altitude, 0.0);
+        (p.length() - PLANET_RADIUS).max(0.0)
+    }
+
+    /// Rayleigh and Mie scattering coefficients at a point, per scene unit.
+    fn scattering(&self, p: Point3) -> (Color, f64) {
+        let altitude = self.altitude(p);
+        let [r, g, b] = RAYLEIGH_SCATTERING;
+        let rayleigh = (-altitude / RAYLEIGH_SCALE_HEIGHT).exp() * self.meters_per_unit;
+        let mie = (-altitude / MIE_SCALE_HEIGHT).exp() * self.meters_per_unit;
+        (rayleigh * Color::new(r, g, b), mie * self.mie_scattering)
+    }
+}
+
+/// Fog whose density falls exponentially with the height.
+pub struct HeightFog {
+    density: f64,
+    height: f64,
+    falloff: f64,
+}
+
+impl HeightFog {
+    /// `density` is the extinction coefficient at `height`, divided by e every `1 / falloff`
+    /// units above it.
+    pub fn new(density: f64, height: f64, falloff: f64) -> HeightFog {
+        HeightFog {
+            density,
+            height,
+            falloff,
+        }
+    }
+
+    fn density(&self, y: f64) -> f64 {
+        self.density * (-self.falloff * (y - self.height)).exp()
+    }
+
+    /// Optical depth from `origin` to infinity along the unit vector `direction`, in closed form.
+    fn optical_depth(&self, origin: Point3, direction: Vec3) -> f64 {
+        let climb = self.falloff * direction.y;
+        if climb <= 0.0 {
+            return if self.density > 0.0 {
+                f64::INFINITY
+            } else {
+                0.0
+            };
+        }
+        self.density(origin.y) / climb
+    }
+
+    /// Distance along the unit vector `direction` beyond which the fog no longer matters, being
+    /// either too thin or opaque.
+    fn extent(&self, origin: Point3, direction: Vec3) -> f64 {
+        if self.density <= 0.0 {
+            return 0.0;
+        }
+        // Height above which the fog is a thousandth of its density
+        let top = self.height + 1000.0_f64.ln() / self.falloff;
+        let entry = if origin.y > top {
+            if direction.y >= 0.0 {
+                return 0.0;
+            }
+            (origin.y - top) / -direction.y
+        } else {
+            0.0
+        };
+        let exit = if direction.y > 0.0 {
+            (top - origin.y) / direction.y
+        } else {
+            f64::INFINITY
+        };
+        exit.min(entry + OPAQUE_DEPTH / self.density(origin.y.min(top)))
+    }
+}
+
+/// Participating media filling the whole scene: the air, seen over the distances between the
+/// objects only since the sky already holds the atmosphere beyond them, and a height fog.
+///
+/// Rays are marched with jittered steps, the light scattered at each step towards the camera
+/// being that of a sample of the environment, so that the haze takes the color of the sun.
+pub struct Atmosphere {
+    air: Option<Air>,
+    fog: Option<HeightFog>,
+}
+
+impl Atmosphere {
+    pub fn new() -> Atmosphere {
+        Atmosphere {
+            air: None,
+            fog: None,
+        }
+    }
+
+    pub fn with_air(mut self, air: Air) -> Atmosphere {
+        self.air = Some(air);
+        self
+    }
+
+    pub fn with_fog(mut self, fog: HeightFog) -> Atmosphere {
+        self.fog = Some(fog);
+        self
+    }
+
+    /// Fraction of the light of the environment reaching `origin` along the unit vector
+    /// `direction`, through the fog.
+    pub fn sky_transmittance(&self, origin: Point3, direction: Vec3) -> Color {
+        let depth = match &self.fog {
+            Some(fog) => fog.optical_depth(origin, direction),
+            None => 0.0,
+        };
+        let transmittance = (-depth).exp();
+        Color::new(transmittance, transmittance, transmittance)
+    }
+
+    /// Extinction coefficients at a point, along with the radiance scattered towards `-direction`
+    /// per unit length for light arriving along `-light` (the unit vectors pointing away from the
+    /// point), per unit of that light.
+    fn coefficients(&self, p: Point3, air: bool, cos_theta: f64) -> (Color, Color) {
+        let (mut extinction, mut scattering) =
+            (Color::new(0.0, 0.0, 0.0), Color::new(0.0, 0.0, 0.0));
+        if let (true, Some(air_model)) = (air, &self.air) {
+            let (rayleigh, mie) = air_model.scattering(p);
+            let mie_phase = HenyeyGreenstein::new(Color::new(1.0, 1.0, 1.0), MIE_ANISOTROPY);
+            extinction = rayleigh + Color::new(1.0, 1.0, 1.0) * (mie / MIE_ALBEDO);
+            scattering = 3.0 / (16.0 * PI) * (1.0 + cos_theta * cos_theta) * rayleigh
+                + Color::new(1.0, 1.0, 1.0) * (mie * mie_phase.phase(cos_theta));
+        }
+        if let Some(fog) = &self.fog {
+            let density = fog.density(p.y);
+            extinction = extinction + Color::new(density, density, density);
+            scattering = scattering + Color::new(1.0, 1.0, 1.0) * (density / (4.0 * PI));
+        }
+        (extinction, scattering)
+    }
+
+    /// Marches `ray` up to the parameter `t_max`, or out of the fog when the ray leaves the scene,
+    /// returning the transmittance along it and the radiance scattered into it towards its origin.
+    pub fn march(
+        &self,
+        ray: &Ray,
+        t_max: Option<f64>,
+        world: &dyn Hittable,
+        environment: &dyn Environment,
+    ) -> (Color, Color) {
+        let direction = ray.direction.unit_vector();
+        let (distance, air) = match (t_max, &self.fog) {
+            (Some(t), _) => (t * ray.direction.length(), true),
+            (None, Some(fog)) => (fog.extent(ray.origin, direction), false),
+            (None, None) => (0.0, false),
+        };
+        if distance <= 0.0 {
+            return (Color::new(1.0, 1.0, 1.0), Color::new(0.0, 0.0, 0.0));
+        }
+
+        let step = distance / STEPS as f64;
+        let offset = random::random_double();
+        let mut depth = Color::new(0.0, 0.0, 0.0);
+        let mut inscattered = Color::new(0.0, 0.0, 0.0);
+
+        for i in 0..STEPS {
+            let p = ray.origin + (i as f64 + offset) * step * direction;
+
+            // Environments which cannot be sampled are lit uniformly over the sphere
+            let (light, radiance, pdf) = environment.sample().unwrap_or_else(|| {
+                let light = Vec3::random_unit_vector();
+                (light, environment.radiance(light), 1.0 / (4.0 * PI))
+            });
+            let (extinction, scattering) = self.coefficients(p, air, direction.dot(light));
+            let transmittance = exp(-(depth + 0.5 * step * extinction));
+
+            if scattering.length() > 0.0 {
+                let shadow_ray = Ray::new(p, light);
+                let visibility = world.transmittance(&shadow_ray, f64::INFINITY, 0.001);
+                if visibility > 0.0 {
+                    inscattered = inscattered
+                        + visibility * step / pdf
+                            * transmittance
+                            * scattering
+                            * self.sky_transmittance(p, light)
+                            * radiance;
+                }
+            }
+            depth = depth + step * extinction;
+        }
+
+        (exp(-depth), inscattered)
+    }
+}
+
+fn exp(v: Vec3) -> Color {
+    Color::new(v.x.exp(), v.y.exp(), v.z.exp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{environment::SkyGradient, hittable_list::HittableList, sky::PreethamSky};
+    use approx::*;
+
+    #[test]
+    fn fog_transmittance() {
+        let fog = HeightFog::new(0.5, 1.0, 2.0);
+        let atmosphere = Atmosphere::new().with_fog(fog);
+        let origin = Point3::new(0.0, 1.0, 0.0);
+
+        // Straight up through the fog, its density integrates to 0.5 / 2
+        let up = atmosphere.sky_transmittance(origin, Vec3::new(0.0, 1.0, 0.0));
+        assert_relative_eq!((-0.25_f64).exp(), up.x);
+        let slanted = atmosphere.sky_transmittance(origin, Vec3::new(0.8, 0.6, 0.0));
+        assert_relative_eq!((-0.25_f64 / 0.6).exp(), slanted.y);
+        // Towards the horizon it is endless
+        let level = atmosphere.sky_transmittance(origin, Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(0.0, level.z);
+
+        // Horizontally the density is constant
+        let world = HittableList::new();
+        let ray = Ray::new(origin, Vec3::new(0.0, 0.0, -2.0));
+        let (transmittance, _) = atmosphere.march(&ray, Some(1.5), &world, &SkyGradient);
+        assert_relative_eq!((-1.5_f64).exp(), transmittance.x, epsilon = 1e-9);
+    }
+
+    #[test]
+    fn air_reddens() {
+        let atmosphere = Atmosphere::new().with_air(Air::new(1000.0, 0.0, 3.0));
+        let world = HittableList::new();
+
+        // Ten kilometers along the ground
+        let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let (transmittance, _) = atmosphere.march(&ray, Some(10.0), &world, &SkyGradient);
+        assert!(transmittance.x > transmittance.y && transmittance.y > transmittance.z);
+        // Mostly Rayleigh scattering in the blue, the planet barely curving over that distance
+        let expected = (-10000.0 * (33.1e-6 + 4.0e-6 / MIE_ALBEDO)).exp();
+        assert_relative_eq!(expected, transmittance.z, epsilon = 0.01);
+
+        // The air high above is thinner
+        let high = Atmosphere::new().with_air(Air::new(1000.0, 5000.0, 3.0));
+        let (thin, _) = high.march(&ray, Some(10.0), &world, &SkyGradient);
+        assert!(thin.z > transmittance.z);
+
+        // Rays leaving the scene only cross the fog
+        let (escaped, light) = atmosphere.march(&ray, None, &world, &SkyGradient);
+        assert_eq!(1.0, escaped.z);
+        assert_eq!(0.0, light.x);
+    }
+
+    #[test]
+    fn haze_follows_the_sun() {
+        let atmosphere = Atmosphere::new().with_air(Air::new(1000.0, 0.0, 3.0));
+        let world = HittableList::new();
+        let haze = |elevation: f64| {
+            let sky = PreethamSky::new(elevation, 0.0, 3.0, 0.27, 1.0);
+            // Looking along the ground towards the sun
+            let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+            let mut total = Color::new(0.0, 0.0, 0.0);
+            for _ in 0..2000 {
+                total = total + atmosphere.march(&ray, Some(5.0), &world, &sky).1;
+            }
+            total
+        };
+
+        random::seed(45);
+        let noon = haze(60.0);
+        let sunset = haze(3.0);
+        // Blue by day, orange around the setting sun
+        assert!(noon.z > noon.x);
+        assert!(sunset.x > sunset.y && sunset.y > sunset.z);
+    }
+}
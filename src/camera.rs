@@ -1,22 +1,214 @@
-use std::f64::consts::PI;
+use std::{f64::consts::PI, rc::Rc};
 
 use crate::{
+    aperture::ApertureShape,
+    color::Color,
+    cv_camera::{CvCamera, Intrinsics},
+    distortion::{DistortedCamera, Distortion},
+    lens_system::{LensCamera, LensSystem},
+    panoramic::{Panorama, PanoramicCamera, DEFAULT_FISHEYE_FOV},
+    physical_camera::{Photographic, PhysicalCamera},
     ray::Ray,
     vec3::{Point3, Vec3},
 };
 
-pub struct Camera {
+/// Maps image coordinates to the rays leaving the camera.
+pub trait Camera {
+    /// Ray through the point at `s` from the left edge and `t` from the bottom edge of the image,
+    /// both in [0, 1]. None where the projection does not cover the image.
+    fn get_ray(&self, s: f64, t: f64) -> Option<Ray>;
+
+    /// Ray through (s, t) along with the weight of its sample for every channel, which lenses
+    /// make vary with vignetting and dispersion.
+    fn get_weighted_ray(&self, s: f64, t: f64) -> Option<(Ray, Color)> {
+        self.get_ray(s, t)
+            .map(|ray| (ray, Color::new(1.0, 1.0, 1.0)))
+    }
+
+    /// Factor applied to the radiance reaching the image.
+    fn exposure(&self) -> f64 {
+        1.0
+    }
+}
+
+/// Where a camera stands and looks, shared by every projection.
+#[derive(Clone)]
+pub struct View {
+    pub lookfrom: Point3,
+    pub lookat: Point3,
+    pub vup: Vec3,
+    /// Vertical field of view in degrees
+    pub vfov: f64,
+    pub aperture: f64,
+    pub aperture_shape: ApertureShape,
+    pub focus_dist: f64,
+    /// Prescription the physical camera traces through instead of a thin lens
+    pub lens: Option<Rc<LensSystem>>,
+    pub exit_pupil_bounds: bool,
+    pub distortion: Distortion,
+}
+
+impl View {
+    pub fn camera(&self, projection: Projection, aspect_ratio: f64) -> Box<dyn Camera> {
+        let camera = self.ideal_camera(projection, aspect_ratio);
+        match self.half_fov_tangents(projection, aspect_ratio) {
+            Some((tan_width, tan_height)) if !self.distortion.is_identity() => Box::new(
+                DistortedCamera::new(camera, self.distortion, tan_width, tan_height),
+            ),
+            _ => camera,
+        }
+    }
+
+    /// Tangents of the half fields of view of the planar projections, which normalize the image
+    /// coordinates of the distortion.
+    pub fn half_fov_tangents(
+        &self,
+        projection: Projection,
+        aspect_ratio: f64,
+    ) -> Option<(f64, f64)> {
+        match projection {
+            Projection::Perspective => {
+                let tan_height = (self.vfov * PI / 360.0).tan() / self.focus_dist;
+                Some((aspect_ratio * tan_height, tan_height))
+            }
+            Projection::Physical(settings) => Some(settings.half_fov_tangents(aspect_ratio)),
+            _ => None,
+        }
+    }
+
+    fn ideal_camera(&self, projection: Projection, aspect_ratio: f64) -> Box<dyn Camera> {
+        match projection {
+            Projection::Perspective => Box::new(
+                PerspectiveCamera::new(
+                    self.lookfrom,
+                    self.lookat,
+                    self.vup,
+                    self.vfov,
+                    aspect_ratio,
+                    self.aperture,
+                    self.focus_dist,
+                )
+                .with_aperture_shape(self.aperture_shape.clone()),
+            ),
+            Projection::Orthographic { view_height } => Box::new(OrthographicCamera::new(
+                self.lookfrom,
+                self.lookat,
+                self.vup,
+                view_height.unwrap_or_else(|| self.view_height()),
+                aspect_ratio,
+            )),
+            Projection::Physical(settings) if self.lens.is_some() => {
+                let camera = LensCamera::new(
+                    self.lookfrom,
+                    self.lookat,
+                    self.vup,
+                    self.lens.as_ref().unwrap(),
+                    &settings,
+                    aspect_ratio,
+                    self.focus_dist,
+                );
+                if self.exit_pupil_bounds {
+                    Box::new(camera.with_exit_pupil_bounds())
+                } else {
+                    Box::new(camera)
+                }
+            }
+            Projection::Physical(settings) => Box::new(
+                PhysicalCamera::new(
+                    self.lookfrom,
+                    self.lookat,
+                    self.vup,
+                    &settings,
+                    aspect_ratio,
+                    self.focus_dist,
+                )
+                .with_aperture_shape(self.aperture_shape.clone()),
+            ),
+            Projection::Cv(intrinsics) => Box::new(
+                CvCamera::new(self.lookfrom, self.lookat, self.vup, intrinsics)
+                    .with_distortion(self.distortion),
+            ),
+            Projection::Panoramic(panorama) => Box::new(PanoramicCamera::new(
+                self.lookfrom,
+                self.lookat,
+                self.vup,
+                panorama,
+                aspect_ratio,
+            )),
+        }
+    }
+
+    /// Height of the perspective view at the distance of the point looked at. The viewport of the
+    /// perspective camera lies at the focus distance while keeping the size given by `vfov`, which
+    /// narrows the actual field of view as the focus distance grows.
+    fn view_height(&self) -> f64 {
+        let distance = (self.lookat - self.lookfrom).length();
+        2.0 * distance * (self.vfov * PI / 360.0).tan() / self.focus_dist
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    Perspective,
+    /// Without a view height, frames the point looked at like the perspective projection
+    Orthographic {
+        view_height: Option<f64>,
+    },
+    Physical(Photographic),
+    /// Pinhole of an intrinsic matrix, as calibrated in computer vision
+    Cv(Intrinsics),
+    Panoramic(Panorama),
+}
+
+impl Projection {
+    pub const ALL: [Projection; 8] = [
+        Projection::Perspective,
+        Projection::Orthographic { view_height: None },
+        Projection::Physical(Photographic::DEFAULT),
+        Projection::Cv(Intrinsics::DEFAULT),
+        Projection::Panoramic(Panorama::Equirectangular),
+        Projection::Panoramic(Panorama::EquidistantFisheye {
+            fov: DEFAULT_FISHEYE_FOV,
+        }),
+        Projection::Panoramic(Panorama::EquisolidFisheye {
+            fov: DEFAULT_FISHEYE_FOV,
+        }),
+        Projection::Panoramic(Panorama::CubeMap),
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Projection::Perspective => "perspective",
+            Projection::Orthographic { .. } => "orthographic",
+            Projection::Physical(_) => "physical",
+            Projection::Cv(_) => "cv",
+            Projection::Panoramic(Panorama::Equirectangular) => "equirectangular",
+            Projection::Panoramic(Panorama::EquidistantFisheye { .. }) => "fisheye",
+            Projection::Panoramic(Panorama::EquisolidFisheye { .. }) => "fisheye-equisolid",
+            Projection::Panoramic(Panorama::CubeMap) => "cubemap",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Projection> {
+        Projection::ALL
+            .into_iter()
+            .find(|projection| projection.name() == name)
+    }
+}
+
+/// Pinhole or thin lens camera.
+pub struct PerspectiveCamera {
     origin: Point3,
     lower_left_corner: Point3,
     horizontal: Vec3,
     vertical: Vec3,
     u: Vec3,
     v: Vec3,
-    w: Vec3,
     lens_radius: f64,
+    aperture_shape: ApertureShape,
 }
 
-impl Camera {
+impl PerspectiveCamera {
     pub fn new(
         lookfrom: Point3,
         lookat: Point3,
@@ -25,7 +217,7 @@ impl Camera {
         aspect_ratio: f64,
         aperture: f64,
         focus_dist: f64,
-    ) -> Camera {
+    ) -> PerspectiveCamera {
         let theta: f64 = vfov * PI / 180.0;
         let h: f64 = (theta / 2.0).tan();
         let viewport_height: f64 = 2.0 * h;
@@ -42,27 +234,165 @@ impl Camera {
 
         let lens_radius = aperture / 2.0;
 
-        Camera {
+        PerspectiveCamera {
             origin,
             lower_left_corner,
             horizontal,
             vertical,
             u,
             v,
-            w,
             lens_radius,
+            aperture_shape: ApertureShape::Circular,
         }
     }
 
-    pub fn get_ray(&self, s: f64, t: f64) -> Ray {
-        let rd = self.lens_radius * Vec3::random_in_unit_sphere();
-        let offset = self.u * rd.x + self.v * rd.y;
+    pub fn with_aperture_shape(self, aperture_shape: ApertureShape) -> PerspectiveCamera {
+        PerspectiveCamera {
+            aperture_shape,
+            ..self
+        }
+    }
+}
+
+impl Camera for PerspectiveCamera {
+    fn get_ray(&self, s: f64, t: f64) -> Option<Ray> {
+        let (x, y) = self.aperture_shape.sample();
+        let offset = self.lens_radius * (self.u * x + self.v * y);
+
+        Some(Ray::new(
+            self.origin + offset,
+            self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin - offset,
+        ))
+    }
+}
+
+/// Parallel projection of a view `view_height` high, for technical and architectural views.
+pub struct OrthographicCamera {
+    lower_left_corner: Point3,
+    horizontal: Vec3,
+    vertical: Vec3,
+    direction: Vec3,
+}
+
+impl OrthographicCamera {
+    pub fn new(
+        lookfrom: Point3,
+        lookat: Point3,
+        vup: Vec3,
+        view_height: f64,
+        aspect_ratio: f64,
+    ) -> OrthographicCamera {
+        let w = (lookfrom - lookat).unit_vector();
+        let u = (vup.cross_product(w)).unit_vector();
+        let v = w.cross_product(u);
+
+        let horizontal = aspect_ratio * view_height * u;
+        let vertical = view_height * v;
+
+        OrthographicCamera {
+            lower_left_corner: lookfrom - horizontal / 2.0 - vertical / 2.0,
+            horizontal,
+            vertical,
+            direction: -w,
+        }
+    }
+}
+
+impl Camera for OrthographicCamera {
+    fn get_ray(&self, s: f64, t: f64) -> Option<Ray> {
+        Some(Ray::new(
+            self.lower_left_corner + s * self.horizontal + t * self.vertical,
+            self.direction,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::*;
+
+    macro_rules! assert_vec3_equal {
+        ($expected:expr, $actual:expr) => {
+            let tolerance = 0.0001;
+            assert_relative_eq!($expected.x, $actual.x, epsilon = tolerance);
+            assert_relative_eq!($expected.y, $actual.y, epsilon = tolerance);
+            assert_relative_eq!($expected.z, $actual.z, epsilon = tolerance);
+        };
+    }
+
+    fn view() -> View {
+        View {
+            lookfrom: Point3::new(0.0, 0.0, 2.0),
+            lookat: Point3::new(0.0, 0.0, 0.0),
+            vup: Vec3::new(0.0, 1.0, 0.0),
+            vfov: 90.0,
+            aperture: 0.0,
+            aperture_shape: ApertureShape::Circular,
+            focus_dist: 2.0,
+            lens: None,
+            exit_pupil_bounds: false,
+            distortion: Distortion::default(),
+        }
+    }
+
+    #[test]
+    fn perspective_rays() {
+        let view = View {
+            focus_dist: 1.0,
+            ..view()
+        };
+        let camera = view.camera(Projection::Perspective, 2.0);
+
+        let center = camera.get_ray(0.5, 0.5).unwrap();
+        assert_vec3_equal!(Point3::new(0.0, 0.0, 2.0), center.origin);
+        assert_vec3_equal!(Vec3::new(0.0, 0.0, -1.0), center.direction.unit_vector());
+
+        // A 90 degrees field of view reaches 45 degrees up at the top edge
+        let top = camera.get_ray(0.5, 1.0).unwrap().direction.unit_vector();
+        assert_relative_eq!(45.0, top.y.asin().to_degrees(), epsilon = 1e-9);
+    }
+
+    #[test]
+    fn orthographic_rays() {
+        let projection = Projection::Orthographic {
+            view_height: Some(3.0),
+        };
+        let camera = view().camera(projection, 2.0);
+
+        // Rays are parallel and leave from a rectangle 6 wide and 3 high around the eye
+        let corner = camera.get_ray(0.0, 0.0).unwrap();
+        let opposite = camera.get_ray(1.0, 1.0).unwrap();
+        assert_vec3_equal!(Point3::new(-3.0, -1.5, 2.0), corner.origin);
+        assert_vec3_equal!(Point3::new(3.0, 1.5, 2.0), opposite.origin);
+        assert_vec3_equal!(Vec3::new(0.0, 0.0, -1.0), corner.direction);
+        assert_vec3_equal!(corner.direction, opposite.direction);
+    }
+
+    #[test]
+    fn orthographic_default_height() {
+        // Frames the plane of the point looked at like the perspective projection
+        let view = view();
+        let perspective = view
+            .camera(Projection::Perspective, 1.0)
+            .get_ray(0.5, 1.0)
+            .unwrap();
+        let orthographic = view.camera(Projection::Orthographic { view_height: None }, 1.0);
+
+        let top = orthographic.get_ray(0.5, 1.0).unwrap().origin;
+        let distance = (view.lookat - view.lookfrom).length();
+        assert_relative_eq!(
+            perspective.direction.y / -perspective.direction.z * distance,
+            top.y,
+            epsilon = 1e-9
+        );
+    }
 
-        Ray {
-            origin: self.origin + offset,
-            direction: self.lower_left_corner + s * self.horizontal + t * self.vertical
-                - self.origin
-                - offset,
+    #[test]
+    fn parse_names() {
+        for projection in Projection::ALL {
+            assert_eq!(Some(projection), Projection::parse(projection.name()));
         }
+        assert_eq!(None, Projection::parse("isometric"));
     }
 }
@@ -0,0 +1,1241 @@
+pub const USAGE: &str = "usage:
+  ray-tracing [render] [options]
+      --scene NAME          scene to render, 'final' (default), 'glass', 'materials', 'metals',
+                            'smoke', 'subsurface' or 'water'
+      --environment FILE    light the scene with an equirectangular .hdr or .pfm image
+      --environment-rotation DEGREES
+                            rotation of the environment around the vertical axis
+      --environment-intensity X
+                            multiplier of the environment or sky radiance (default 1)
+      --sky                 light the scene with a physically based daylight sky and sun
+      --sun-elevation DEGREES
+                            angle of the sun above the horizon (default 45)
+      --sun-azimuth DEGREES angle of the sun from the -z axis towards +x (default 0)
+      --turbidity T         haziness of the atmosphere, from 2 (clear) to 10 (default 3)
+      --sun-radius DEGREES  angular radius of the sun disk, larger suns give softer shadows
+                            (default 0.27)
+      --volume FILE         add a heterogeneous medium to the scene from a voxel grid, a header line
+                            'nx ny nz channel...' among density, temperature (kelvins) and emission
+                            (red, green, blue) then the channels of every voxel, x varying fastest
+      --volume-bounds X0,Y0,Z0,X1,Y1,Z1
+                            corners of the box filled by the grid (default -1,0,-1,1,2,1)
+      --volume-density S    extinction coefficient of a density of 1, scattering and absorption
+                            together (default 1)
+      --volume-albedo A     fraction of the light scattered by the medium rather than absorbed, a
+                            gray level or R,G,B (default 1)
+      --volume-anisotropy G Henyey-Greenstein anisotropy of the medium, from -1 (backward) to 1
+                            (forward, default 0)
+      --volume-emission S   scale of the emission and black body radiation of the medium, emitted
+                            at every collision whatever the albedo (default 1)
+      --atmosphere          fill the scene with air scattering the light of the environment, hazing
+                            distant objects, its aerosols following the turbidity
+      --meters-per-unit M   size of a scene unit for the air (default 1)
+      --altitude M          altitude of the scene origin above the sea in meters (default 0)
+      --fog DENSITY         add a fog of this extinction coefficient at the fog height, falling
+                            exponentially above it
+      --fog-height Y        height of the fog density (default 0)
+      --fog-falloff F       rate at which the fog thins with the height, per scene unit (default 1)
+      --projection NAME     'perspective' (default), 'orthographic', 'physical' (settings below),
+                            'cv' (intrinsic matrix below), or all around the camera
+                            'equirectangular' (best at 2:1), 'fisheye' (equidistant),
+                            'fisheye-equisolid' or 'cubemap' (six faces, best at 3:2)
+      --view-height H       height of the orthographic view, by default framing the point looked
+                            at like the perspective projection
+      --fisheye-fov DEGREES field of view of the fisheye projections (default 180)
+      --aperture D          lens diameter of the perspective camera, overriding that of the scene
+      --aperture-blades N   polygonal aperture of N blades instead of a circular one
+      --aperture-rotation DEGREES
+                            rotation of the polygonal aperture
+      --aperture-mask FILE  aperture shaped by the luminance of a .hdr or .pfm image
+      --focal-length MM     focal length of the physical camera (default 50)
+      --sensor WxH          sensor size of the physical camera in millimeters (default 36x24)
+      --f-number N          aperture of the physical camera (default 16)
+      --shutter SECONDS     exposure time of the physical camera, as a number or a fraction like
+                            1/250 (default 1/125)
+      --iso S               sensitivity of the physical camera (default 100)
+      --focus-distance D    distance to the plane in focus of the physical camera, in meters
+      --lens NAME           trace the physical camera through a lens prescription, the
+                            'double-gauss' preset or a file of 'radius thickness ior aperture
+                            [abbe]' lines in millimeters, scaled to the focal length
+      --exit-pupil          only sample the exit pupil of the lens, blocking fewer rays
+      --intrinsics FX,FY,CX,CY[,SKEW]
+                            intrinsic matrix of the cv camera in pixels, with pixel centers at
+                            integer coordinates from the top left corner (default a focal length
+                            of the width, centered)
+      --extrinsics R11,R12,...,R33,T1,T2,T3
+                            place the camera with the rotation and translation taking world
+                            points to the OpenCV camera frame (x right, y down, z forward)
+      --calibration FILE    also write the exact intrinsics, distortion and extrinsics of the
+                            render to FILE, as OpenCV YAML or JSON depending on its extension
+      --distortion K1,K2,P1,P2[,K3]
+                            Brown-Conrady distortion of the perspective, physical or cv camera, with
+                            the coefficients of an OpenCV calibration
+      --chromatic-aberration R,B
+                            lateral chromatic aberration, magnification of the red and blue
+                            images relative to the green one minus one (like 0.002,-0.002)
+      --stereo LAYOUT       render both eyes 'side-by-side' or 'top-bottom' (left eye first),
+                            omni-directional stereo with the panoramic projections
+      --ipd D               distance between the eyes (default 0.064)
+      --convergence D       distance at which the eyes converge, 'inf' keeping them parallel
+                            (default the distance to the point looked at)
+      --width N             image width in pixels (default 1200)
+      --height N            image height in pixels (default two thirds of the width)
+      --spp N               samples per pixel (default 100)
+      --max-depth N         maximum number of bounces (default 50)
+      --integrator NAME     'path' tracing (default), 'ao' for ambient occlusion, or a debugging
+                            view among
+                            'shading-normal', 'geometric-normal', 'front-face', 'depth', 'uv',
+                            'path-depth' (number of bounces) and 'intersections' (heatmap of the
+                            intersection tests made by the path)
+      --ao-distance D       distance up to which geometry occludes in the 'ao' view (default 1)
+      --filter NAME         reconstruction filter spreading each sample over the pixels around it,
+                            'box' (default), 'tent', 'gaussian', 'mitchell', 'lanczos' or
+                            'blackman-harris'
+      --filter-radius R     radius of the filter in pixels (default 0.5 for the box, 1 for the
+                            tent, 1.5 for the gaussian, 3 for lanczos and 2 otherwise)
+      --seed N              seed of the sampler, use different seeds to split a frame
+      -o, --output FILE     write the image to FILE instead of the standard output, as PPM,
+                            PFM or EXR depending on its extension
+      --accumulation FILE   also write the accumulation buffer to FILE
+      --aov LIST            comma separated passes to write along the image, among albedo,
+                            normal, depth, position and object_id, or all of them with 'all'.
+                            They are layers of EXR outputs and separate PFM images otherwise
+      --denoise STRENGTH    denoise the image guided by its albedo, normal and depth, higher
+                            strengths smoothing more (1 is a good start)
+      --frames N[..M]       render the frames N to M, writing each to the output, accumulation and
+                            calibration paths with their run of '#' replaced by the zero-padded
+                            frame number. Frames whose image exists are skipped, so that an
+                            interrupted sequence resumes, and each frame has its own seed
+      --animation FILE      move the camera along keyframes, lines of 'frame lookfrom_x lookfrom_y
+                            lookfrom_z lookat_x lookat_y lookat_z vfov focus_dist'
+      --interpolation NAME  'catmull-rom' (default) or 'linear' between keyframes, or a 'bezier'
+                            curve with the keyframes as control points
+  ray-tracing merge BUFFER... [options]
+      -o, --output FILE     write the merged image to FILE instead of the standard output
+      --accumulation FILE   also write the merged accumulation buffer to FILE
+  ray-tracing undistort IMAGE [options]
+      --fov DEGREES         vertical field of view of the .hdr or .pfm IMAGE, normalizing the
+                            coefficients
+      --distortion K1,K2,P1,P2[,K3]
+      --chromatic-aberration R,B
+                            distortion to remove, as for render
+      -o, --output FILE     write the undistorted image to FILE instead of the standard output
+  ray-tracing bake [options]
+      --scene NAME          scene whose ambient occlusion is baked (default 'final')
+      --object N            index of the object whose texture is baked (default 0)
+      --texture-width N     width of the texture (default 512)
+      --texture-height N    height of the texture (default 256)
+      --vertices FILE       bake the vertices of FILE, lines of 'x y z nx ny nz', instead of a
+                            texture, writing one occlusion value per line
+      --samples N           rays per texel or vertex (default 64)
+      --ao-distance D       distance up to which geometry occludes (default 1)
+      --seed N              seed of the sampler
+      -o, --output FILE     write the texture or values to FILE instead of the standard output";
+
+use std::path::Path;
+
+use crate::{
+    animation::Interpolation,
+    aov::Aov,
+    camera::Projection,
+    cv_camera::{Extrinsics, Intrinsics},
+    distortion::Distortion,
+    filter::Filter,
+    integrator::{self, Integrator},
+    panoramic::{Panorama, DEFAULT_FISHEYE_FOV},
+    physical_camera::Photographic,
+    scene, sky,
+    stereo::{StereoLayout, DEFAULT_INTERPUPILLARY_DISTANCE},
+};
+
+pub const ASPECT_RATIO: f64 = 3.0 / 2.0;
+
+#[derive(Clone, Debug)]
+pub struct RenderOptions {
+    pub scene: String,
+    pub environment: Option<String>,
+    pub environment_rotation: f64,
+    pub environment_intensity: f64,
+    pub sky: bool,
+    pub sun_elevation: f64,
+    pub sun_azimuth: f64,
+    pub turbidity: f64,
+    pub sun_radius: f64,
+    pub volume: Option<String>,
+    pub volume_bounds: [f64; 6],
+    pub volume_density: f64,
+    pub volume_albedo: [f64; 3],
+    pub volume_anisotropy: f64,
+    pub volume_emission: f64,
+    pub atmosphere: bool,
+    pub meters_per_unit: f64,
+    pub altitude: f64,
+    pub fog: f64,
+    pub fog_height: f64,
+    pub fog_falloff: f64,
+    pub projection: Projection,
+    pub view_height: Option<f64>,
+    pub fisheye_fov: f64,
+    pub photographic: Photographic,
+    pub lens: Option<String>,
+    pub exit_pupil: bool,
+    pub aperture: Option<f64>,
+    pub aperture_blades: Option<u32>,
+    pub aperture_rotation: f64,
+    pub aperture_mask: Option<String>,
+    pub intrinsics: Option<[f64; 5]>,
+    pub extrinsics: Option<Extrinsics>,
+    pub calibration: Option<String>,
+    pub distortion: Distortion,
+    pub stereo: Option<StereoLayout>,
+    pub interpupillary_distance: f64,
+    pub convergence_distance: Option<f64>,
+    pub image_width: usize,
+    pub height: Option<usize>,
+    pub samples_per_pixel: u32,
+    pub max_depth: i32,
+    pub integrator: Integrator,
+    pub ao_distance: f64,
+    pub filter: Filter,
+    pub filter_radius: Option<f64>,
+    pub seed: Option<u64>,
+    pub output: Option<String>,
+    pub accumulation: Option<String>,
+    pub aovs: Vec<Aov>,
+    pub denoise: Option<f64>,
+    pub frames: Option<(u64, u64)>,
+    pub animation: Option<String>,
+    pub interpolation: Interpolation,
+}
+
+impl RenderOptions {
+    pub fn image_height(&self) -> usize {
+        self.height
+            .unwrap_or(((self.image_width as f64) / ASPECT_RATIO) as usize)
+    }
+
+    pub fn aspect_ratio(&self) -> f64 {
+        self.image_width as f64 / self.image_height() as f64
+    }
+
+    /// Hashes every option that changes what is rendered, leaving out the sampling ones, so that
+    /// buffers rendered with different seeds or sample counts can be merged.
+    pub fn scene_hash(&self) -> u64 {
+        let mut options = self.clone();
+        options.samples_per_pixel = 0;
+        options.seed = None;
+        options.output = None;
+        options.accumulation = None;
+        options.aovs = Vec::new();
+        options.calibration = None;
+        options.denoise = None;
+
+        fnv1a(format!("{:?}", options).as_bytes())
+    }
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            scene: "final".to_string(),
+            environment: None,
+            environment_rotation: 0.0,
+            environment_intensity: 1.0,
+            sky: false,
+            sun_elevation: 45.0,
+            sun_azimuth: 0.0,
+            turbidity: 3.0,
+            sun_radius: sky::SUN_ANGULAR_RADIUS,
+            volume: None,
+            volume_bounds: [-1.0, 0.0, -1.0, 1.0, 2.0, 1.0],
+            volume_density: 1.0,
+            volume_albedo: [1.0; 3],
+            volume_anisotropy: 0.0,
+            volume_emission: 1.0,
+            atmosphere: false,
+            meters_per_unit: 1.0,
+            altitude: 0.0,
+            fog: 0.0,
+            fog_height: 0.0,
+            fog_falloff: 1.0,
+            projection: Projection::Perspective,
+            view_height: None,
+            fisheye_fov: DEFAULT_FISHEYE_FOV,
+            photographic: Photographic::default(),
+            lens: None,
+            exit_pupil: false,
+            aperture: None,
+            aperture_blades: None,
+            aperture_rotation: 0.0,
+            aperture_mask: None,
+            intrinsics: None,
+            extrinsics: None,
+            calibration: None,
+            distortion: Distortion::default(),
+            stereo: None,
+            interpupillary_distance: DEFAULT_INTERPUPILLARY_DISTANCE,
+            convergence_distance: None,
+            image_width: 1200,
+            height: None,
+            samples_per_pixel: 100,
+            max_depth: 50,
+            integrator: Integrator::PathTracer,
+            ao_distance: integrator::DEFAULT_AO_DISTANCE,
+            filter: Filter::ALL[0],
+            filter_radius: None,
+            seed: None,
+            output: None,
+            accumulation: None,
+            aovs: Vec::new(),
+            denoise: None,
+            frames: None,
+            animation: None,
+            interpolation: Interpolation::CatmullRom,
+        }
+    }
+}
+
+pub struct MergeOptions {
+    pub inputs: Vec<String>,
+    pub output: Option<String>,
+    pub accumulation: Option<String>,
+}
+
+pub struct UndistortOptions {
+    pub input: String,
+    pub fov: f64,
+    pub distortion: Distortion,
+    pub output: Option<String>,
+}
+
+pub struct BakeOptions {
+    pub scene: String,
+    pub object: usize,
+    pub texture_width: usize,
+    pub texture_height: usize,
+    pub vertices: Option<String>,
+    pub samples: u32,
+    pub ao_distance: f64,
+    pub seed: Option<u64>,
+    pub output: Option<String>,
+}
+
+pub enum Command {
+    Render(Box<RenderOptions>),
+    Merge(MergeOptions),
+    Bake(BakeOptions),
+    Undistort(UndistortOptions),
+}
+
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut args = args.into_iter().peekable();
+
+    match args.peek().map(String::as_str) {
+        Some("merge") => {
+            args.next();
+            parse_merge(args).map(Command::Merge)
+        }
+        Some("bake") => {
+            args.next();
+            parse_bake(args).map(Command::Bake)
+        }
+        Some("undistort") => {
+            args.next();
+            parse_undistort(args).map(Command::Undistort)
+        }
+        Some("render") => {
+            args.next();
+            parse_render(args).map(|options| Command::Render(Box::new(options)))
+        }
+        _ => parse_render(args).map(|options| Command::Render(Box::new(options))),
+    }
+}
+
+fn parse_render(mut args: impl Iterator<Item = String>) -> Result<RenderOptions, String> {
+    let mut options = RenderOptions::default();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--scene" => options.scene = parse_value(&arg, args.next())?,
+            "--environment" => options.environment = Some(parse_value(&arg, args.next())?),
+            "--environment-rotation" => {
+                options.environment_rotation = parse_value(&arg, args.next())?
+            }
+            "--environment-intensity" => {
+                options.environment_intensity = parse_value(&arg, args.next())?
+            }
+            "--sky" => options.sky = true,
+            "--sun-elevation" => options.sun_elevation = parse_value(&arg, args.next())?,
+            "--sun-azimuth" => options.sun_azimuth = parse_value(&arg, args.next())?,
+            "--turbidity" => options.turbidity = parse_value(&arg, args.next())?,
+            "--sun-radius" => options.sun_radius = parse_value(&arg, args.next())?,
+            "--volume" => options.volume = Some(parse_value(&arg, args.next())?),
+            "--volume-bounds" => {
+                let values: String = parse_value(&arg, args.next())?;
+                options.volume_bounds = parse_numbers(&values)
+                    .and_then(|values| values.try_into().ok())
+                    .ok_or_else(|| format!("invalid value '{}' for '{}'", values, arg))?;
+            }
+            "--volume-density" => options.volume_density = parse_value(&arg, args.next())?,
+            "--volume-albedo" => {
+                let values: String = parse_value(&arg, args.next())?;
+                options.volume_albedo = match parse_numbers(&values).as_deref() {
+                    Some([gray]) => [*gray; 3],
+                    Some([red, green, blue]) => [*red, *green, *blue],
+                    _ => return Err(format!("invalid value '{}' for '{}'", values, arg)),
+                };
+            }
+            "--volume-anisotropy" => options.volume_anisotropy = parse_value(&arg, args.next())?,
+            "--volume-emission" => options.volume_emission = parse_value(&arg, args.next())?,
+            "--atmosphere" => options.atmosphere = true,
+            "--meters-per-unit" => options.meters_per_unit = parse_value(&arg, args.next())?,
+            "--altitude" => options.altitude = parse_value(&arg, args.next())?,
+            "--fog" => options.fog = parse_value(&arg, args.next())?,
+            "--fog-height" => options.fog_height = parse_value(&arg, args.next())?,
+            "--fog-falloff" => options.fog_falloff = parse_value(&arg, args.next())?,
+            "--projection" => {
+                let name: String = parse_value(&arg, args.next())?;
+                options.projection = Projection::parse(&name)
+                    .ok_or_else(|| format!("unknown projection '{}'", name))?;
+            }
+            "--view-height" => options.view_height = Some(parse_value(&arg, args.next())?),
+            "--fisheye-fov" => options.fisheye_fov = parse_value(&arg, args.next())?,
+            "--lens" => options.lens = Some(parse_value(&arg, args.next())?),
+            "--exit-pupil" => options.exit_pupil = true,
+            "--intrinsics" => {
+                let values: String = parse_value(&arg, args.next())?;
+                options.intrinsics = Some(
+                    parse_intrinsics(&values)
+                        .ok_or_else(|| format!("invalid value '{}' for '{}'", values, arg))?,
+                )
+            }
+            "--extrinsics" => {
+                let values: String = parse_value(&arg, args.next())?;
+                options.extrinsics = Some(Extrinsics::parse(&values).ok_or_else(|| {
+                    format!(
+                        "invalid extrinsics '{}', expected a rotation and translation",
+                        values
+                    )
+                })?)
+            }
+            "--calibration" => options.calibration = Some(parse_value(&arg, args.next())?),
+            "--distortion" => {
+                options.distortion = parse_distortion(&arg, args.next(), options.distortion)?
+            }
+            "--chromatic-aberration" => {
+                options.distortion.lateral_chromatic = parse_chromatic(&arg, args.next())?
+            }
+            "--aperture" => options.aperture = Some(parse_value(&arg, args.next())?),
+            "--aperture-blades" => options.aperture_blades = Some(parse_value(&arg, args.next())?),
+            "--aperture-rotation" => options.aperture_rotation = parse_value(&arg, args.next())?,
+            "--aperture-mask" => options.aperture_mask = Some(parse_value(&arg, args.next())?),
+            "--focal-length" => options.photographic.focal_length = parse_value(&arg, args.next())?,
+            "--sensor" => {
+                let size: String = parse_value(&arg, args.next())?;
+                let (width, height) =
+                    parse_size(&size).ok_or_else(|| format!("invalid sensor size '{}'", size))?;
+                options.photographic.sensor_width = width;
+                options.photographic.sensor_height = height;
+            }
+            "--f-number" => options.photographic.f_number = parse_value(&arg, args.next())?,
+            "--shutter" => {
+                let time: String = parse_value(&arg, args.next())?;
+                options.photographic.shutter_time = parse_fraction(&time)
+                    .ok_or_else(|| format!("invalid shutter time '{}'", time))?;
+            }
+            "--iso" => options.photographic.iso = parse_value(&arg, args.next())?,
+            "--focus-distance" => {
+                options.photographic.focus_distance = Some(parse_value(&arg, args.next())?)
+            }
+            "--stereo" => {
+                let name: String = parse_value(&arg, args.next())?;
+                options.stereo = Some(
+                    StereoLayout::parse(&name)
+                        .ok_or_else(|| format!("unknown stereo layout '{}'", name))?,
+                );
+            }
+            "--ipd" => options.interpupillary_distance = parse_value(&arg, args.next())?,
+            "--convergence" => options.convergence_distance = Some(parse_value(&arg, args.next())?),
+            "--width" => options.image_width = parse_value(&arg, args.next())?,
+            "--height" => options.height = Some(parse_value(&arg, args.next())?),
+            "--spp" => options.samples_per_pixel = parse_value(&arg, args.next())?,
+            "--max-depth" => options.max_depth = parse_value(&arg, args.next())?,
+            "--integrator" => {
+                let name: String = parse_value(&arg, args.next())?;
+                options.integrator = Integrator::parse(&name)
+                    .ok_or_else(|| format!("unknown integrator '{}'", name))?;
+            }
+            "--ao-distance" => options.ao_distance = parse_value(&arg, args.next())?,
+            "--filter" => {
+                let name: String = parse_value(&arg, args.next())?;
+                options.filter =
+                    Filter::parse(&name).ok_or_else(|| format!("unknown filter '{}'", name))?;
+            }
+            "--filter-radius" => options.filter_radius = Some(parse_value(&arg, args.next())?),
+            "--seed" => options.seed = Some(parse_value(&arg, args.next())?),
+            "-o" | "--output" => options.output = Some(parse_value(&arg, args.next())?),
+            "--accumulation" => options.accumulation = Some(parse_value(&arg, args.next())?),
+            "--aov" => options.aovs = parse_aovs(&parse_value::<String>(&arg, args.next())?)?,
+            "--denoise" => options.denoise = Some(parse_value(&arg, args.next())?),
+            "--frames" => {
+                let range: String = parse_value(&arg, args.next())?;
+                options.frames = Some(
+                    parse_frames(&range)
+                        .ok_or_else(|| format!("invalid frame range '{}'", range))?,
+                );
+            }
+            "--animation" => options.animation = Some(parse_value(&arg, args.next())?),
+            "--interpolation" => {
+                let name: String = parse_value(&arg, args.next())?;
+                options.interpolation = Interpolation::parse(&name)
+                    .ok_or_else(|| format!("unknown interpolation '{}'", name))?;
+            }
+            _ => return Err(format!("unknown argument '{}'", arg)),
+        }
+    }
+
+    if !scene::SCENES.contains(&options.scene.as_str()) {
+        return Err(format!("unknown scene '{}'", options.scene));
+    }
+    if options.sky && options.environment.is_some() {
+        return Err("the sky and an environment map cannot be used together".to_string());
+    }
+    if [options.volume_density, options.volume_emission]
+        .iter()
+        .any(|value| !value.is_finite() || *value < 0.0)
+        || options
+            .volume_albedo
+            .iter()
+            .any(|value| !(0.0..=1.0).contains(value))
+    {
+        return Err(
+            "the volume density and emission must be finite and non-negative, and its albedo \
+             within [0, 1]"
+                .to_string(),
+        );
+    }
+    if !options.volume_anisotropy.is_finite() || options.volume_anisotropy.abs() >= 1.0 {
+        return Err("the anisotropy must be within ]-1, 1[".to_string());
+    }
+    let [x0, y0, z0, x1, y1, z1] = options.volume_bounds;
+    if x0 == x1 || y0 == y1 || z0 == z1 {
+        return Err("the volume bounds must span a box".to_string());
+    }
+    if options.sun_radius <= 0.0 {
+        return Err("the sun radius must be positive".to_string());
+    }
+    if options.meters_per_unit <= 0.0 || options.fog < 0.0 || options.fog_falloff <= 0.0 {
+        return Err(
+            "the meters per unit and fog falloff must be positive, the fog density not negative"
+                .to_string(),
+        );
+    }
+    if options.image_width < 2 || options.image_height() < 2 {
+        return Err("the image must be at least 2 pixels wide and high".to_string());
+    }
+    if !options.aovs.is_empty() && options.output.is_none() {
+        return Err("writing AOVs needs an output file".to_string());
+    }
+    if options.ao_distance <= 0.0 {
+        return Err("the ambient occlusion distance must be positive".to_string());
+    }
+    if let Integrator::AmbientOcclusion { max_distance } = &mut options.integrator {
+        *max_distance = options.ao_distance;
+    }
+    if let Some(radius) = options.filter_radius {
+        if radius < 0.5 {
+            return Err("the filter radius must be at least half a pixel".to_string());
+        }
+        options.filter.set_radius(radius);
+    }
+    if options.view_height.is_some_and(|height| height <= 0.0) {
+        return Err("the view height must be positive".to_string());
+    }
+    if options.interpupillary_distance < 0.0 {
+        return Err("the interpupillary distance cannot be negative".to_string());
+    }
+    if options
+        .convergence_distance
+        .is_some_and(|distance| distance <= 0.0)
+    {
+        return Err("the convergence distance must be positive".to_string());
+    }
+    if !(options.fisheye_fov > 0.0 && options.fisheye_fov <= 360.0) {
+        return Err("the fisheye field of view must be within ]0, 360]".to_string());
+    }
+    if (options.lens.is_some() || options.exit_pupil)
+        && !matches!(options.projection, Projection::Physical(_))
+    {
+        return Err("lenses need the physical projection".to_string());
+    }
+    if !options.distortion.is_identity()
+        && !matches!(
+            options.projection,
+            Projection::Perspective | Projection::Physical(_) | Projection::Cv(_)
+        )
+    {
+        return Err("distortion needs the perspective, physical or cv projection".to_string());
+    }
+    if options.intrinsics.is_some() && !matches!(options.projection, Projection::Cv(_)) {
+        return Err("intrinsics need the cv projection".to_string());
+    }
+    if options
+        .intrinsics
+        .is_some_and(|[fx, fy, ..]| fx <= 0.0 || fy <= 0.0)
+    {
+        return Err("the focal lengths of the intrinsics must be positive".to_string());
+    }
+    if let Some(path) = &options.calibration {
+        let extension = Path::new(path).extension().and_then(|e| e.to_str());
+        if !matches!(extension, Some("yaml" | "yml" | "json")) {
+            return Err("calibrations are written as .yaml, .yml or .json".to_string());
+        }
+        let pinhole = matches!(
+            options.projection,
+            Projection::Perspective | Projection::Physical(_) | Projection::Cv(_)
+        );
+        if !pinhole || options.lens.is_some() || options.stereo.is_some() {
+            return Err(
+                "calibrations are written for the perspective, physical and cv projections, \
+                 without lens or stereo"
+                    .to_string(),
+            );
+        }
+    }
+    if options.exit_pupil && options.lens.is_none() {
+        return Err("the exit pupil is that of a lens".to_string());
+    }
+    if options.aperture.is_some_and(|aperture| aperture < 0.0) {
+        return Err("the aperture cannot be negative".to_string());
+    }
+    if options.aperture_blades.is_some_and(|blades| blades < 3) {
+        return Err("a polygonal aperture needs at least 3 blades".to_string());
+    }
+    if options.aperture_blades.is_some() && options.aperture_mask.is_some() {
+        return Err("the aperture is either polygonal or shaped by a mask".to_string());
+    }
+    if options.animation.is_some() && options.frames.is_none() {
+        return Err("an animation is rendered with --frames".to_string());
+    }
+    if options.animation.is_some() && options.extrinsics.is_some() {
+        return Err("the camera is either animated or placed by extrinsics".to_string());
+    }
+    if options.frames.is_some() {
+        let sequence_paths = [&options.output, &options.accumulation, &options.calibration];
+        if options.output.is_none()
+            || sequence_paths
+                .iter()
+                .any(|path| path.as_ref().is_some_and(|path| !path.contains('#')))
+        {
+            return Err(
+                "frames are written to paths with a run of '#' for the frame number".to_string(),
+            );
+        }
+    }
+    let photographic = &options.photographic;
+    let lengths = [
+        photographic.focal_length,
+        photographic.sensor_width,
+        photographic.sensor_height,
+        photographic.f_number,
+        photographic.shutter_time,
+        photographic.iso,
+    ];
+    if lengths.iter().any(|value| *value <= 0.0)
+        || photographic
+            .focus_distance
+            .is_some_and(|distance| distance <= 0.0)
+    {
+        return Err("the physical camera settings must be positive".to_string());
+    }
+    let (width, height) = (options.image_width, options.image_height());
+    match &mut options.projection {
+        Projection::Orthographic { view_height } => *view_height = options.view_height,
+        Projection::Physical(settings) => *settings = options.photographic,
+        Projection::Cv(intrinsics) => {
+            *intrinsics = match options.intrinsics {
+                Some([fx, fy, cx, cy, skew]) => Intrinsics {
+                    fx,
+                    fy,
+                    cx,
+                    cy,
+                    skew,
+                    width,
+                    height,
+                },
+                None => Intrinsics::centered(width, height),
+            }
+        }
+        Projection::Panoramic(
+            Panorama::EquidistantFisheye { fov } | Panorama::EquisolidFisheye { fov },
+        ) => *fov = options.fisheye_fov,
+        _ => (),
+    }
+
+    Ok(options)
+}
+
+fn parse_merge(mut args: impl Iterator<Item = String>) -> Result<MergeOptions, String> {
+    let mut options = MergeOptions {
+        inputs: Vec::new(),
+        output: None,
+        accumulation: None,
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => options.output = Some(parse_value(&arg, args.next())?),
+            "--accumulation" => options.accumulation = Some(parse_value(&arg, args.next())?),
+            _ if arg.starts_with('-') => return Err(format!("unknown argument '{}'", arg)),
+            _ => options.inputs.push(arg),
+        }
+    }
+
+    if options.inputs.is_empty() {
+        return Err("merge needs at least one accumulation buffer".to_string());
+    }
+
+    Ok(options)
+}
+
+fn parse_undistort(mut args: impl Iterator<Item = String>) -> Result<UndistortOptions, String> {
+    let mut input = None;
+    let mut fov = None;
+    let mut distortion = Distortion::default();
+    let mut output = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--fov" => fov = Some(parse_value(&arg, args.next())?),
+            "--distortion" => distortion = parse_distortion(&arg, args.next(), distortion)?,
+            "--chromatic-aberration" => {
+                distortion.lateral_chromatic = parse_chromatic(&arg, args.next())?
+            }
+            "-o" | "--output" => output = Some(parse_value(&arg, args.next())?),
+            _ if arg.starts_with('-') => return Err(format!("unknown argument '{}'", arg)),
+            _ if input.is_none() => input = Some(arg),
+            _ => return Err("undistort takes a single image".to_string()),
+        }
+    }
+
+    let input = input.ok_or("undistort needs an image")?;
+    let fov: f64 = fov.ok_or("undistort needs the field of view of the image")?;
+    if !(fov > 0.0 && fov < 180.0) {
+        return Err("the field of view must be within ]0, 180[".to_string());
+    }
+
+    Ok(UndistortOptions {
+        input,
+        fov,
+        distortion,
+        output,
+    })
+}
+
+fn parse_bake(mut args: impl Iterator<Item = String>) -> Result<BakeOptions, String> {
+    let mut options = BakeOptions {
+        scene: "final".to_string(),
+        object: 0,
+        texture_width: 512,
+        texture_height: 256,
+        vertices: None,
+        samples: 64,
+        ao_distance: integrator::DEFAULT_AO_DISTANCE,
+        seed: None,
+        output: None,
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--scene" => options.scene = parse_value(&arg, args.next())?,
+            "--object" => options.object = parse_value(&arg, args.next())?,
+            "--texture-width" => options.texture_width = parse_value(&arg, args.next())?,
+            "--texture-height" => options.texture_height = parse_value(&arg, args.next())?,
+            "--vertices" => options.vertices = Some(parse_value(&arg, args.next())?),
+            "--samples" => options.samples = parse_value(&arg, args.next())?,
+            "--ao-distance" => options.ao_distance = parse_value(&arg, args.next())?,
+            "--seed" => options.seed = Some(parse_value(&arg, args.next())?),
+            "-o" | "--output" => options.output = Some(parse_value(&arg, args.next())?),
+            _ => return Err(format!("unknown argument '{}'", arg)),
+        }
+    }
+
+    if !scene::SCENES.contains(&options.scene.as_str()) {
+        return Err(format!("unknown scene '{}'", options.scene));
+    }
+    if options.texture_width == 0 || options.texture_height == 0 || options.samples == 0 {
+        return Err("the texture size and the number of samples must be positive".to_string());
+    }
+    if options.ao_distance <= 0.0 {
+        return Err("the ambient occlusion distance must be positive".to_string());
+    }
+
+    Ok(options)
+}
+
+fn parse_aovs(list: &str) -> Result<Vec<Aov>, String> {
+    if list == "all" {
+        return Ok(Aov::ALL.to_vec());
+    }
+
+    list.split(',')
+        .map(|name| Aov::parse(name).ok_or_else(|| format!("unknown AOV '{}'", name)))
+        .collect()
+}
+
+/// Parses distortion coefficients, keeping the chromatic aberration of `distortion`.
+fn parse_distortion(
+    name: &str,
+    value: Option<String>,
+    distortion: Distortion,
+) -> Result<Distortion, String> {
+    let value: String = parse_value(name, value)?;
+    let coefficients = Distortion::parse(&value)
+        .ok_or_else(|| format!("invalid distortion '{}', expected k1,k2,p1,p2[,k3]", value))?;
+    Ok(Distortion {
+        lateral_chromatic: distortion.lateral_chromatic,
+        ..coefficients
+    })
+}
+
+/// Parses the red and blue magnifications of the chromatic aberration, like `0.002,-0.002`.
+fn parse_chromatic(name: &str, value: Option<String>) -> Result<(f64, f64), String> {
+    let value: String = parse_value(name, value)?;
+    value
+        .split_once(',')
+        .and_then(|(red, blue)| Some((red.parse().ok()?, blue.parse().ok()?)))
+        .filter(|(red, blue): &(f64, f64)| *red > -1.0 && *blue > -1.0)
+        .ok_or_else(|| format!("invalid value '{}' for '{}'", value, name))
+}
+
+/// Parses comma separated numbers.
+fn parse_numbers(values: &str) -> Option<Vec<f64>> {
+    values.split(',').map(|value| value.parse().ok()).collect()
+}
+
+/// Parses `fx,fy,cx,cy[,skew]`.
+fn parse_intrinsics(values: &str) -> Option<[f64; 5]> {
+    let values: Vec<f64> = values
+        .split(',')
+        .map(str::parse)
+        .collect::<Result<_, _>>()
+        .ok()?;
+    match values.as_slice() {
+        [fx, fy, cx, cy] => Some([*fx, *fy, *cx, *cy, 0.0]),
+        [fx, fy, cx, cy, skew] => Some([*fx, *fy, *cx, *cy, *skew]),
+        _ => None,
+    }
+}
+
+/// Parses inclusive frame ranges like `1..24`, or a single frame.
+fn parse_frames(range: &str) -> Option<(u64, u64)> {
+    let (first, last) = match range.split_once("..") {
+        Some((first, last)) => (first.parse().ok()?, last.parse().ok()?),
+        None => {
+            let frame = range.parse().ok()?;
+            (frame, frame)
+        }
+    };
+    (first <= last).then_some((first, last))
+}
+
+/// Parses sizes like `36x24`.
+fn parse_size(size: &str) -> Option<(f64, f64)> {
+    let (width, height) = size.split_once('x')?;
+    Some((width.parse().ok()?, height.parse().ok()?))
+}
+
+/// Parses numbers, or fractions like `1/125`.
+fn parse_fraction(value: &str) -> Option<f64> {
+    match value.split_once('/') {
+        Some((numerator, denominator)) => {
+            Some(numerator.parse::<f64>().ok()? / denominator.parse::<f64>().ok()?)
+        }
+        None => value.parse().ok(),
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(name: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("missing value for '{}'", name))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value '{}' for '{}'", value, name))
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn parse_render_options() {
+        match parse(args(
+            "--width 300 --spp 8 --seed 3 --integrator uv -o out.ppm",
+        )) {
+            Ok(Command::Render(options)) => {
+                assert_eq!(Integrator::Uv, options.integrator);
+                assert_eq!(300, options.image_width);
+                assert_eq!(200, options.image_height());
+                assert_eq!(8, options.samples_per_pixel);
+                assert_eq!(Some(3), options.seed);
+                assert_eq!(Some("out.ppm".to_string()), options.output);
+            }
+            _ => panic!("Should have parsed render options"),
+        }
+    }
+
+    #[test]
+    fn parse_merge_options() {
+        match parse(args("merge a.acc b.acc -o merged.ppm")) {
+            Ok(Command::Merge(options)) => {
+                assert_eq!(vec!["a.acc", "b.acc"], options.inputs);
+                assert_eq!(Some("merged.ppm".to_string()), options.output);
+            }
+            _ => panic!("Should have parsed merge options"),
+        }
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert!(parse(args("--spp many")).is_err());
+        assert!(parse(args("--unknown")).is_err());
+        assert!(parse(args("merge")).is_err());
+        assert!(parse(args("--aov normal")).is_err());
+        assert!(parse(args("--aov normal,curvature -o image.exr")).is_err());
+        assert!(parse(args("--denoise")).is_err());
+        assert!(parse(args("--scene empty")).is_err());
+        assert!(parse(args("--integrator whitted")).is_err());
+        assert!(parse(args("--sky --environment sky.hdr")).is_err());
+    }
+
+    #[test]
+    fn parse_ambient_occlusion() {
+        match parse(args("--ao-distance 2.5 --integrator ao")) {
+            Ok(Command::Render(options)) => assert_eq!(
+                Integrator::AmbientOcclusion { max_distance: 2.5 },
+                options.integrator
+            ),
+            _ => panic!("Should have parsed render options"),
+        }
+        match parse(args("bake --object 3 --samples 16 --vertices mesh.txt")) {
+            Ok(Command::Bake(options)) => {
+                assert_eq!(3, options.object);
+                assert_eq!(16, options.samples);
+                assert_eq!(Some("mesh.txt".to_string()), options.vertices);
+            }
+            _ => panic!("Should have parsed bake options"),
+        }
+        assert!(parse(args("--integrator ao --ao-distance 0")).is_err());
+        assert!(parse(args("bake --samples 0")).is_err());
+    }
+
+    #[test]
+    fn parse_volume() {
+        match parse(args(
+            "--volume cloud.txt --volume-bounds 0,0,0,4,2,-3 --volume-density 5 \
+             --volume-albedo 0.9,0.8,0.7 --volume-anisotropy 0.6 --volume-emission 3",
+        )) {
+            Ok(Command::Render(options)) => {
+                assert_eq!(Some("cloud.txt".to_string()), options.volume);
+                assert_eq!([0.0, 0.0, 0.0, 4.0, 2.0, -3.0], options.volume_bounds);
+                assert_eq!(5.0, options.volume_density);
+                assert_eq!([0.9, 0.8, 0.7], options.volume_albedo);
+                assert_eq!(0.6, options.volume_anisotropy);
+                assert_eq!(3.0, options.volume_emission);
+            }
+            _ => panic!("Should have parsed render options"),
+        }
+        match parse(args("--volume-albedo 0.5")) {
+            Ok(Command::Render(options)) => assert_eq!([0.5; 3], options.volume_albedo),
+            _ => panic!("Should have parsed render options"),
+        }
+        assert!(parse(args("--volume-bounds 0,0,0,1,1")).is_err());
+        assert!(parse(args("--volume-bounds 0,0,0,1,0,1")).is_err());
+        assert!(parse(args("--volume-albedo 0.5,0.5")).is_err());
+        assert!(parse(args("--volume-albedo 2")).is_err());
+        assert!(parse(args("--volume-anisotropy 1")).is_err());
+        assert!(parse(args("--volume-anisotropy nan")).is_err());
+        assert!(parse(args("--volume-density nan")).is_err());
+        assert!(parse(args("--volume-density inf")).is_err());
+        assert!(parse(args("--volume-emission nan")).is_err());
+        assert!(parse(args("--anisotropy 0.5")).is_err());
+    }
+
+    #[test]
+    fn parse_atmosphere() {
+        match parse(args(
+            "--atmosphere --meters-per-unit 50 --altitude 1200 --fog 0.2 --fog-height -1 \
+             --fog-falloff 0.5",
+        )) {
+            Ok(Command::Render(options)) => {
+                assert!(options.atmosphere);
+                assert_eq!(50.0, options.meters_per_unit);
+                assert_eq!(1200.0, options.altitude);
+                assert_eq!(0.2, options.fog);
+                assert_eq!(-1.0, options.fog_height);
+                assert_eq!(0.5, options.fog_falloff);
+            }
+            _ => panic!("Should have parsed render options"),
+        }
+        assert!(parse(args("--meters-per-unit 0")).is_err());
+        assert!(parse(args("--fog -1")).is_err());
+        assert!(parse(args("--fog 1 --fog-falloff 0")).is_err());
+    }
+
+    #[test]
+    fn parse_projection() {
+        match parse(args("--view-height 4 --projection orthographic")) {
+            Ok(Command::Render(options)) => assert_eq!(
+                Projection::Orthographic {
+                    view_height: Some(4.0)
+                },
+                options.projection
+            ),
+            _ => panic!("Should have parsed render options"),
+        }
+        match parse(args(
+            "--projection fisheye-equisolid --fisheye-fov 220 --height 500",
+        )) {
+            Ok(Command::Render(options)) => {
+                assert_eq!(
+                    Projection::Panoramic(Panorama::EquisolidFisheye { fov: 220.0 }),
+                    options.projection
+                );
+                assert_eq!(500, options.image_height());
+                assert_eq!(1200.0 / 500.0, options.aspect_ratio());
+            }
+            _ => panic!("Should have parsed render options"),
+        }
+        assert!(parse(args("--projection isometric")).is_err());
+        assert!(parse(args("--projection fisheye --fisheye-fov 400")).is_err());
+        assert!(parse(args("--projection orthographic --view-height -1")).is_err());
+    }
+
+    #[test]
+    fn parse_physical_camera() {
+        match parse(args(
+            "--projection physical --focal-length 85 --sensor 23.5x15.6 --f-number 1.8 \
+             --shutter 1/250 --iso 400 --focus-distance 2.5",
+        )) {
+            Ok(Command::Render(options)) => assert_eq!(
+                Projection::Physical(Photographic {
+                    focal_length: 85.0,
+                    sensor_width: 23.5,
+                    sensor_height: 15.6,
+                    f_number: 1.8,
+                    shutter_time: 0.004,
+                    iso: 400.0,
+                    focus_distance: Some(2.5),
+                }),
+                options.projection
+            ),
+            _ => panic!("Should have parsed render options"),
+        }
+        assert!(parse(args("--shutter 1/fast")).is_err());
+        assert!(parse(args("--sensor 36")).is_err());
+        assert!(parse(args("--f-number 0")).is_err());
+    }
+
+    #[test]
+    fn parse_lens() {
+        match parse(args(
+            "--projection physical --lens double-gauss --exit-pupil",
+        )) {
+            Ok(Command::Render(options)) => {
+                assert_eq!(Some("double-gauss".to_string()), options.lens);
+                assert!(options.exit_pupil);
+            }
+            _ => panic!("Should have parsed render options"),
+        }
+        assert!(parse(args("--lens double-gauss")).is_err());
+        assert!(parse(args("--projection physical --exit-pupil")).is_err());
+    }
+
+    #[test]
+    fn parse_aperture() {
+        match parse(args(
+            "--aperture 0.5 --aperture-blades 6 --aperture-rotation 15",
+        )) {
+            Ok(Command::Render(options)) => {
+                assert_eq!(Some(0.5), options.aperture);
+                assert_eq!(Some(6), options.aperture_blades);
+                assert_eq!(15.0, options.aperture_rotation);
+            }
+            _ => panic!("Should have parsed render options"),
+        }
+        assert!(parse(args("--aperture-blades 2")).is_err());
+        assert!(parse(args("--aperture-blades 5 --aperture-mask heart.pfm")).is_err());
+    }
+
+    #[test]
+    fn parse_distortion_options() {
+        match parse(args(
+            "--chromatic-aberration 0.002,-0.001 --distortion -0.2,0.05,0,0.001",
+        )) {
+            Ok(Command::Render(options)) => assert_eq!(
+                Distortion {
+                    k1: -0.2,
+                    k2: 0.05,
+                    p2: 0.001,
+                    lateral_chromatic: (0.002, -0.001),
+                    ..Default::default()
+                },
+                options.distortion
+            ),
+            _ => panic!("Should have parsed render options"),
+        }
+        assert!(parse(args("--distortion 0.1")).is_err());
+        assert!(parse(args("--chromatic-aberration 0.01")).is_err());
+        assert!(parse(args("--projection fisheye --distortion 0.1,0,0,0")).is_err());
+
+        match parse(args(
+            "undistort plate.pfm --fov 40 --distortion -0.1,0,0,0 -o out.pfm",
+        )) {
+            Ok(Command::Undistort(options)) => {
+                assert_eq!("plate.pfm", options.input);
+                assert_eq!(40.0, options.fov);
+                assert_eq!(-0.1, options.distortion.k1);
+                assert_eq!(Some("out.pfm".to_string()), options.output);
+            }
+            _ => panic!("Should have parsed undistort options"),
+        }
+        assert!(parse(args("undistort plate.pfm")).is_err());
+        assert!(parse(args("undistort --fov 40")).is_err());
+    }
+
+    #[test]
+    fn parse_cv_camera() {
+        match parse(args(
+            "--projection cv --intrinsics 800,780,320,240,0.5 --width 640 --height 480 \
+             --extrinsics 1,0,0,0,1,0,0,0,1,0,0,5 --calibration camera.yaml",
+        )) {
+            Ok(Command::Render(options)) => {
+                assert_eq!(
+                    Projection::Cv(Intrinsics {
+                        fx: 800.0,
+                        fy: 780.0,
+                        cx: 320.0,
+                        cy: 240.0,
+                        skew: 0.5,
+                        width: 640,
+                        height: 480,
+                    }),
+                    options.projection
+                );
+                assert_eq!([0.0, 0.0, 5.0], options.extrinsics.unwrap().translation);
+                assert_eq!(Some("camera.yaml".to_string()), options.calibration);
+            }
+            _ => panic!("Should have parsed render options"),
+        }
+        match parse(args("--projection cv --width 300 --height 200")) {
+            Ok(Command::Render(options)) => assert_eq!(
+                Projection::Cv(Intrinsics::centered(300, 200)),
+                options.projection
+            ),
+            _ => panic!("Should have parsed render options"),
+        }
+        assert!(parse(args("--intrinsics 800,800,320,240")).is_err());
+        assert!(parse(args("--projection cv --intrinsics 800,800")).is_err());
+        assert!(parse(args("--extrinsics 1,1,0,0,1,0,0,0,1,0,0,5")).is_err());
+        assert!(parse(args("--calibration camera.txt")).is_err());
+        assert!(parse(args("--projection fisheye --calibration camera.json")).is_err());
+    }
+
+    #[test]
+    fn parse_filter() {
+        match parse(args("--filter-radius 2.5 --filter lanczos")) {
+            Ok(Command::Render(options)) => {
+                assert_eq!(Filter::Lanczos { radius: 2.5 }, options.filter)
+            }
+            _ => panic!("Should have parsed render options"),
+        }
+        match parse(args("--filter mitchell")) {
+            Ok(Command::Render(options)) => {
+                assert_eq!(Filter::Mitchell { radius: 2.0 }, options.filter)
+            }
+            _ => panic!("Should have parsed render options"),
+        }
+        assert!(parse(args("--filter sinc")).is_err());
+        assert!(parse(args("--filter tent --filter-radius 0.2")).is_err());
+    }
+
+    #[test]
+    fn parse_sequence() {
+        match parse(args(
+            "--frames 5..12 --animation path.txt --interpolation bezier -o frames/####.exr",
+        )) {
+            Ok(Command::Render(options)) => {
+                assert_eq!(Some((5, 12)), options.frames);
+                assert_eq!(Some("path.txt".to_string()), options.animation);
+                assert_eq!(Interpolation::Bezier, options.interpolation);
+            }
+            _ => panic!("Should have parsed render options"),
+        }
+        match parse(args("--frames 3 -o frame#.ppm")) {
+            Ok(Command::Render(options)) => assert_eq!(Some((3, 3)), options.frames),
+            _ => panic!("Should have parsed render options"),
+        }
+        assert!(parse(args("--frames 12..5 -o frame#.ppm")).is_err());
+        assert!(parse(args("--frames 1..10 -o frame.ppm")).is_err());
+        assert!(parse(args("--frames 1..10 -o #.ppm --accumulation frame.acc")).is_err());
+        assert!(parse(args("--animation path.txt -o #.ppm")).is_err());
+        assert!(parse(args("--frames 1..2 -o #.ppm --interpolation cubic")).is_err());
+    }
+
+    #[test]
+    fn parse_stereo() {
+        match parse(args("--stereo top-bottom --ipd 0.07 --convergence inf")) {
+            Ok(Command::Render(options)) => {
+                assert_eq!(Some(StereoLayout::TopBottom), options.stereo);
+                assert_eq!(0.07, options.interpupillary_distance);
+                assert_eq!(Some(f64::INFINITY), options.convergence_distance);
+            }
+            _ => panic!("Should have parsed render options"),
+        }
+        assert!(parse(args("--stereo anaglyph")).is_err());
+        assert!(parse(args("--stereo side-by-side --convergence 0")).is_err());
+    }
+
+    #[test]
+    fn parse_aov_list() {
+        match parse(args("--aov depth,normal -o image.exr")) {
+            Ok(Command::Render(options)) => assert_eq!(vec![Aov::Depth, Aov::Normal], options.aovs),
+            _ => panic!("Should have parsed render options"),
+        }
+        match parse(args("--aov all -o image.exr")) {
+            Ok(Command::Render(options)) => assert_eq!(Aov::ALL.to_vec(), options.aovs),
+            _ => panic!("Should have parsed render options"),
+        }
+    }
+
+    #[test]
+    fn scene_hash_ignores_sampling() {
+        let first = RenderOptions::default();
+        let second = RenderOptions {
+            seed: Some(12),
+            samples_per_pixel: 3,
+            output: Some("other.ppm".to_string()),
+            calibration: Some("camera.yaml".to_string()),
+            ..Default::default()
+        };
+        let third = RenderOptions {
+            image_width: 600,
+            ..Default::default()
+        };
+
+        assert_eq!(first.scene_hash(), second.scene_hash());
+        assert_ne!(first.scene_hash(), third.scene_hash());
+    }
+}
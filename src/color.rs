@@ -1,16 +1,66 @@
-use crate::vec3::Vec3;
+use std::io::{self, Write};
+
+use crate::{random, vec3::Vec3};
 
 pub type Color = Vec3;
 
 impl Color {
-    pub fn write_color(&self, samples_per_pixel: i32) {
-        let scale = 1.0 / samples_per_pixel as f64;
-        println!(
+    /// Picks one of the red, green and blue channels, along with the weight of a sample carrying
+    /// only that channel.
+    pub fn random_channel() -> (usize, Color) {
+        let channel = ((random::random_double() * 3.0) as usize).min(2);
+        let weight = match channel {
+            0 => Color::new(3.0, 0.0, 0.0),
+            1 => Color::new(0.0, 3.0, 0.0),
+            _ => Color::new(0.0, 0.0, 3.0),
+        };
+        (channel, weight)
+    }
+
+    /// Linear sRGB color of a black body at `temperature` kelvins, its spectrum being normalized to
+    /// a peak of 1 so that hotter bodies get bluer rather than brighter. Cool bodies peaking in the
+    /// infrared are dim.
+    pub fn blackbody(temperature: f64) -> Color {
+        if temperature <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        // Planck's law in nanometers, up to constants cancelled by the normalization
+        let planck = |wavelength: f64| {
+            let meters = wavelength * 1e-9;
+            1.0 / (meters.powi(5) * ((0.014_387_77 / (meters * temperature)).exp() - 1.0))
+        };
+        let peak = planck(2.897_772e6 / temperature);
+
+        let (mut x, mut y, mut z, mut normalization) = (0.0, 0.0, 0.0, 0.0);
+        for wavelength in (380..=780).step_by(5).map(|wavelength| wavelength as f64) {
+            let radiance = planck(wavelength) / peak;
+            let (x_bar, y_bar, z_bar) = cie_matching(wavelength);
+            x += radiance * x_bar;
+            y += radiance * y_bar;
+            z += radiance * z_bar;
+            normalization += y_bar;
+        }
+        let (x, y, z) = (x / normalization, y / normalization, z / normalization);
+
+        // Out of gamut components are clipped
+        Color::new(
+            (3.2406 * x - 1.5372 * y - 0.4986 * z).max(0.0),
+            (-0.9689 * x + 1.8758 * y + 0.0415 * z).max(0.0),
+            (0.0557 * x - 0.2040 * y + 1.0570 * z).max(0.0),
+        )
+    }
+
+    /// Writes the gamma-corrected color, `weight` being the total weight of the summed samples.
+    pub fn write_color(&self, out: &mut impl Write, weight: f64) -> io::Result<()> {
+        let scale = 1.0 / weight;
+        writeln!(
+            out,
             "{} {} {}",
             (256.0 * Self::clamp((self.x * scale).sqrt(), 0.0, 0.999)) as u8,
             (256.0 * Self::clamp((self.y * scale).sqrt(), 0.0, 0.999)) as u8,
             (256.0 * Self::clamp((self.z * scale).sqrt(), 0.0, 0.999)) as u8
-        );
+        )
     }
 
     fn clamp(x: f64, min: f64, max: f64) -> f64 {
@@ -20,6 +70,45 @@ impl Color {
         if x > max {
             return max;
         };
-        return x;
+        x
+    }
+}
+
+/// CIE 1931 color matching functions at a wavelength in nanometers, from the multi-lobe fit of
+/// Wyman, Sloan and Shirley.
+fn cie_matching(wavelength: f64) -> (f64, f64, f64) {
+    let lobe = |mean: f64, below: f64, above: f64| {
+        let sigma = if wavelength < mean { below } else { above };
+        let t = (wavelength - mean) / sigma;
+        (-0.5 * t * t).exp()
+    };
+
+    (
+        1.056 * lobe(599.8, 37.9, 31.0) + 0.362 * lobe(442.0, 16.0, 26.7)
+            - 0.065 * lobe(501.1, 20.4, 26.2),
+        0.821 * lobe(568.8, 46.9, 40.5) + 0.286 * lobe(530.9, 16.3, 31.1),
+        1.217 * lobe(437.0, 11.8, 36.0) + 0.681 * lobe(459.0, 26.0, 13.8),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blackbody_colors() {
+        let candle = Color::blackbody(1900.0);
+        let daylight = Color::blackbody(6500.0);
+        let sky = Color::blackbody(12000.0);
+
+        assert!(candle.x > candle.y && candle.y > candle.z);
+        assert!(sky.z > sky.x);
+        // Close to white around the temperature of the D65 white point
+        assert!((daylight.x / daylight.z - 1.0).abs() < 0.15);
+        assert!((daylight.y / daylight.z - 1.0).abs() < 0.15);
+
+        // Bodies peaking in the infrared barely glow
+        assert!(Color::blackbody(800.0).x < 0.01);
+        assert_eq!(0.0, Color::blackbody(0.0).length());
     }
 }
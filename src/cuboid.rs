@@ -0,0 +1,174 @@
+use crate::{
+    hittable::{HitRecord, Hittable},
+    material::Material,
+    ray::Ray,
+    vec3::{Point3, Vec3},
+};
+
+/// Box aligned with the axes, between its `min` and `max` corners.
+pub struct Cuboid<T: Material> {
+    min: Point3,
+    max: Point3,
+    material: T,
+}
+
+impl<T: Material> Cuboid<T> {
+    pub fn new(a: Point3, b: Point3, material: T) -> Cuboid<T> {
+        Cuboid {
+            min: Point3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)),
+            max: Point3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)),
+            material,
+        }
+    }
+}
+
+fn component(v: Vec3, axis: usize) -> f64 {
+    match axis {
+        0 => v.x,
+        1 => v.y,
+        _ => v.z,
+    }
+}
+
+fn axis_vector(axis: usize, value: f64) -> Vec3 {
+    match axis {
+        0 => Vec3::new(value, 0.0, 0.0),
+        1 => Vec3::new(0.0, value, 0.0),
+        _ => Vec3::new(0.0, 0.0, value),
+    }
+}
+
+/// Parameters along the ray of its entry into and exit from the box between `min` and `max`, with
+/// the outward normals of the faces crossed, by the slabs method.
+pub fn slabs(min: Point3, max: Point3, ray: &Ray) -> Option<((f64, Vec3), (f64, Vec3))> {
+    let (mut near, mut far) = (f64::NEG_INFINITY, f64::INFINITY);
+    let (mut near_normal, mut far_normal) = (Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0));
+
+    for axis in 0..3 {
+        let origin = component(ray.origin, axis);
+        let direction = component(ray.direction, axis);
+        let (min, max) = (component(min, axis), component(max, axis));
+
+        if direction == 0.0 {
+            if origin < min || origin > max {
+                return None;
+            }
+            continue;
+        }
+
+        let (mut t0, mut t1) = ((min - origin) / direction, (max - origin) / direction);
+        let mut sign = -1.0;
+        if t0 > t1 {
+            (t0, t1) = (t1, t0);
+            sign = 1.0;
+        }
+        if t0 > near {
+            near = t0;
+            near_normal = axis_vector(axis, sign);
+        }
+        if t1 < far {
+            far = t1;
+            far_normal = axis_vector(axis, -sign);
+        }
+        if near > far {
+            return None;
+        }
+    }
+
+    Some(((near, near_normal), (far, far_normal)))
+}
+
+impl<T: Material> Hittable for Cuboid<T> {
+    fn hit(&self, ray: &Ray, t_max: f64, t_min: f64) -> Option<HitRecord<'_>> {
+        let ((near, near_normal), (far, far_normal)) = slabs(self.min, self.max, ray)?;
+
+        let (t, outward_normal) = if near >= t_min && near <= t_max {
+            (near, near_normal)
+        } else if far >= t_min && far <= t_max {
+            (far, far_normal)
+        } else {
+            return None;
+        };
+
+        let p = ray.at(t);
+        let front_face = ray.direction.dot(outward_normal) < 0.0;
+        let mut rec = HitRecord::new(p, outward_normal, t, front_face, &self.material);
+
+        // Coordinates across the face, along the two other axes
+        let normal_axis = (0..3)
+            .find(|axis| component(outward_normal, *axis) != 0.0)
+            .unwrap();
+        let (u_axis, v_axis) = ((normal_axis + 1) % 3, (normal_axis + 2) % 3);
+        let relative = |axis| {
+            (component(p, axis) - component(self.min, axis))
+                / (component(self.max, axis) - component(self.min, axis))
+        };
+        (rec.u, rec.v) = (relative(u_axis), relative(v_axis));
+
+        Some(rec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{color::Color, material::Lambertian};
+    use approx::*;
+
+    macro_rules! assert_vec3_equal {
+        ($expected:expr, $actual:expr) => {
+            let tolerance = 0.0001;
+            assert_relative_eq!($expected.x, $actual.x, epsilon = tolerance);
+            assert_relative_eq!($expected.y, $actual.y, epsilon = tolerance);
+            assert_relative_eq!($expected.z, $actual.z, epsilon = tolerance);
+        };
+    }
+
+    fn unit_cube() -> Cuboid<Lambertian> {
+        Cuboid::new(
+            Point3::new(1.0, 1.0, 1.0),
+            Point3::new(-1.0, -1.0, -1.0),
+            Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+        )
+    }
+
+    #[test]
+    fn hit_faces() {
+        let cube = unit_cube();
+        let ray = Ray::new(Point3::new(0.5, 0.2, 5.0), Vec3::new(0.0, 0.0, -2.0));
+
+        let front = cube.hit(&ray, f64::INFINITY, 0.0).unwrap();
+        assert_relative_eq!(2.0, front.t);
+        assert_vec3_equal!(Vec3::new(0.0, 0.0, 1.0), front.normal);
+        assert!(front.front_face);
+        assert_relative_eq!(0.75, front.u);
+        assert_relative_eq!(0.6, front.v);
+
+        // From inside, the far face is hit with the normal facing the ray
+        let back = cube.hit(&ray, f64::INFINITY, 2.5).unwrap();
+        assert_relative_eq!(3.0, back.t);
+        assert_vec3_equal!(Vec3::new(0.0, 0.0, -1.0), back.geometric_normal);
+        assert_vec3_equal!(Vec3::new(0.0, 0.0, 1.0), back.normal);
+        assert!(!back.front_face);
+
+        let diagonal = Ray::new(Point3::new(3.0, 0.0, 0.0), Vec3::new(-1.0, 0.5, 0.0));
+        let side = cube.hit(&diagonal, f64::INFINITY, 0.0).unwrap();
+        assert_vec3_equal!(Point3::new(1.0, 1.0, 0.0), side.p);
+    }
+
+    #[test]
+    fn miss() {
+        let cube = unit_cube();
+        let parallel = Ray::new(Point3::new(0.0, 2.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(cube.hit(&parallel, f64::INFINITY, 0.0).is_none());
+
+        let away = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(cube.hit(&away, f64::INFINITY, 0.0).is_none());
+
+        let passing = Ray::new(Point3::new(3.0, 0.0, 0.0), Vec3::new(-1.0, 2.0, 0.0));
+        assert!(cube.hit(&passing, f64::INFINITY, 0.0).is_none());
+
+        let short = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(cube.hit(&short, 3.0, 0.0).is_none());
+    }
+}
@@ -0,0 +1,471 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use crate::{
+    camera::{Camera, Projection, View},
+    color::Color,
+    distortion::Distortion,
+    ray::Ray,
+    vec3::{Point3, Vec3},
+};
+
+/// Intrinsic matrix K of a pinhole camera in the conventions of OpenCV: pixel centers at integer
+/// coordinates from the top left corner, x going right and y down. The render loop covers pixel
+/// j with s in [j, j + 1] / (width - 1), which `CvCamera` follows exactly.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Intrinsics {
+    pub fx: f64,
+    pub fy: f64,
+    pub cx: f64,
+    pub cy: f64,
+    pub skew: f64,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Intrinsics {
+    pub const DEFAULT: Intrinsics = Intrinsics::centered(1200, 800);
+
+    /// Focal length of the image width, centered on the image.
+    pub const fn centered(width: usize, height: usize) -> Intrinsics {
+        Intrinsics {
+            fx: width as f64,
+            fy: width as f64,
+            cx: (width as f64 - 2.0) / 2.0,
+            cy: height as f64 / 2.0,
+            skew: 0.0,
+            width,
+            height,
+        }
+    }
+
+    /// Pixel of the point (s, t) of the image.
+    fn pixel(&self, s: f64, t: f64) -> (f64, f64) {
+        (
+            s * (self.width - 1) as f64 - 0.5,
+            self.height as f64 - 0.5 - t * (self.height - 1) as f64,
+        )
+    }
+
+    /// Normalized image coordinates x / z and y / z of a pixel.
+    fn normalize(&self, u: f64, v: f64) -> (f64, f64) {
+        let y = (v - self.cy) / self.fy;
+        ((u - self.cx - self.skew * y) / self.fx, y)
+    }
+}
+
+/// Rotation R and translation t taking world points to the camera frame of OpenCV, x right, y
+/// down and z forward.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Extrinsics {
+    pub rotation: [[f64; 3]; 3],
+    pub translation: [f64; 3],
+}
+
+impl Extrinsics {
+    /// Parses the rows of R then t, `r11,r12,...,r33,t1,t2,t3`. None unless R is a rotation.
+    pub fn parse(values: &str) -> Option<Extrinsics> {
+        let values: Vec<f64> = values
+            .split(',')
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .ok()?;
+        if values.len() != 12 {
+            return None;
+        }
+
+        let extrinsics = Extrinsics {
+            rotation: [
+                [values[0], values[1], values[2]],
+                [values[3], values[4], values[5]],
+                [values[6], values[7], values[8]],
+            ],
+            translation: [values[9], values[10], values[11]],
+        };
+        let [x, y, z] = extrinsics
+            .rotation
+            .map(|row| Vec3::new(row[0], row[1], row[2]));
+        let orthonormal = [x.length(), y.length(), z.length()]
+            .iter()
+            .all(|length| (length - 1.0).abs() < 1e-6)
+            && x.dot(y).abs() < 1e-6
+            && x.dot(z).abs() < 1e-6
+            && y.dot(z).abs() < 1e-6;
+        (orthonormal && x.cross_product(y).dot(z) > 0.0).then_some(extrinsics)
+    }
+
+    pub fn from_view(view: &View) -> Extrinsics {
+        let w = (view.lookfrom - view.lookat).unit_vector();
+        let u = (view.vup.cross_product(w)).unit_vector();
+        let v = w.cross_product(u);
+
+        let rows = [u, -v, -w];
+        Extrinsics {
+            rotation: rows.map(|row| [row.x, row.y, row.z]),
+            translation: rows.map(|row| -row.dot(view.lookfrom)),
+        }
+    }
+
+    /// Moves the view to the camera position and orientation.
+    pub fn place(&self, view: &mut View) {
+        let [right, down, forward] = self.rotation.map(|row| Vec3::new(row[0], row[1], row[2]));
+        let [t1, t2, t3] = self.translation;
+        let lookfrom = -(t1 * right + t2 * down + t3 * forward);
+
+        let distance = (view.lookat - view.lookfrom).length();
+        view.lookfrom = lookfrom;
+        view.lookat = lookfrom + distance * forward;
+        view.vup = -down;
+    }
+}
+
+/// Pinhole camera of an intrinsic matrix, placed by its view.
+pub struct CvCamera {
+    origin: Point3,
+    right: Vec3,
+    down: Vec3,
+    forward: Vec3,
+    intrinsics: Intrinsics,
+    distortion: Distortion,
+}
+
+impl CvCamera {
+    pub fn new(lookfrom: Point3, lookat: Point3, vup: Vec3, intrinsics: Intrinsics) -> CvCamera {
+        let w = (lookfrom - lookat).unit_vector();
+        let u = (vup.cross_product(w)).unit_vector();
+        let v = w.cross_product(u);
+
+        CvCamera {
+            origin: lookfrom,
+            right: u,
+            down: -v,
+            forward: -w,
+            intrinsics,
+            distortion: Distortion::default(),
+        }
+    }
+
+    pub fn with_distortion(self, distortion: Distortion) -> CvCamera {
+        CvCamera { distortion, ..self }
+    }
+}
+
+impl Camera for CvCamera {
+    fn get_ray(&self, s: f64, t: f64) -> Option<Ray> {
+        self.get_weighted_ray(s, t).map(|(ray, _)| ray)
+    }
+
+    fn get_weighted_ray(&self, s: f64, t: f64) -> Option<(Ray, Color)> {
+        let (u, v) = self.intrinsics.pixel(s, t);
+        let (x, y) = self.intrinsics.normalize(u, v);
+        let (channel, weight) = self.distortion.sample_channel();
+        let (x, y) = self.distortion.ideal_point(x, y, channel);
+
+        let direction = x * self.right + y * self.down + self.forward;
+        Some((Ray::new(self.origin, direction), weight))
+    }
+}
+
+/// Exact calibration of a pinhole render, as OpenCV writes it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Calibration {
+    pub intrinsics: Intrinsics,
+    pub distortion: Distortion,
+    pub extrinsics: Extrinsics,
+}
+
+impl Calibration {
+    /// Calibration of the image of `width` by `height` pixels rendered from `view`, None when the
+    /// projection is not a pinhole one.
+    pub fn new(
+        view: &View,
+        projection: Projection,
+        width: usize,
+        height: usize,
+    ) -> Option<Calibration> {
+        let intrinsics = match projection {
+            Projection::Cv(intrinsics) => intrinsics,
+            _ if view.lens.is_some() => return None,
+            _ => {
+                let (tan_width, tan_height) =
+                    view.half_fov_tangents(projection, width as f64 / height as f64)?;
+                Intrinsics {
+                    fx: (width - 1) as f64 / (2.0 * tan_width),
+                    fy: (height - 1) as f64 / (2.0 * tan_height),
+                    ..Intrinsics::centered(width, height)
+                }
+            }
+        };
+
+        Some(Calibration {
+            intrinsics,
+            distortion: view.distortion,
+            extrinsics: Extrinsics::from_view(view),
+        })
+    }
+
+    /// Writes OpenCV YAML or JSON, depending on the extension of `path`.
+    pub fn write(&self, path: &str) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        match Path::new(path).extension().and_then(|e| e.to_str()) {
+            Some("json") => self.write_json(&mut out)?,
+            _ => self.write_yaml(&mut out)?,
+        }
+        out.flush()
+    }
+
+    fn matrices(&self) -> [(&'static str, usize, usize, Vec<f64>); 4] {
+        let k = &self.intrinsics;
+        let d = &self.distortion;
+        [
+            (
+                "camera_matrix",
+                3,
+                3,
+                vec![k.fx, k.skew, k.cx, 0.0, k.fy, k.cy, 0.0, 0.0, 1.0],
+            ),
+            (
+                "distortion_coefficients",
+                1,
+                5,
+                vec![d.k1, d.k2, d.p1, d.p2, d.k3],
+            ),
+            ("rotation_matrix", 3, 3, self.extrinsics.rotation.concat()),
+            (
+                "translation_vector",
+                3,
+                1,
+                self.extrinsics.translation.to_vec(),
+            ),
+        ]
+    }
+
+    pub fn write_yaml(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "%YAML:1.0")?;
+        writeln!(out, "---")?;
+        writeln!(out, "image_width: {}", self.intrinsics.width)?;
+        writeln!(out, "image_height: {}", self.intrinsics.height)?;
+        for (name, rows, cols, data) in self.matrices() {
+            writeln!(out, "{}: !!opencv-matrix", name)?;
+            writeln!(out, "   rows: {}", rows)?;
+            writeln!(out, "   cols: {}", cols)?;
+            writeln!(out, "   dt: d")?;
+            writeln!(out, "   data: [ {} ]", join(&data))?;
+        }
+        Ok(())
+    }
+
+    pub fn write_json(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "{{")?;
+        writeln!(out, "    \"image_width\": {},", self.intrinsics.width)?;
+        write!(out, "    \"image_height\": {}", self.intrinsics.height)?;
+        for (name, rows, cols, data) in self.matrices() {
+            writeln!(out, ",")?;
+            writeln!(out, "    \"{}\": {{", name)?;
+            writeln!(out, "        \"type_id\": \"opencv-matrix\",")?;
+            writeln!(out, "        \"rows\": {},", rows)?;
+            writeln!(out, "        \"cols\": {},", cols)?;
+            writeln!(out, "        \"dt\": \"d\",")?;
+            writeln!(out, "        \"data\": [ {} ]", join(&data))?;
+            write!(out, "    }}")?;
+        }
+        writeln!(out, "\n}}")
+    }
+}
+
+/// Shortest representations reading back to the same values.
+fn join(values: &[f64]) -> String {
+    values
+        .iter()
+        .map(|value| format!("{:?}", value))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aperture::ApertureShape;
+    use approx::*;
+
+    fn view() -> View {
+        View {
+            lookfrom: Point3::new(1.0, 2.0, 3.0),
+            lookat: Point3::new(0.0, 0.0, 0.0),
+            vup: Vec3::new(0.0, 1.0, 0.0),
+            vfov: 40.0,
+            aperture: 0.0,
+            aperture_shape: ApertureShape::Circular,
+            focus_dist: 1.0,
+            lens: None,
+            exit_pupil_bounds: false,
+            distortion: Distortion::default(),
+        }
+    }
+
+    /// Pixel at which the calibration projects a world point.
+    fn project(calibration: &Calibration, point: Point3) -> (f64, f64) {
+        let Extrinsics {
+            rotation,
+            translation,
+        } = calibration.extrinsics;
+        let camera: Vec<f64> = (0..3)
+            .map(|i| {
+                rotation[i][0] * point.x
+                    + rotation[i][1] * point.y
+                    + rotation[i][2] * point.z
+                    + translation[i]
+            })
+            .collect();
+        let (x, y) = (camera[0] / camera[2], camera[1] / camera[2]);
+        let k = &calibration.intrinsics;
+        (k.fx * x + k.skew * y + k.cx, k.fy * y + k.cy)
+    }
+
+    /// Point (s, t) of the image at pixel (u, v).
+    fn image_point(k: &Intrinsics, u: f64, v: f64) -> (f64, f64) {
+        (
+            (u + 0.5) / (k.width - 1) as f64,
+            (k.height as f64 - 0.5 - v) / (k.height - 1) as f64,
+        )
+    }
+
+    fn assert_ray_through(camera: &dyn Camera, s: f64, t: f64, point: Point3) {
+        let ray = camera.get_ray(s, t).unwrap();
+        let to_point = (point - ray.origin).unit_vector();
+        let direction = ray.direction.unit_vector();
+        assert_relative_eq!(to_point.x, direction.x, epsilon = 1e-9);
+        assert_relative_eq!(to_point.y, direction.y, epsilon = 1e-9);
+        assert_relative_eq!(to_point.z, direction.z, epsilon = 1e-9);
+    }
+
+    #[test]
+    fn intrinsics_rays() {
+        let intrinsics = Intrinsics {
+            fx: 800.0,
+            fy: 780.0,
+            cx: 310.0,
+            cy: 245.0,
+            skew: 1.5,
+            width: 640,
+            height: 480,
+        };
+        let projection = Projection::Cv(intrinsics);
+        let camera = view().camera(projection, 640.0 / 480.0);
+        let calibration = Calibration::new(&view(), projection, 640, 480).unwrap();
+
+        // Rays go through the points projected at their pixel
+        for point in [
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(0.3, -0.2, 0.1),
+            Point3::new(-0.5, 0.4, 0.2),
+        ] {
+            let (u, v) = project(&calibration, point);
+            let (s, t) = image_point(&intrinsics, u, v);
+            assert_ray_through(camera.as_ref(), s, t, point);
+        }
+
+        // The principal point looks forward
+        let (s, t) = image_point(&intrinsics, 310.0, 245.0);
+        let forward = camera.get_ray(s, t).unwrap().direction.unit_vector();
+        let expected = (view().lookat - view().lookfrom).unit_vector();
+        assert_relative_eq!(expected.x, forward.x, epsilon = 1e-9);
+        assert_relative_eq!(expected.y, forward.y, epsilon = 1e-9);
+    }
+
+    #[test]
+    fn perspective_calibration() {
+        // The calibration of a perspective render is exact, distortion included
+        let view = View {
+            distortion: Distortion::parse("-0.1,0.01,0.001,0,0").unwrap(),
+            ..view()
+        };
+        let camera = view.camera(Projection::Perspective, 1.5);
+        let calibration = Calibration::new(&view, Projection::Perspective, 300, 200).unwrap();
+        assert_eq!(view.distortion, calibration.distortion);
+
+        for point in [Point3::new(0.0, 0.0, 0.0), Point3::new(0.4, 0.3, -0.2)] {
+            let (u, v) = project(
+                &Calibration {
+                    distortion: Distortion::default(),
+                    ..calibration.clone()
+                },
+                point,
+            );
+            let k = &calibration.intrinsics;
+            let (x, y) = ((u - k.cx) / k.fx, (v - k.cy) / k.fy);
+            let (x, y) = calibration.distortion.distort(x, y);
+            let (s, t) = image_point(k, k.fx * x + k.cx, k.fy * y + k.cy);
+            assert_ray_through(camera.as_ref(), s, t, point);
+        }
+
+        assert!(Calibration::new(&view, Projection::ALL[4], 300, 200).is_none());
+    }
+
+    #[test]
+    fn extrinsics() {
+        let extrinsics = Extrinsics::from_view(&view());
+        let mut placed = View {
+            lookfrom: Point3::new(0.0, 0.0, 0.0),
+            lookat: Point3::new(0.0, 0.0, -1.0),
+            ..view()
+        };
+        extrinsics.place(&mut placed);
+        let again = Extrinsics::from_view(&placed);
+        for i in 0..3 {
+            assert_relative_eq!(
+                extrinsics.translation[i],
+                again.translation[i],
+                epsilon = 1e-12
+            );
+            for j in 0..3 {
+                assert_relative_eq!(
+                    extrinsics.rotation[i][j],
+                    again.rotation[i][j],
+                    epsilon = 1e-12
+                );
+            }
+        }
+
+        // The identity looks down +z from the origin, y down
+        let identity = Extrinsics::parse("1,0,0,0,1,0,0,0,1,0,0,0").unwrap();
+        identity.place(&mut placed);
+        assert_eq!(Point3::new(0.0, 0.0, 0.0).x, placed.lookfrom.x);
+        assert_relative_eq!(1.0, placed.lookat.z);
+        assert_relative_eq!(-1.0, placed.vup.y);
+
+        assert!(Extrinsics::parse("1,0,0,0,1,0,0,0,1,0,0").is_none());
+        assert!(Extrinsics::parse("2,0,0,0,1,0,0,0,1,0,0,0").is_none());
+        assert!(Extrinsics::parse("1,0,0,0,1,0,0,0,-1,0,0,0").is_none());
+    }
+
+    #[test]
+    fn write_opencv() {
+        let calibration = Calibration {
+            intrinsics: Intrinsics::centered(64, 48),
+            distortion: Distortion::parse("-0.1,0.01,0,0").unwrap(),
+            extrinsics: Extrinsics::parse("1,0,0,0,1,0,0,0,1,0.5,0,2").unwrap(),
+        };
+
+        let mut yaml = Vec::new();
+        calibration.write_yaml(&mut yaml).unwrap();
+        let yaml = String::from_utf8(yaml).unwrap();
+        assert!(yaml.starts_with("%YAML:1.0\n---\nimage_width: 64\nimage_height: 48\n"));
+        assert!(yaml.contains(
+            "camera_matrix: !!opencv-matrix\n   rows: 3\n   cols: 3\n   dt: d\n   \
+             data: [ 64.0, 0.0, 31.0, 0.0, 64.0, 24.0, 0.0, 0.0, 1.0 ]\n"
+        ));
+        assert!(yaml.contains("   data: [ -0.1, 0.01, 0.0, 0.0, 0.0 ]\n"));
+        assert!(yaml.contains("translation_vector: !!opencv-matrix\n   rows: 3\n   cols: 1\n"));
+
+        let mut json = Vec::new();
+        calibration.write_json(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert!(json.starts_with("{\n    \"image_width\": 64,\n    \"image_height\": 48,\n"));
+        assert!(json.contains("\"type_id\": \"opencv-matrix\""));
+        assert!(json.contains("\"data\": [ 0.5, 0.0, 2.0 ]\n    }\n}\n"));
+    }
+}
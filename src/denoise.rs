@@ -0,0 +1,292 @@
+use crate::{
+    accumulation::AccumulationBuffer,
+    aov::{Aov, AovBuffer},
+    color::Color,
+    vec3::Vec3,
+};
+
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+/// Parameters of the edge-avoiding à-trous wavelet filter. Every sigma is the tolerance of the
+/// difference between two pixels of a buffer, lower values preserving more edges.
+pub struct DenoiseSettings {
+    pub iterations: u32,
+    pub color_sigma: f64,
+    pub albedo_sigma: f64,
+    pub normal_sigma: f64,
+    /// Tolerated depth difference relative to the depth of the filtered pixel
+    pub depth_sigma: f64,
+}
+
+impl DenoiseSettings {
+    /// Default settings with the color tolerance scaled by `strength`, 0 disabling the filter.
+    pub fn with_strength(strength: f64) -> DenoiseSettings {
+        DenoiseSettings {
+            iterations: 5,
+            color_sigma: 0.6 * strength,
+            albedo_sigma: 0.1,
+            normal_sigma: 0.3,
+            depth_sigma: 0.05,
+        }
+    }
+}
+
+struct Guides {
+    albedo: Vec<Color>,
+    normal: Vec<Vec3>,
+    depth: Vec<f64>,
+}
+
+impl Guides {
+    fn new(aovs: &AovBuffer) -> Guides {
+        let planes = |aov| -> Vec<Vec3> {
+            let planes = aovs.planes(aov);
+            (0..aovs.width * aovs.height)
+                .map(|i| {
+                    Vec3::new(
+                        planes[0][i] as f64,
+                        planes[1][i] as f64,
+                        planes[2][i] as f64,
+                    )
+                })
+                .collect()
+        };
+
+        Guides {
+            albedo: planes(Aov::Albedo),
+            normal: planes(Aov::Normal),
+            depth: aovs.planes(Aov::Depth)[0]
+                .iter()
+                .map(|depth| *depth as f64)
+                .collect(),
+        }
+    }
+
+    fn weight(&self, p: usize, q: usize, settings: &DenoiseSettings) -> f64 {
+        let albedo = (self.albedo[p] - self.albedo[q]).length_squared();
+        let normal = (self.normal[p] - self.normal[q]).length_squared();
+        let depth = (self.depth[p] - self.depth[q]).abs() / self.depth[p].max(1e-3);
+
+        (-albedo / (settings.albedo_sigma * settings.albedo_sigma)
+            - normal / (settings.normal_sigma * settings.normal_sigma)
+            - depth / settings.depth_sigma)
+            .exp()
+    }
+}
+
+/// Denoises the beauty image with the first hit AOVs as edge stopping guides.
+///
+/// Lighting is filtered apart from the albedo, which is multiplied back afterwards so that
+/// textures are not blurred.
+pub fn denoise(
+    beauty: &AccumulationBuffer,
+    aovs: &AovBuffer,
+    settings: &DenoiseSettings,
+) -> AccumulationBuffer {
+    let width = beauty.width;
+    let height = beauty.height;
+    let guides = Guides::new(aovs);
+
+    let demodulation: Vec<Color> = guides
+        .albedo
+        .iter()
+        .map(|albedo| {
+            Color::new(
+                demodulation_factor(albedo.x),
+                demodulation_factor(albedo.y),
+                demodulation_factor(albedo.z),
+            )
+        })
+        .collect();
+
+    let mut irradiance: Vec<Color> = (0..width * height)
+        .map(|i| beauty.pixel(i % width, i / width) / demodulation[i])
+        .collect();
+
+    if settings.color_sigma > 0.0 {
+        for iteration in 0..settings.iterations {
+            let step = 1 << iteration;
+            // Finer levels hold less noise, the color tolerance shrinks accordingly
+            let color_sigma = settings.color_sigma / (1 << iteration) as f64;
+            irradiance = filter_step(
+                &irradiance,
+                &guides,
+                width,
+                height,
+                step,
+                color_sigma,
+                settings,
+            );
+        }
+    }
+
+    let mut result = AccumulationBuffer::new(width, height, beauty.scene_hash);
+    for (i, value) in irradiance.iter().enumerate() {
+        result.add_sample(i % width, i / width, *value * demodulation[i], 1.0);
+    }
+    result
+}
+
+fn demodulation_factor(albedo: f64) -> f64 {
+    if albedo > 1e-3 {
+        albedo
+    } else {
+        1.0
+    }
+}
+
+fn filter_step(
+    input: &[Color],
+    guides: &Guides,
+    width: usize,
+    height: usize,
+    step: isize,
+    color_sigma: f64,
+    settings: &DenoiseSettings,
+) -> Vec<Color> {
+    let mut output = Vec::with_capacity(input.len());
+
+    for y in 0..height as isize {
+        for x in 0..width as isize {
+            let p = (y as usize) * width + x as usize;
+            let mut sum = Color::new(0.0, 0.0, 0.0);
+            let mut total = 0.0;
+
+            for (dy, ky) in KERNEL.iter().enumerate() {
+                for (dx, kx) in KERNEL.iter().enumerate() {
+                    let qx = x + (dx as isize - 2) * step;
+                    let qy = y + (dy as isize - 2) * step;
+                    if qx < 0 || qy < 0 || qx >= width as isize || qy >= height as isize {
+                        continue;
+                    }
+
+                    let q = (qy as usize) * width + qx as usize;
+                    let color = (input[p] - input[q]).length_squared();
+                    let weight = kx
+                        * ky
+                        * guides.weight(p, q, settings)
+                        * (-color / (color_sigma * color_sigma)).exp();
+
+                    sum = sum + weight * input[q];
+                    total += weight;
+                }
+            }
+
+            output.push(sum / total);
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        filter::Filter,
+        hittable::HitRecord,
+        integrator::Integrator,
+        material::Lambertian,
+        random,
+        ray::Ray,
+        render::{self, RenderSettings},
+        scene,
+        vec3::Point3,
+    };
+
+    fn mean_squared_error(image: &AccumulationBuffer, reference: &AccumulationBuffer) -> f64 {
+        let mut error = 0.0;
+        for y in 0..image.height {
+            for x in 0..image.width {
+                error += (image.pixel(x, y) - reference.pixel(x, y)).length_squared();
+            }
+        }
+        error / (image.width * image.height) as f64
+    }
+
+    fn render_reference_scene(samples_per_pixel: u32, seed: u64) -> render::Frame {
+        let scene = scene::load("materials", 3.0 / 2.0).unwrap();
+        let settings = RenderSettings {
+            image_width: 48,
+            image_height: 32,
+            samples_per_pixel,
+            max_depth: 10,
+            integrator: Integrator::PathTracer,
+            filter: Filter::Box { radius: 0.5 },
+            record_aovs: true,
+        };
+
+        random::seed(seed);
+        render::render(&scene, &settings, 0)
+    }
+
+    #[test]
+    fn reduces_error_on_reference_scene() {
+        let reference = render_reference_scene(256, 1);
+        let noisy = render_reference_scene(4, 2);
+
+        let denoised = denoise(
+            &noisy.beauty,
+            noisy.aovs.as_ref().unwrap(),
+            &DenoiseSettings::with_strength(1.0),
+        );
+
+        let before = mean_squared_error(&noisy.beauty, &reference.beauty);
+        let after = mean_squared_error(&denoised, &reference.beauty);
+        assert!(
+            after < 0.5 * before,
+            "error went from {} to {}",
+            before,
+            after
+        );
+    }
+
+    #[test]
+    fn zero_strength_keeps_image() {
+        let noisy = render_reference_scene(1, 3);
+
+        let result = denoise(
+            &noisy.beauty,
+            noisy.aovs.as_ref().unwrap(),
+            &DenoiseSettings::with_strength(0.0),
+        );
+
+        assert!(mean_squared_error(&result, &noisy.beauty) < 1e-12);
+    }
+
+    #[test]
+    fn preserves_normal_edges() {
+        let material = Lambertian::new(Color::new(1.0, 1.0, 1.0));
+        let up = HitRecord::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            1.0,
+            true,
+            &material,
+        );
+        let side = HitRecord::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            1.0,
+            true,
+            &material,
+        );
+
+        // Left half faces up and is bright, right half faces the side and is dark
+        let mut beauty = AccumulationBuffer::new(8, 8, 0);
+        let mut aovs = AovBuffer::new(8, 8);
+        let ray = Ray::new(Point3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        for y in 0..8 {
+            for x in 0..8 {
+                let (value, record) = if x < 4 { (1.0, &up) } else { (0.1, &side) };
+                beauty.add_sample(x, y, Color::new(value, value, value), 1.0);
+                aovs.add_sample(x, y, &ray, Some(record));
+            }
+        }
+
+        let result = denoise(&beauty, &aovs, &DenoiseSettings::with_strength(10.0));
+
+        assert!((result.pixel(3, 4).x - 1.0).abs() < 1e-3);
+        assert!((result.pixel(4, 4).x - 0.1).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,333 @@
+use crate::{camera::Camera, color::Color, image::Image, ray::Ray};
+
+/// Iterations of the fixed point search inverting the distortion, as in OpenCV.
+const UNDISTORT_ITERATIONS: usize = 20;
+
+/// Brown-Conrady distortion of a lens in the normalized image coordinates x / z and y / z of
+/// OpenCV, y going down, with its coefficients, along with lateral chromatic aberration.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Distortion {
+    /// Radial coefficients
+    pub k1: f64,
+    pub k2: f64,
+    pub k3: f64,
+    /// Tangential coefficients
+    pub p1: f64,
+    pub p2: f64,
+    /// Magnifications of the red and blue images relative to the green one, minus one
+    pub lateral_chromatic: (f64, f64),
+}
+
+impl Distortion {
+    /// Parses coefficients in the order of OpenCV, `k1,k2,p1,p2[,k3]`.
+    pub fn parse(coefficients: &str) -> Option<Distortion> {
+        let values: Vec<f64> = coefficients
+            .split(',')
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .ok()?;
+        let (k1, k2, p1, p2, k3) = match values.as_slice() {
+            [k1, k2, p1, p2] => (*k1, *k2, *p1, *p2, 0.0),
+            [k1, k2, p1, p2, k3] => (*k1, *k2, *p1, *p2, *k3),
+            _ => return None,
+        };
+
+        Some(Distortion {
+            k1,
+            k2,
+            k3,
+            p1,
+            p2,
+            ..Default::default()
+        })
+    }
+
+    pub fn is_identity(&self) -> bool {
+        *self == Distortion::default()
+    }
+
+    /// Channel traced by a ray and the weight of its sample, one channel at a time with chromatic
+    /// aberration and all of them otherwise.
+    pub fn sample_channel(&self) -> (usize, Color) {
+        if self.lateral_chromatic != (0.0, 0.0) {
+            Color::random_channel()
+        } else {
+            (1, Color::new(1.0, 1.0, 1.0))
+        }
+    }
+
+    /// Ideal point shown at (x, y) in the image of a channel.
+    pub fn ideal_point(&self, x: f64, y: f64, channel: usize) -> (f64, f64) {
+        let scale = self.channel_scale(channel);
+        self.undistort(x / scale, y / scale)
+    }
+
+    /// Magnification of the image of a channel.
+    fn channel_scale(&self, channel: usize) -> f64 {
+        match channel {
+            0 => 1.0 + self.lateral_chromatic.0,
+            2 => 1.0 + self.lateral_chromatic.1,
+            _ => 1.0,
+        }
+    }
+
+    /// Where the lens images the ideal point (x, y).
+    pub fn distort(&self, x: f64, y: f64) -> (f64, f64) {
+        let r2 = x * x + y * y;
+        let radial = 1.0 + r2 * (self.k1 + r2 * (self.k2 + r2 * self.k3));
+        (
+            x * radial + 2.0 * self.p1 * x * y + self.p2 * (r2 + 2.0 * x * x),
+            y * radial + self.p1 * (r2 + 2.0 * y * y) + 2.0 * self.p2 * x * y,
+        )
+    }
+
+    /// Ideal point the lens images at (x, y), inverting `distort`.
+    pub fn undistort(&self, x: f64, y: f64) -> (f64, f64) {
+        let (mut ideal_x, mut ideal_y) = (x, y);
+        for _ in 0..UNDISTORT_ITERATIONS {
+            let r2 = ideal_x * ideal_x + ideal_y * ideal_y;
+            let radial = 1.0 + r2 * (self.k1 + r2 * (self.k2 + r2 * self.k3));
+            let tangential_x =
+                2.0 * self.p1 * ideal_x * ideal_y + self.p2 * (r2 + 2.0 * ideal_x * ideal_x);
+            let tangential_y =
+                self.p1 * (r2 + 2.0 * ideal_y * ideal_y) + 2.0 * self.p2 * ideal_x * ideal_y;
+            ideal_x = (x - tangential_x) / radial;
+            ideal_y = (y - tangential_y) / radial;
+        }
+        (ideal_x, ideal_y)
+    }
+}
+
+/// Planar camera seen through a distortion, the tangents of its half fields of view giving the
+/// normalized image coordinates. Chromatic aberration traces one channel per ray.
+pub struct DistortedCamera {
+    camera: Box<dyn Camera>,
+    distortion: Distortion,
+    tan_width: f64,
+    tan_height: f64,
+}
+
+impl DistortedCamera {
+    pub fn new(
+        camera: Box<dyn Camera>,
+        distortion: Distortion,
+        tan_width: f64,
+        tan_height: f64,
+    ) -> DistortedCamera {
+        DistortedCamera {
+            camera,
+            distortion,
+            tan_width,
+            tan_height,
+        }
+    }
+}
+
+impl Camera for DistortedCamera {
+    fn get_ray(&self, s: f64, t: f64) -> Option<Ray> {
+        self.get_weighted_ray(s, t).map(|(ray, _)| ray)
+    }
+
+    fn get_weighted_ray(&self, s: f64, t: f64) -> Option<(Ray, Color)> {
+        // The pixel shows the ideal point the lens images there for the channel
+        let (channel, weight) = self.distortion.sample_channel();
+        let (x, y) = self.distortion.ideal_point(
+            (2.0 * s - 1.0) * self.tan_width,
+            (1.0 - 2.0 * t) * self.tan_height,
+            channel,
+        );
+        let (ray, camera_weight) = self.camera.get_weighted_ray(
+            0.5 + x / (2.0 * self.tan_width),
+            0.5 - y / (2.0 * self.tan_height),
+        )?;
+
+        Some((ray, weight * camera_weight))
+    }
+
+    fn exposure(&self) -> f64 {
+        self.camera.exposure()
+    }
+}
+
+/// Removes the distortion of an image whose vertical field of view has a half angle of tangent
+/// `tan_height`, resampling it where the lens imaged every ideal point.
+pub fn undistort_image(image: &Image, distortion: &Distortion, tan_height: f64) -> Image {
+    let tan_width = tan_height * image.width as f64 / image.height as f64;
+    let mut pixels = Vec::with_capacity(image.pixels.len());
+
+    for row in 0..image.height {
+        for column in 0..image.width {
+            let x = (2.0 * (column as f64 + 0.5) / image.width as f64 - 1.0) * tan_width;
+            let y = (2.0 * (row as f64 + 0.5) / image.height as f64 - 1.0) * tan_height;
+            let (distorted_x, distorted_y) = distortion.distort(x, y);
+
+            let mut channels = [0.0; 3];
+            for (channel, value) in channels.iter_mut().enumerate() {
+                let scale = distortion.channel_scale(channel);
+                let color = bilinear(
+                    image,
+                    ((distorted_x * scale / tan_width + 1.0) / 2.0) * image.width as f64 - 0.5,
+                    ((distorted_y * scale / tan_height + 1.0) / 2.0) * image.height as f64 - 0.5,
+                );
+                *value = [color.x, color.y, color.z][channel];
+            }
+            pixels.push(Color::new(channels[0], channels[1], channels[2]));
+        }
+    }
+
+    Image {
+        width: image.width,
+        height: image.height,
+        pixels,
+    }
+}
+
+/// Interpolates the pixels around (x, y), in pixels from the center of the top left one. Black
+/// outside of the image.
+fn bilinear(image: &Image, x: f64, y: f64) -> Color {
+    if x < -0.5 || y < -0.5 || x > image.width as f64 - 0.5 || y > image.height as f64 - 0.5 {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+
+    let x = x.clamp(0.0, (image.width - 1) as f64);
+    let y = y.clamp(0.0, (image.height - 1) as f64);
+    let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+    let (x1, y1) = (
+        (x0 + 1).min(image.width - 1),
+        (y0 + 1).min(image.height - 1),
+    );
+    let (fx, fy) = (x - x0 as f64, y - y0 as f64);
+
+    let top = (1.0 - fx) * image.pixel(x0, y0) + fx * image.pixel(x1, y0);
+    let bottom = (1.0 - fx) * image.pixel(x0, y1) + fx * image.pixel(x1, y1);
+    (1.0 - fy) * top + fy * bottom
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        aperture::ApertureShape,
+        camera::{Projection, View},
+        random,
+        vec3::{Point3, Vec3},
+    };
+    use approx::*;
+
+    fn view(distortion: Distortion) -> View {
+        View {
+            lookfrom: Point3::new(0.0, 0.0, 0.0),
+            lookat: Point3::new(0.0, 0.0, -1.0),
+            vup: Vec3::new(0.0, 1.0, 0.0),
+            vfov: 90.0,
+            aperture: 0.0,
+            aperture_shape: ApertureShape::Circular,
+            focus_dist: 1.0,
+            lens: None,
+            exit_pupil_bounds: false,
+            distortion,
+        }
+    }
+
+    #[test]
+    fn parse_coefficients() {
+        let distortion = Distortion::parse("-0.2,0.05,0.001,-0.002,0.01").unwrap();
+        assert_eq!(-0.2, distortion.k1);
+        assert_eq!(0.05, distortion.k2);
+        assert_eq!(0.001, distortion.p1);
+        assert_eq!(-0.002, distortion.p2);
+        assert_eq!(0.01, distortion.k3);
+        assert_eq!(Some(0.0), Distortion::parse("0.1,0,0,0").map(|d| d.k3));
+        assert!(Distortion::parse("0.1,0.2").is_none());
+    }
+
+    #[test]
+    fn undistort_inverts_distort() {
+        let distortion = Distortion::parse("-0.25,0.08,0.002,-0.001,-0.01").unwrap();
+        for (x, y) in [(0.0, 0.0), (0.3, -0.2), (-0.5, 0.4), (0.7, 0.1)] {
+            let (distorted_x, distorted_y) = distortion.distort(x, y);
+            let (ideal_x, ideal_y) = distortion.undistort(distorted_x, distorted_y);
+            assert_relative_eq!(x, ideal_x, epsilon = 1e-9);
+            assert_relative_eq!(y, ideal_y, epsilon = 1e-9);
+        }
+
+        // Barrel distortion pulls points towards the center
+        let (x, _) = distortion.distort(0.5, 0.0);
+        assert!(x < 0.5);
+    }
+
+    #[test]
+    fn distorted_rays() {
+        let ideal = view(Distortion::default()).camera(Projection::Perspective, 1.0);
+        let barrel =
+            view(Distortion::parse("-0.1,0,0,0").unwrap()).camera(Projection::Perspective, 1.0);
+
+        // The center is kept, the edges see further out
+        let center = barrel.get_ray(0.5, 0.5).unwrap().direction;
+        assert_relative_eq!(0.0, center.x / -center.z, epsilon = 1e-12);
+        let edge = barrel.get_ray(1.0, 0.5).unwrap().direction;
+        let ideal_edge = ideal.get_ray(1.0, 0.5).unwrap().direction;
+        assert_relative_eq!(1.0, ideal_edge.x / -ideal_edge.z, epsilon = 1e-12);
+        assert!(edge.x / -edge.z > 1.0);
+        let (distorted, _) = Distortion::parse("-0.1,0,0,0")
+            .unwrap()
+            .distort(edge.x / -edge.z, 0.0);
+        assert_relative_eq!(1.0, distorted, epsilon = 1e-9);
+    }
+
+    #[test]
+    fn lateral_chromatic_aberration() {
+        let distortion = Distortion {
+            lateral_chromatic: (0.01, -0.01),
+            ..Default::default()
+        };
+        let camera = view(distortion).camera(Projection::Perspective, 1.0);
+
+        // The red image is larger, so a pixel shows it from closer to the center
+        random::seed(5);
+        let mut tangents = [None; 3];
+        for _ in 0..100 {
+            let (ray, weight) = camera.get_weighted_ray(1.0, 0.5).unwrap();
+            let channel = [weight.x, weight.y, weight.z]
+                .iter()
+                .position(|value| *value > 0.0)
+                .unwrap();
+            tangents[channel] = Some(ray.direction.x / -ray.direction.z);
+        }
+        let [red, green, blue] = tangents.map(Option::unwrap);
+        assert_relative_eq!(1.0, green, epsilon = 1e-12);
+        assert_relative_eq!(1.0 / 1.01, red, epsilon = 1e-12);
+        assert_relative_eq!(1.0 / 0.99, blue, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn undistort_rendered_image() {
+        // A vertical line imaged through barrel distortion bends towards the center, undistorting
+        // straightens it again at its ideal position
+        let distortion = Distortion::parse("-0.1,0,0,0").unwrap();
+        let (width, height) = (64, 64);
+        let ideal_x = 0.6;
+        let mut pixels = vec![Color::new(0.0, 0.0, 0.0); width * height];
+        for i in 0..1000 {
+            let (x, y) = distortion.distort(ideal_x, 2.0 * i as f64 / 999.0 - 1.0);
+            let column = ((x + 1.0) / 2.0 * width as f64) as usize;
+            let row = ((y + 1.0) / 2.0 * height as f64) as usize;
+            pixels[row.min(height - 1) * width + column] = Color::new(1.0, 1.0, 1.0);
+        }
+        let image = Image {
+            width,
+            height,
+            pixels,
+        };
+
+        let undistorted = undistort_image(&image, &distortion, 1.0);
+        let expected = (ideal_x + 1.0) / 2.0 * width as f64 - 0.5;
+        for row in 8..height - 8 {
+            let (sum, weight) = (0..width).fold((0.0, 0.0), |(sum, weight), column| {
+                let value = undistorted.pixel(column, row).x;
+                (sum + column as f64 * value, weight + value)
+            });
+            assert!((sum / weight - expected).abs() < 1.0);
+        }
+    }
+}
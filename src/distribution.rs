@@ -0,0 +1,152 @@
+/// Piecewise-constant distribution over [0, 1) built from a tabulated function.
+pub struct Distribution1D {
+    func: Vec<f64>,
+    cdf: Vec<f64>,
+    func_int: f64,
+}
+
+impl Distribution1D {
+    pub fn new(func: Vec<f64>) -> Distribution1D {
+        let n = func.len();
+        let mut cdf = vec![0.0; n + 1];
+        for i in 1..=n {
+            cdf[i] = cdf[i - 1] + func[i - 1].abs() / n as f64;
+        }
+
+        let func_int = cdf[n];
+        for (i, value) in cdf.iter_mut().enumerate().skip(1) {
+            *value = if func_int == 0.0 {
+                i as f64 / n as f64
+            } else {
+                *value / func_int
+            };
+        }
+
+        Distribution1D {
+            func,
+            cdf,
+            func_int,
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.func.len()
+    }
+
+    /// Integral of the function over [0, 1)
+    pub fn integral(&self) -> f64 {
+        self.func_int
+    }
+
+    /// Maps a uniform number to a sample, returning it along with its density and the index of
+    /// the segment it falls into.
+    pub fn sample_continuous(&self, u: f64) -> (f64, f64, usize) {
+        let offset = self
+            .cdf
+            .partition_point(|value| *value <= u)
+            .saturating_sub(1)
+            .min(self.count() - 1);
+
+        let mut du = u - self.cdf[offset];
+        let width = self.cdf[offset + 1] - self.cdf[offset];
+        if width > 0.0 {
+            du /= width;
+        }
+
+        let pdf = if self.func_int > 0.0 {
+            self.func[offset].abs() / self.func_int
+        } else {
+            1.0
+        };
+
+        ((offset as f64 + du) / self.count() as f64, pdf, offset)
+    }
+
+    pub fn pdf(&self, x: f64) -> f64 {
+        if self.func_int == 0.0 {
+            return 1.0;
+        }
+        let offset = ((x * self.count() as f64) as usize).min(self.count() - 1);
+        self.func[offset].abs() / self.func_int
+    }
+}
+
+/// Piecewise-constant distribution over [0, 1)² from a function tabulated row by row.
+pub struct Distribution2D {
+    conditional: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    pub fn new(func: &[f64], width: usize, height: usize) -> Distribution2D {
+        let conditional: Vec<Distribution1D> = func
+            .chunks(width)
+            .take(height)
+            .map(|row| Distribution1D::new(row.to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(conditional.iter().map(|d| d.integral()).collect());
+
+        Distribution2D {
+            conditional,
+            marginal,
+        }
+    }
+
+    /// Returns a sampled point and its density.
+    pub fn sample_continuous(&self, u0: f64, u1: f64) -> ((f64, f64), f64) {
+        let (y, pdf_y, row) = self.marginal.sample_continuous(u1);
+        let (x, pdf_x, _) = self.conditional[row].sample_continuous(u0);
+
+        ((x, y), pdf_x * pdf_y)
+    }
+
+    pub fn pdf(&self, x: f64, y: f64) -> f64 {
+        let row = ((y * self.marginal.count() as f64) as usize).min(self.marginal.count() - 1);
+        self.marginal.pdf(y) * self.conditional[row].pdf(x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::*;
+
+    #[test]
+    fn sample_1d() {
+        let distribution = Distribution1D::new(vec![1.0, 3.0]);
+
+        assert_relative_eq!(2.0, distribution.integral());
+
+        let (x, pdf, offset) = distribution.sample_continuous(0.125);
+        assert_relative_eq!(0.25, x);
+        assert_relative_eq!(0.5, pdf);
+        assert_eq!(0, offset);
+
+        let (x, pdf, offset) = distribution.sample_continuous(0.625);
+        assert_relative_eq!(0.75, x);
+        assert_relative_eq!(1.5, pdf);
+        assert_eq!(1, offset);
+        assert_relative_eq!(1.5, distribution.pdf(x));
+    }
+
+    #[test]
+    fn zero_function_is_uniform() {
+        let distribution = Distribution1D::new(vec![0.0; 4]);
+        let (x, pdf, _) = distribution.sample_continuous(0.3);
+
+        assert_relative_eq!(0.3, x, epsilon = 1e-12);
+        assert_relative_eq!(1.0, pdf);
+    }
+
+    #[test]
+    fn sample_2d() {
+        // Only the bottom right cell has any weight
+        let distribution = Distribution2D::new(&[0.0, 0.0, 0.0, 2.0], 2, 2);
+        let ((x, y), pdf) = distribution.sample_continuous(0.5, 0.5);
+
+        assert!(x >= 0.5 && y >= 0.5);
+        assert_relative_eq!(4.0, pdf);
+        assert_relative_eq!(4.0, distribution.pdf(x, y));
+        assert_relative_eq!(0.0, distribution.pdf(0.25, 0.25));
+    }
+}
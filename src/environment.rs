@@ -0,0 +1,197 @@
+use std::f64::consts::PI;
+
+use crate::{color::Color, distribution::Distribution2D, image::Image, random, vec3::Vec3};
+
+/// Light arriving from infinitely far away, seen by the rays leaving the scene.
+pub trait Environment {
+    /// Radiance arriving along the opposite of the unit vector `direction`.
+    fn radiance(&self, direction: Vec3) -> Color;
+
+    /// Samples a unit direction towards the environment, returning it with the radiance coming
+    /// from it and its solid angle density. None when the environment cannot be sampled, the
+    /// integrator then only finds it by scattering.
+    fn sample(&self) -> Option<(Vec3, Color, f64)> {
+        None
+    }
+
+    /// Solid angle density with which `sample` picks `direction`.
+    fn pdf(&self, _direction: Vec3) -> f64 {
+        0.0
+    }
+}
+
+/// Blend from white at the horizon to light blue at the zenith.
+pub struct SkyGradient;
+
+impl Environment for SkyGradient {
+    fn radiance(&self, direction: Vec3) -> Color {
+        let t = 0.5 * (direction.y + 1.0);
+
+        (1.0 - t) * Color::new(1.0, 1.0, 1.0) + t * Color::new(0.5, 0.7, 1.0)
+    }
+}
+
+pub fn luminance(color: Color) -> f64 {
+    0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+}
+
+/// Equirectangular (latitude-longitude) image around the scene, importance sampled according to
+/// its luminance.
+pub struct EnvironmentMap {
+    image: Image,
+    /// Rotation around the vertical axis, in radians
+    rotation: f64,
+    intensity: f64,
+    distribution: Distribution2D,
+}
+
+impl EnvironmentMap {
+    pub fn new(image: Image, rotation_degrees: f64, intensity: f64) -> EnvironmentMap {
+        // Rows near the poles cover a smaller solid angle
+        let mut weights = Vec::with_capacity(image.width * image.height);
+        for y in 0..image.height {
+            let sin_theta = (PI * (y as f64 + 0.5) / image.height as f64).sin();
+            for x in 0..image.width {
+                weights.push(luminance(image.pixel(x, y)) * sin_theta);
+            }
+        }
+        let distribution = Distribution2D::new(&weights, image.width, image.height);
+
+        EnvironmentMap {
+            image,
+            rotation: rotation_degrees * PI / 180.0,
+            intensity,
+            distribution,
+        }
+    }
+
+    /// Image coordinates in [0, 1)² of a unit direction.
+    fn direction_to_uv(&self, direction: Vec3) -> (f64, f64) {
+        let theta = direction.y.clamp(-1.0, 1.0).acos();
+        let phi = (direction.z.atan2(direction.x) - self.rotation + PI).rem_euclid(2.0 * PI);
+
+        (phi / (2.0 * PI), theta / PI)
+    }
+
+    fn uv_to_direction(&self, u: f64, v: f64) -> Vec3 {
+        let theta = v * PI;
+        let phi = 2.0 * PI * u - PI + self.rotation;
+
+        Vec3::new(
+            theta.sin() * phi.cos(),
+            theta.cos(),
+            theta.sin() * phi.sin(),
+        )
+    }
+
+    fn lookup(&self, u: f64, v: f64) -> Color {
+        let x = ((u * self.image.width as f64) as usize).min(self.image.width - 1);
+        let y = ((v * self.image.height as f64) as usize).min(self.image.height - 1);
+
+        self.intensity * self.image.pixel(x, y)
+    }
+}
+
+impl Environment for EnvironmentMap {
+    fn radiance(&self, direction: Vec3) -> Color {
+        let (u, v) = self.direction_to_uv(direction);
+        self.lookup(u, v)
+    }
+
+    fn sample(&self) -> Option<(Vec3, Color, f64)> {
+        let ((u, v), map_pdf) = self
+            .distribution
+            .sample_continuous(random::random_double(), random::random_double());
+        let sin_theta = (v * PI).sin();
+        if map_pdf == 0.0 || sin_theta == 0.0 {
+            return None;
+        }
+
+        // Change of variables from the image to the sphere of directions
+        let pdf = map_pdf / (2.0 * PI * PI * sin_theta);
+
+        Some((self.uv_to_direction(u, v), self.lookup(u, v), pdf))
+    }
+
+    fn pdf(&self, direction: Vec3) -> f64 {
+        let (u, v) = self.direction_to_uv(direction);
+        let sin_theta = (v * PI).sin();
+        if sin_theta == 0.0 {
+            return 0.0;
+        }
+
+        self.distribution.pdf(u, v) / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::*;
+
+    macro_rules! assert_vec3_equal {
+        ($expected:expr, $actual:expr) => {
+            let tolerance = 0.0001;
+            assert_relative_eq!($expected.x, $actual.x, epsilon = tolerance);
+            assert_relative_eq!($expected.y, $actual.y, epsilon = tolerance);
+            assert_relative_eq!($expected.z, $actual.z, epsilon = tolerance);
+        };
+    }
+
+    fn sun_map() -> EnvironmentMap {
+        // A dim map with a single bright pixel
+        let mut pixels = vec![Color::new(0.1, 0.1, 0.1); 16 * 8];
+        pixels[2 * 16 + 5] = Color::new(1000.0, 1000.0, 1000.0);
+        let image = Image {
+            width: 16,
+            height: 8,
+            pixels,
+        };
+        EnvironmentMap::new(image, 30.0, 2.0)
+    }
+
+    #[test]
+    fn direction_mapping() {
+        let map = sun_map();
+        let direction = Vec3::new(1.0, 2.0, -3.0).unit_vector();
+        let (u, v) = map.direction_to_uv(direction);
+
+        assert_vec3_equal!(direction, map.uv_to_direction(u, v));
+    }
+
+    #[test]
+    fn samples_bright_pixels() {
+        let map = sun_map();
+        random::seed(5);
+
+        let mut bright = 0;
+        for _ in 0..100 {
+            let (direction, radiance, pdf) = map.sample().unwrap();
+            assert_relative_eq!(pdf, map.pdf(direction), max_relative = 1e-6);
+            assert_vec3_equal!(radiance, map.radiance(direction));
+            if radiance.x > 1000.0 {
+                bright += 1;
+            }
+        }
+
+        assert!(bright > 90);
+    }
+
+    #[test]
+    fn pdf_integrates_to_one() {
+        let map = sun_map();
+        let steps = 400;
+        let mut integral = 0.0;
+        for i in 0..steps {
+            for j in 0..steps {
+                let u = (j as f64 + 0.5) / steps as f64;
+                let v = (i as f64 + 0.5) / steps as f64;
+                let direction = map.uv_to_direction(u, v);
+                let solid_angle = 2.0 * PI * PI * (v * PI).sin() / (steps as f64 * steps as f64);
+                integral += map.pdf(direction) * solid_angle;
+            }
+        }
+
+        assert_relative_eq!(1.0, integral, epsilon = 0.01);
+    }
+}
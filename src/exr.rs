@@ -0,0 +1,128 @@
+use std::io::{self, Write};
+
+const MAGIC: [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+const PIXEL_TYPE_FLOAT: i32 = 2;
+
+/// A plane of values named after its layer and component, e.g. `albedo.R`.
+pub struct Channel {
+    pub name: String,
+    pub values: Vec<f32>,
+}
+
+/// Writes an uncompressed scanline OpenEXR image, rows of the channels starting from the top.
+pub fn write_exr(
+    out: &mut impl Write,
+    width: usize,
+    height: usize,
+    mut channels: Vec<Channel>,
+) -> io::Result<()> {
+    // Readers expect the channels sorted by name
+    channels.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&MAGIC);
+    header.extend_from_slice(&2i32.to_le_bytes());
+
+    let mut channel_list = Vec::new();
+    for channel in &channels {
+        channel_list.extend_from_slice(channel.name.as_bytes());
+        channel_list.push(0);
+        channel_list.extend_from_slice(&PIXEL_TYPE_FLOAT.to_le_bytes());
+        // pLinear and reserved bytes, then x and y sampling
+        channel_list.extend_from_slice(&[0, 0, 0, 0]);
+        channel_list.extend_from_slice(&1i32.to_le_bytes());
+        channel_list.extend_from_slice(&1i32.to_le_bytes());
+    }
+    channel_list.push(0);
+
+    let mut window = Vec::new();
+    for value in [0, 0, width as i32 - 1, height as i32 - 1] {
+        window.extend_from_slice(&value.to_le_bytes());
+    }
+
+    write_attribute(&mut header, "channels", "chlist", &channel_list);
+    write_attribute(&mut header, "compression", "compression", &[0]);
+    write_attribute(&mut header, "dataWindow", "box2i", &window);
+    write_attribute(&mut header, "displayWindow", "box2i", &window);
+    write_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    write_attribute(
+        &mut header,
+        "pixelAspectRatio",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    write_attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    write_attribute(
+        &mut header,
+        "screenWindowWidth",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    header.push(0);
+
+    out.write_all(&header)?;
+
+    // Every scanline block holds its row number, its size and the row of each channel
+    let data_size = channels.len() * width * 4;
+    let block_size = 8 + data_size;
+    let first_block = header.len() + 8 * height;
+    for y in 0..height {
+        out.write_all(&((first_block + y * block_size) as u64).to_le_bytes())?;
+    }
+
+    for y in 0..height {
+        out.write_all(&(y as i32).to_le_bytes())?;
+        out.write_all(&(data_size as i32).to_le_bytes())?;
+        for channel in &channels {
+            for value in &channel.values[y * width..(y + 1) * width] {
+                out.write_all(&value.to_le_bytes())?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn write_attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layout() {
+        let channels = vec![
+            Channel {
+                name: "R".to_string(),
+                values: vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0],
+            },
+            Channel {
+                name: "B".to_string(),
+                values: vec![0.0; 6],
+            },
+        ];
+        let mut bytes = Vec::new();
+        write_exr(&mut bytes, 3, 2, channels).unwrap();
+
+        assert_eq!(MAGIC, bytes[..4]);
+
+        // The offset table points at the blocks, the last one ending the file
+        let header_end = bytes.len() - 2 * 8 - 2 * (8 + 2 * 3 * 4);
+        let first = u64::from_le_bytes(bytes[header_end..header_end + 8].try_into().unwrap());
+        assert_eq!(header_end + 16, first as usize);
+
+        // B is written before R in every block
+        let block = first as usize;
+        let row = i32::from_le_bytes(bytes[block..block + 4].try_into().unwrap());
+        let red = f32::from_le_bytes(bytes[block + 20..block + 24].try_into().unwrap());
+        assert_eq!(0, row);
+        assert_eq!(1.0, red);
+    }
+}
@@ -0,0 +1,174 @@
+use std::f64::consts::PI;
+
+/// Reconstruction filter weighting the samples of the pixels around them, by their offsets in
+/// pixels. Every filter is separable and zero beyond its radius.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    /// Averages the samples inside each pixel, as long as the radius is half a pixel
+    Box {
+        radius: f64,
+    },
+    Tent {
+        radius: f64,
+    },
+    /// Gaussian of standard deviation a third of the radius, shifted to vanish at the radius
+    Gaussian {
+        radius: f64,
+    },
+    /// Mitchell-Netravali cubic with B = C = 1/3, stretched over the radius
+    Mitchell {
+        radius: f64,
+    },
+    /// Sinc windowed by a wider sinc, with as many lobes as the radius
+    Lanczos {
+        radius: f64,
+    },
+    /// Four-term Blackman-Harris window over the diameter
+    BlackmanHarris {
+        radius: f64,
+    },
+}
+
+impl Filter {
+    pub const ALL: [Filter; 6] = [
+        Filter::Box { radius: 0.5 },
+        Filter::Tent { radius: 1.0 },
+        Filter::Gaussian { radius: 1.5 },
+        Filter::Mitchell { radius: 2.0 },
+        Filter::Lanczos { radius: 3.0 },
+        Filter::BlackmanHarris { radius: 2.0 },
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Filter::Box { .. } => "box",
+            Filter::Tent { .. } => "tent",
+            Filter::Gaussian { .. } => "gaussian",
+            Filter::Mitchell { .. } => "mitchell",
+            Filter::Lanczos { .. } => "lanczos",
+            Filter::BlackmanHarris { .. } => "blackman-harris",
+        }
+    }
+
+    /// Returns the filter of that name with its default radius.
+    pub fn parse(name: &str) -> Option<Filter> {
+        Filter::ALL.into_iter().find(|filter| filter.name() == name)
+    }
+
+    pub fn radius(&self) -> f64 {
+        match *self {
+            Filter::Box { radius }
+            | Filter::Tent { radius }
+            | Filter::Gaussian { radius }
+            | Filter::Mitchell { radius }
+            | Filter::Lanczos { radius }
+            | Filter::BlackmanHarris { radius } => radius,
+        }
+    }
+
+    pub fn set_radius(&mut self, value: f64) {
+        match self {
+            Filter::Box { radius }
+            | Filter::Tent { radius }
+            | Filter::Gaussian { radius }
+            | Filter::Mitchell { radius }
+            | Filter::Lanczos { radius }
+            | Filter::BlackmanHarris { radius } => *radius = value,
+        }
+    }
+
+    /// Weight of a sample offset by `dx` and `dy` pixels from the center of a pixel.
+    pub fn weight(&self, dx: f64, dy: f64) -> f64 {
+        self.evaluate(dx) * self.evaluate(dy)
+    }
+
+    fn evaluate(&self, x: f64) -> f64 {
+        let radius = self.radius();
+        let x = x.abs();
+        if x > radius {
+            return 0.0;
+        }
+
+        match self {
+            Filter::Box { .. } => 1.0,
+            Filter::Tent { .. } => radius - x,
+            Filter::Gaussian { .. } => {
+                let gaussian = |x: f64| (-x * x / (2.0 * (radius / 3.0).powi(2))).exp();
+                gaussian(x) - gaussian(radius)
+            }
+            Filter::Mitchell { .. } => mitchell(2.0 * x / radius, 1.0 / 3.0, 1.0 / 3.0),
+            Filter::Lanczos { .. } => sinc(x) * sinc(x / radius),
+            Filter::BlackmanHarris { .. } => {
+                let t = 2.0 * PI * (x + radius) / (2.0 * radius);
+                0.35875 - 0.48829 * t.cos() + 0.14128 * (2.0 * t).cos() - 0.01168 * (3.0 * t).cos()
+            }
+        }
+    }
+}
+
+/// Mitchell-Netravali cubic, nonzero over [0, 2[.
+fn mitchell(x: f64, b: f64, c: f64) -> f64 {
+    let (x2, x3) = (x * x, x * x * x);
+    let value = if x < 1.0 {
+        (12.0 - 9.0 * b - 6.0 * c) * x3 + (-18.0 + 12.0 * b + 6.0 * c) * x2 + (6.0 - 2.0 * b)
+    } else if x < 2.0 {
+        (-b - 6.0 * c) * x3
+            + (6.0 * b + 30.0 * c) * x2
+            + (-12.0 * b - 48.0 * c) * x
+            + (8.0 * b + 24.0 * c)
+    } else {
+        0.0
+    };
+    value / 6.0
+}
+
+fn sinc(x: f64) -> f64 {
+    if x < 1e-5 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::*;
+
+    #[test]
+    fn zero_beyond_radius() {
+        for filter in Filter::ALL {
+            let radius = filter.radius();
+            assert!(filter.weight(0.0, 0.0) > 0.0, "{}", filter.name());
+            if filter.name() != "box" {
+                assert_relative_eq!(0.0, filter.weight(radius, 0.0), epsilon = 1e-4);
+            }
+            assert_eq!(0.0, filter.weight(0.0, radius + 0.01));
+            assert_eq!(filter.weight(0.3, -0.2), filter.weight(-0.3, 0.2));
+        }
+    }
+
+    #[test]
+    fn filter_values() {
+        assert_eq!(1.0, Filter::Box { radius: 0.5 }.weight(0.49, -0.49));
+        assert_relative_eq!(0.25, Filter::Tent { radius: 1.0 }.weight(0.5, 0.5));
+        assert_relative_eq!(8.0 / 9.0, Filter::Mitchell { radius: 2.0 }.evaluate(0.0));
+        assert_relative_eq!(1.0 / 18.0, Filter::Mitchell { radius: 2.0 }.evaluate(1.0));
+
+        // Lanczos crosses zero at every pixel center and has a negative lobe
+        let lanczos = Filter::Lanczos { radius: 3.0 };
+        assert_relative_eq!(0.0, lanczos.evaluate(1.0), epsilon = 1e-12);
+        assert_relative_eq!(0.0, lanczos.evaluate(2.0), epsilon = 1e-12);
+        assert!(lanczos.evaluate(1.5) < 0.0);
+
+        assert_relative_eq!(1.0, Filter::BlackmanHarris { radius: 2.0 }.evaluate(0.0));
+    }
+
+    #[test]
+    fn set_radius() {
+        let mut filter = Filter::parse("gaussian").unwrap();
+        filter.set_radius(2.5);
+        assert_eq!(Filter::Gaussian { radius: 2.5 }, filter);
+        assert_eq!(None, Filter::parse("sinc"));
+    }
+}
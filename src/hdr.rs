@@ -0,0 +1,187 @@
+use std::io::{self, BufRead};
+
+use crate::{color::Color, image::Image};
+
+/// Largest width or height accepted when reading, bounding the allocation a header can request.
+const MAX_DIMENSION: usize = 1 << 16;
+
+/// Reads a Radiance RGBE image, flat or run-length encoded, stored top to bottom.
+pub fn read_hdr(input: &mut impl BufRead) -> io::Result<Image> {
+    let mut line = String::new();
+    input.read_line(&mut line)?;
+    if !line.starts_with("#?") {
+        return Err(invalid_data("not a Radiance HDR image"));
+    }
+
+    // Header variables end with an empty line
+    loop {
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            return Err(invalid_data("truncated HDR header"));
+        }
+        let variable = line.trim();
+        if variable.is_empty() {
+            break;
+        }
+        if variable.starts_with("FORMAT=") && variable != "FORMAT=32-bit_rle_rgbe" {
+            return Err(invalid_data("unsupported HDR pixel format"));
+        }
+    }
+
+    line.clear();
+    input.read_line(&mut line)?;
+    let resolution: Vec<&str> = line.split_whitespace().collect();
+    let (height, width) = match resolution.as_slice() {
+        ["-Y", height, "+X", width] => (parse(height)?, parse(width)?),
+        _ => return Err(invalid_data("unsupported HDR image orientation")),
+    };
+    if !(1..=MAX_DIMENSION).contains(&width) || !(1..=MAX_DIMENSION).contains(&height) {
+        return Err(invalid_data("unsupported HDR image resolution"));
+    }
+
+    let mut pixels = Vec::with_capacity(width * height);
+    let mut scanline = vec![[0u8; 4]; width];
+    for _ in 0..height {
+        read_scanline(input, &mut scanline)?;
+        pixels.extend(scanline.iter().map(rgbe_to_color));
+    }
+
+    Ok(Image {
+        width,
+        height,
+        pixels,
+    })
+}
+
+fn read_scanline(input: &mut impl BufRead, scanline: &mut [[u8; 4]]) -> io::Result<()> {
+    let width = scanline.len();
+    let mut start = [0u8; 4];
+    input.read_exact(&mut start)?;
+
+    let encoded = (8..0x8000).contains(&width)
+        && start[0] == 2
+        && start[1] == 2
+        && ((start[2] as usize) << 8 | start[3] as usize) == width;
+    if !encoded {
+        scanline[0] = start;
+        for pixel in scanline.iter_mut().skip(1) {
+            input.read_exact(pixel)?;
+        }
+        return Ok(());
+    }
+
+    // Every component is stored separately as runs of equal bytes or literal bytes
+    for component in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let mut count = [0u8; 1];
+            input.read_exact(&mut count)?;
+            let (count, run) = if count[0] > 128 {
+                (count[0] as usize - 128, true)
+            } else {
+                (count[0] as usize, false)
+            };
+            if count == 0 || x + count > width {
+                return Err(invalid_data("corrupted HDR scanline"));
+            }
+
+            let mut value = [0u8; 1];
+            if run {
+                input.read_exact(&mut value)?;
+            }
+            for pixel in &mut scanline[x..x + count] {
+                if !run {
+                    input.read_exact(&mut value)?;
+                }
+                pixel[component] = value[0];
+            }
+            x += count;
+        }
+    }
+
+    Ok(())
+}
+
+fn rgbe_to_color(rgbe: &[u8; 4]) -> Color {
+    if rgbe[3] == 0 {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+    let scale = 2.0_f64.powi(rgbe[3] as i32 - (128 + 8));
+    Color::new(
+        (rgbe[0] as f64 + 0.5) * scale,
+        (rgbe[1] as f64 + 0.5) * scale,
+        (rgbe[2] as f64 + 0.5) * scale,
+    )
+}
+
+fn parse(value: &str) -> io::Result<usize> {
+    value
+        .parse()
+        .map_err(|_| invalid_data("invalid HDR image resolution"))
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::*;
+
+    fn header(width: usize, height: usize) -> Vec<u8> {
+        format!(
+            "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+            height, width
+        )
+        .into_bytes()
+    }
+
+    #[test]
+    fn read_flat() {
+        let mut bytes = header(2, 1);
+        // 1.0 is a mantissa of 128 with an exponent of 129
+        bytes.extend_from_slice(&[128, 64, 0, 129, 0, 0, 0, 0]);
+        let image = read_hdr(&mut bytes.as_slice()).unwrap();
+
+        assert_eq!(2, image.width);
+        assert_relative_eq!(1.0, image.pixel(0, 0).x, epsilon = 0.01);
+        assert_relative_eq!(0.5, image.pixel(0, 0).y, epsilon = 0.01);
+        assert_relative_eq!(0.0, image.pixel(1, 0).x);
+    }
+
+    #[test]
+    fn read_run_length_encoded() {
+        let mut bytes = header(8, 1);
+        bytes.extend_from_slice(&[2, 2, 0, 8]);
+        // Red is one run, green literal bytes, blue and exponent runs
+        bytes.extend_from_slice(&[128 + 8, 128]);
+        bytes.extend_from_slice(&[8, 0, 16, 32, 48, 64, 80, 96, 112]);
+        bytes.extend_from_slice(&[128 + 8, 0]);
+        bytes.extend_from_slice(&[128 + 8, 129]);
+        let image = read_hdr(&mut bytes.as_slice()).unwrap();
+
+        assert_eq!(8, image.width);
+        for x in 0..8 {
+            assert_relative_eq!(1.0, image.pixel(x, 0).x, epsilon = 0.01);
+            assert_relative_eq!(x as f64 / 8.0, image.pixel(x, 0).y, epsilon = 0.01);
+        }
+    }
+
+    #[test]
+    fn read_invalid() {
+        let bytes = b"P3\n1 1\n255\n".to_vec();
+        assert!(read_hdr(&mut bytes.as_slice()).is_err());
+
+        for (width, height) in [(0, 1), (1, 0), (MAX_DIMENSION + 1, 1), (1, usize::MAX)] {
+            let mut bytes = header(width, height);
+            bytes.extend_from_slice(&[0; 4]);
+            assert!(
+                read_hdr(&mut bytes.as_slice()).is_err(),
+                "{}x{}",
+                width,
+                height
+            );
+        }
+    }
+}
@@ -4,12 +4,20 @@ use crate::{
     vec3::{Point3, Vec3},
 };
 
+#[derive(Clone, Copy)]
 pub struct HitRecord<'a> {
     pub p: Point3,
+    /// Shading normal, facing the incoming ray
     pub normal: Vec3,
+    /// Outward normal of the underlying geometry
+    pub geometric_normal: Vec3,
     pub t: f64,
     pub front_face: bool,
     pub material: &'a dyn Material,
+    pub object_id: usize,
+    /// Surface coordinates, both in [0, 1]
+    pub u: f64,
+    pub v: f64,
 }
 
 impl<'a> HitRecord<'a> {
@@ -19,17 +27,43 @@ impl<'a> HitRecord<'a> {
         t: f64,
         front_face: bool,
         material: &'a dyn Material,
-    ) -> HitRecord {
+    ) -> HitRecord<'a> {
         HitRecord {
             p,
             normal: if front_face { normal } else { -normal },
+            geometric_normal: normal,
             t,
             front_face,
             material,
+            object_id: 0,
+            u: 0.0,
+            v: 0.0,
         }
     }
 }
 
 pub trait Hittable {
-    fn hit(&self, ray: &Ray, t_max: f64, t_min: f64) -> Option<HitRecord>;
+    fn hit(&self, ray: &Ray, t_max: f64, t_min: f64) -> Option<HitRecord<'_>>;
+
+    /// Whether anything lies along the ray between `t_min` and `t_max`, which unlike `hit` does
+    /// not need the closest intersection.
+    fn occluded(&self, ray: &Ray, t_max: f64, t_min: f64) -> bool {
+        self.hit(ray, t_max, t_min).is_some()
+    }
+
+    /// Fraction of the light going through the object along the ray between `t_min` and `t_max`,
+    /// 0 for opaque surfaces and an unbiased estimate for participating media.
+    fn transmittance(&self, ray: &Ray, t_max: f64, t_min: f64) -> f64 {
+        if self.occluded(ray, t_max, t_min) {
+            0.0
+        } else {
+            1.0
+        }
+    }
+
+    /// Point and outward normal of the surface at coordinates (u, v), for the objects whose
+    /// texture can be baked.
+    fn surface_point(&self, _u: f64, _v: f64) -> Option<(Point3, Vec3)> {
+        None
+    }
 }
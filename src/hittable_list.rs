@@ -1,4 +1,7 @@
-use crate::hittable::{HitRecord, Hittable};
+use crate::{
+    hittable::{HitRecord, Hittable},
+    stats,
+};
 
 pub struct HittableList {
     pub objects: Vec<Box<dyn Hittable>>,
@@ -16,23 +19,41 @@ impl HittableList {
 }
 
 impl Hittable for HittableList {
-    fn hit(&self, ray: &crate::ray::Ray, t_max: f64, t_min: f64) -> Option<HitRecord> {
+    fn hit(&self, ray: &crate::ray::Ray, t_max: f64, t_min: f64) -> Option<HitRecord<'_>> {
         let mut temp_rec: Option<HitRecord> = None;
         let mut closest = f64::MAX;
 
-        for object in &self.objects {
+        for (index, object) in self.objects.iter().enumerate() {
+            stats::count_intersection_test();
             let hit_value = object.hit(ray, t_max, t_min);
-            match hit_value {
-                Some(record) => {
-                    if record.t < closest {
-                        closest = record.t;
-                        temp_rec = Some(record);
-                    }
+            if let Some(mut record) = hit_value {
+                if record.t < closest {
+                    closest = record.t;
+                    record.object_id = index;
+                    temp_rec = Some(record);
                 }
-                None => {}
             }
         }
 
         temp_rec
     }
+
+    fn occluded(&self, ray: &crate::ray::Ray, t_max: f64, t_min: f64) -> bool {
+        self.objects.iter().any(|object| {
+            stats::count_intersection_test();
+            object.occluded(ray, t_max, t_min)
+        })
+    }
+
+    fn transmittance(&self, ray: &crate::ray::Ray, t_max: f64, t_min: f64) -> f64 {
+        let mut transmittance = 1.0;
+        for object in &self.objects {
+            stats::count_intersection_test();
+            transmittance *= object.transmittance(ray, t_max, t_min);
+            if transmittance == 0.0 {
+                break;
+            }
+        }
+        transmittance
+    }
 }
@@ -0,0 +1,33 @@
+use std::{
+    fs::File,
+    io::{self, BufReader},
+    path::Path,
+};
+
+use crate::{color::Color, hdr, pfm};
+
+/// Floating point RGB image, rows starting from the top.
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+}
+
+impl Image {
+    /// Loads a Radiance `.hdr` or a `.pfm` image.
+    pub fn load(path: &str) -> io::Result<Image> {
+        let mut input = BufReader::new(File::open(path)?);
+        match Path::new(path).extension().and_then(|e| e.to_str()) {
+            Some("hdr") => hdr::read_hdr(&mut input),
+            Some("pfm") => pfm::read_pfm(&mut input),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{}: unsupported image format", path),
+            )),
+        }
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+}
@@ -0,0 +1,449 @@
+use crate::{
+    ambient_occlusion,
+    color::Color,
+    hittable::{HitRecord, Hittable},
+    ray::Ray,
+    scene::Scene,
+    stats,
+    vec3::Vec3,
+};
+
+/// Number of intersection tests shown in red by the intersection count heatmap.
+const HEATMAP_RANGE: f64 = 20000.0;
+/// Distance up to which geometry occludes by default, in scene units.
+pub const DEFAULT_AO_DISTANCE: f64 = 1.0;
+
+/// How the color of a camera ray is computed. Ambient occlusion is a quick preview of the geometry,
+/// everything else but the path tracer a debugging view of the first hit or of the cost of the
+/// path.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Integrator {
+    PathTracer,
+    AmbientOcclusion { max_distance: f64 },
+    ShadingNormal,
+    GeometricNormal,
+    FrontFace,
+    Depth,
+    Uv,
+    PathDepth,
+    IntersectionCount,
+}
+
+impl Integrator {
+    pub const ALL: [Integrator; 9] = [
+        Integrator::PathTracer,
+        Integrator::AmbientOcclusion {
+            max_distance: DEFAULT_AO_DISTANCE,
+        },
+        Integrator::ShadingNormal,
+        Integrator::GeometricNormal,
+        Integrator::FrontFace,
+        Integrator::Depth,
+        Integrator::Uv,
+        Integrator::PathDepth,
+        Integrator::IntersectionCount,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Integrator::PathTracer => "path",
+            Integrator::AmbientOcclusion { .. } => "ao",
+            Integrator::ShadingNormal => "shading-normal",
+            Integrator::GeometricNormal => "geometric-normal",
+            Integrator::FrontFace => "front-face",
+            Integrator::Depth => "depth",
+            Integrator::Uv => "uv",
+            Integrator::PathDepth => "path-depth",
+            Integrator::IntersectionCount => "intersections",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Integrator> {
+        Integrator::ALL
+            .into_iter()
+            .find(|integrator| integrator.name() == name)
+    }
+
+    /// Returns the color of a camera ray along with its first hit.
+    pub fn sample<'a>(
+        &self,
+        ray: &Ray,
+        scene: &'a Scene,
+        max_depth: i32,
+    ) -> (Color, Option<HitRecord<'a>>) {
+        if max_depth <= 0 {
+            return (Color::new(0.0, 0.0, 0.0), None);
+        }
+
+        let tests_before = stats::intersection_tests();
+        let rec = scene.world.hit(ray, f64::INFINITY, 0.001);
+
+        let color = match (self, rec) {
+            (Integrator::PathTracer, _) => shade(ray, rec, scene, max_depth, None),
+            (Integrator::IntersectionCount, _) => {
+                shade(ray, rec, scene, max_depth, None);
+                let tests = (stats::intersection_tests() - tests_before) as f64;
+                heatmap((1.0 + tests).ln() / (1.0 + HEATMAP_RANGE).ln())
+            }
+            (Integrator::AmbientOcclusion { .. }, None) => Color::new(1.0, 1.0, 1.0),
+            (Integrator::AmbientOcclusion { max_distance }, Some(record)) => {
+                // A single ray per sample, the samples per pixel averaging them
+                if ambient_occlusion::occluded(&scene.world, record.p, record.normal, *max_distance)
+                {
+                    Color::new(0.0, 0.0, 0.0)
+                } else {
+                    Color::new(1.0, 1.0, 1.0)
+                }
+            }
+            (_, None) => Color::new(0.0, 0.0, 0.0),
+            (Integrator::ShadingNormal, Some(record)) => normal_color(record.normal),
+            (Integrator::GeometricNormal, Some(record)) => normal_color(record.geometric_normal),
+            (Integrator::FrontFace, Some(record)) => {
+                if record.front_face {
+                    Color::new(0.0, 1.0, 0.0)
+                } else {
+                    Color::new(1.0, 0.0, 0.0)
+                }
+            }
+            (Integrator::Depth, Some(record)) => {
+                let value = 1.0 / (1.0 + record.t * ray.direction.length());
+                Color::new(value, value, value)
+            }
+            (Integrator::Uv, Some(record)) => Color::new(record.u, record.v, 0.0),
+            (Integrator::PathDepth, Some(record)) => {
+                heatmap(path_depth(ray, record, scene, max_depth) as f64 / max_depth as f64)
+            }
+        };
+
+        (color, rec)
+    }
+}
+
+fn normal_color(normal: Vec3) -> Color {
+    0.5 * (normal + Vec3::new(1.0, 1.0, 1.0))
+}
+
+/// Maps a value of [0, 1] from blue to green, yellow and red.
+fn heatmap(value: f64) -> Color {
+    let t = value.clamp(0.0, 1.0) * 3.0;
+    if t < 1.0 {
+        Color::new(0.0, t, 1.0 - t)
+    } else if t < 2.0 {
+        Color::new(t - 1.0, 1.0, 0.0)
+    } else {
+        Color::new(1.0, 3.0 - t, 0.0)
+    }
+}
+
+/// Number of times a path scatters before leaving the scene, being absorbed or reaching the
+/// maximum depth.
+fn path_depth(ray: &Ray, record: HitRecord, scene: &Scene, max_depth: i32) -> i32 {
+    let (mut ray, mut record) = (Ray::new(ray.origin, ray.direction), record);
+    let mut depth = 0;
+
+    while depth < max_depth {
+        let (_, scattered, success) = record.material.scatter(&ray, record);
+        if !success {
+            break;
+        }
+        depth += 1;
+
+        match scene.world.hit(&scattered, f64::INFINITY, 0.001) {
+            Some(next) => record = next,
+            None => break,
+        }
+        ray = scattered;
+    }
+
+    depth
+}
+
+/// `bsdf_pdf` is the density with which the previous bounce sampled the direction of `ray`,
+/// None for camera rays and perfectly specular bounces.
+fn trace(ray: &Ray, scene: &Scene, depth: i32, bsdf_pdf: Option<f64>) -> Color {
+    if depth <= 0 {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+
+    let rec = scene.world.hit(ray, f64::INFINITY, 0.001);
+    shade(ray, rec, scene, depth, bsdf_pdf)
+}
+
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let squared = pdf * pdf;
+    if squared == 0.0 {
+        return 0.0;
+    }
+    squared / (squared + other_pdf * other_pdf)
+}
+
+/// Returns the radiance carried by a ray whose closest hit is already known, through the
+/// atmosphere of the scene.
+fn shade(
+    ray: &Ray,
+    rec: Option<HitRecord>,
+    scene: &Scene,
+    depth: i32,
+    bsdf_pdf: Option<f64>,
+) -> Color {
+    let t_max = rec.as_ref().map(|record| record.t);
+    let mut color = shade_surface(ray, rec, scene, depth, bsdf_pdf);
+    if let Some(t) = t_max {
+        // Absorbed by the glass or liquid the ray travels through
+        color = ray.media.transmittance(t * ray.direction.length()) * color;
+    }
+
+    match &scene.atmosphere {
+        Some(atmosphere) => {
+            let (transmittance, inscattered) =
+                atmosphere.march(ray, t_max, &scene.world, scene.environment.as_ref());
+            transmittance * color + inscattered
+        }
+        None => color,
+    }
+}
+
+/// Radiance leaving the hit towards the ray, or arriving from the environment on a miss.
+///
+/// The environment is reached both by sampling it from every non-specular hit and by scattering,
+/// the two estimates being combined with multiple importance sampling.
+fn shade_surface(
+    ray: &Ray,
+    rec: Option<HitRecord>,
+    scene: &Scene,
+    depth: i32,
+    bsdf_pdf: Option<f64>,
+) -> Color {
+    let record = match rec {
+        Some(record) => record,
+        None => {
+            let direction = ray.direction.unit_vector();
+            let radiance = scene.environment.radiance(direction);
+
+            return match bsdf_pdf {
+                Some(pdf) => power_heuristic(pdf, scene.environment.pdf(direction)) * radiance,
+                None => radiance,
+            };
+        }
+    };
+
+    let mut color = record.material.emitted(&record);
+
+    if let Some((direction, radiance, light_pdf)) = scene.environment.sample() {
+        if let Some((f, pdf)) = record.material.evaluate(ray, &record, direction) {
+            let shadow_ray = Ray::new(record.p, direction);
+            // Media in the way dim the light rather than blocking it
+            let transmittance = if pdf > 0.0 {
+                scene.world.transmittance(&shadow_ray, f64::INFINITY, 0.001)
+            } else {
+                0.0
+            };
+            if transmittance > 0.0 {
+                let fog = match &scene.atmosphere {
+                    Some(atmosphere) => atmosphere.sky_transmittance(record.p, direction),
+                    None => Color::new(1.0, 1.0, 1.0),
+                };
+                color = color
+                    + transmittance * power_heuristic(light_pdf, pdf) / light_pdf
+                        * fog
+                        * f
+                        * radiance;
+            }
+        }
+    }
+
+    let (attenuation, scattered, success) = record.material.scatter(ray, record);
+    if success {
+        let media = record.material.media(ray, &record, &scattered);
+        let scattered = scattered.with_media(media);
+        let pdf = record
+            .material
+            .evaluate(ray, &record, scattered.direction)
+            .map(|(_, pdf)| pdf);
+        let depth = if record.material.counts_as_bounce() {
+            depth - 1
+        } else {
+            depth
+        };
+        color = color + attenuation * trace(&scattered, scene, depth, pdf);
+    }
+
+    color
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        aperture::ApertureShape,
+        camera::{Projection, View},
+        distortion::Distortion,
+        environment::SkyGradient,
+        hittable_list::HittableList,
+        material::Lambertian,
+        media_stack::{Interior, MediaStack},
+        random,
+        sphere::Sphere,
+        vec3::Point3,
+    };
+    use approx::*;
+
+    macro_rules! assert_vec3_equal {
+        ($expected:expr, $actual:expr) => {
+            let tolerance = 0.0001;
+            assert_relative_eq!($expected.x, $actual.x, epsilon = tolerance);
+            assert_relative_eq!($expected.y, $actual.y, epsilon = tolerance);
+            assert_relative_eq!($expected.z, $actual.z, epsilon = tolerance);
+        };
+    }
+
+    fn sphere_scene() -> Scene {
+        let mut world = HittableList::new();
+        world.add(Sphere::new(
+            Point3::new(0.0, 0.0, -2.0),
+            1.0,
+            Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+        ));
+
+        let view = View {
+            lookfrom: Point3::new(0.0, 0.0, 0.0),
+            lookat: Point3::new(0.0, 0.0, -1.0),
+            vup: Vec3::new(0.0, 1.0, 0.0),
+            vfov: 90.0,
+            aperture: 0.0,
+            aperture_shape: ApertureShape::Circular,
+            focus_dist: 1.0,
+            lens: None,
+            exit_pupil_bounds: false,
+            distortion: Distortion::default(),
+        };
+
+        Scene {
+            world,
+            camera: view.camera(Projection::Perspective, 1.0),
+            view,
+            environment: Box::new(SkyGradient),
+            atmosphere: None,
+        }
+    }
+
+    #[test]
+    fn parse_names() {
+        for integrator in Integrator::ALL {
+            assert_eq!(Some(integrator), Integrator::parse(integrator.name()));
+        }
+        assert_eq!(None, Integrator::parse("whitted"));
+    }
+
+    #[test]
+    fn first_hit_views() {
+        let scene = sphere_scene();
+        let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+
+        let (normal, record) = Integrator::ShadingNormal.sample(&ray, &scene, 10);
+        assert!(record.is_some());
+        assert_vec3_equal!(Color::new(0.5, 0.5, 1.0), normal);
+
+        let (front, _) = Integrator::FrontFace.sample(&ray, &scene, 10);
+        assert_vec3_equal!(Color::new(0.0, 1.0, 0.0), front);
+
+        let (depth, _) = Integrator::Depth.sample(&ray, &scene, 10);
+        assert_vec3_equal!(Color::new(0.5, 0.5, 0.5), depth);
+    }
+
+    #[test]
+    fn back_faces() {
+        let scene = sphere_scene();
+        let ray = Ray::new(Point3::new(0.0, 0.0, -2.0), Vec3::new(0.0, 0.0, -1.0));
+
+        let (front, _) = Integrator::FrontFace.sample(&ray, &scene, 10);
+        assert_vec3_equal!(Color::new(1.0, 0.0, 0.0), front);
+
+        // The shading normal faces the ray, the geometric one points outwards
+        let (shading, _) = Integrator::ShadingNormal.sample(&ray, &scene, 10);
+        let (geometric, _) = Integrator::GeometricNormal.sample(&ray, &scene, 10);
+        assert_vec3_equal!(Color::new(0.5, 0.5, 1.0), shading);
+        assert_vec3_equal!(Color::new(0.5, 0.5, 0.0), geometric);
+    }
+
+    #[test]
+    fn absorption_inside_media() {
+        let scene = sphere_scene();
+        let ray = || Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let water = Interior {
+            object_id: 7,
+            ir: 1.33,
+            priority: 0,
+            absorption: Color::new(0.5, 1.0, 2.0),
+        };
+
+        random::seed(50);
+        let (clear, _) = Integrator::PathTracer.sample(&ray(), &scene, 10);
+        random::seed(50);
+        let inside = ray().with_media(MediaStack::default().push(water));
+        let (absorbed, _) = Integrator::PathTracer.sample(&inside, &scene, 10);
+
+        // The light from the sphere, a unit away, is absorbed along the way
+        let expected = Color::new(
+            clear.x * (-0.5_f64).exp(),
+            clear.y * (-1.0_f64).exp(),
+            clear.z * (-2.0_f64).exp(),
+        );
+        assert_vec3_equal!(expected, absorbed);
+    }
+
+    #[test]
+    fn misses_are_black() {
+        let scene = sphere_scene();
+        let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+
+        let (color, record) = Integrator::Uv.sample(&ray, &scene, 10);
+        assert!(record.is_none());
+        assert_vec3_equal!(Color::new(0.0, 0.0, 0.0), color);
+
+        let (color, _) = Integrator::PathTracer.sample(&ray, &scene, 10);
+        assert_vec3_equal!(Color::new(0.5, 0.7, 1.0), color);
+    }
+
+    #[test]
+    fn ambient_occlusion_view() {
+        let scene = sphere_scene();
+        let hit = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let miss = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        let integrator = Integrator::AmbientOcclusion { max_distance: 1.0 };
+
+        // Nothing occludes the outside of a lone convex sphere
+        let (color, _) = integrator.sample(&hit, &scene, 10);
+        assert_vec3_equal!(Color::new(1.0, 1.0, 1.0), color);
+        let (color, _) = integrator.sample(&miss, &scene, 10);
+        assert_vec3_equal!(Color::new(1.0, 1.0, 1.0), color);
+
+        // While its inside is closed
+        let inside = Ray::new(Point3::new(0.0, 0.0, -2.0), Vec3::new(0.0, 0.0, -1.0));
+        let integrator = Integrator::AmbientOcclusion { max_distance: 5.0 };
+        let (color, _) = integrator.sample(&inside, &scene, 10);
+        assert_vec3_equal!(Color::new(0.0, 0.0, 0.0), color);
+    }
+
+    #[test]
+    fn intersection_heatmap() {
+        let scene = sphere_scene();
+        let miss = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+
+        // A single test is close to the cold end of the ramp
+        let (color, _) = Integrator::IntersectionCount.sample(&miss, &scene, 10);
+        assert!(color.z > 0.7 && color.x == 0.0);
+
+        // Hitting the sphere costs more tests, along with the bounces
+        let hit = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let (hot, _) = Integrator::IntersectionCount.sample(&hit, &scene, 10);
+        assert!(hot.z < color.z);
+    }
+
+    #[test]
+    fn heatmap_ramp() {
+        assert_vec3_equal!(Color::new(0.0, 0.0, 1.0), heatmap(0.0));
+        assert_vec3_equal!(Color::new(1.0, 1.0, 0.0), heatmap(2.0 / 3.0));
+        assert_vec3_equal!(Color::new(1.0, 0.0, 0.0), heatmap(1.5));
+    }
+}
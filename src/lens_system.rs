@@ -0,0 +1,778 @@
+use std::{fs, io};
+
+use crate::{
+    camera::Camera,
+    color::Color,
+    physical_camera::Photographic,
+    random,
+    ray::Ray,
+    vec3::{Point3, Vec3},
+};
+
+/// Double Gauss lens of US patent 2,673,491 (Tronnier), scaled to 50 mm, from Modern Lens Design
+/// p. 312. The patent gives no dispersion, the Abbe numbers are those of common glasses with
+/// these indices.
+const DOUBLE_GAUSS: &str = "# radius thickness ior aperture abbe
+29.475   3.76   1.67   25.2  47.2
+84.83    0.12   1      25.2
+19.275   4.025  1.67   23    47.2
+40.77    3.275  1.699  23    30.1
+12.75    5.705  1      18
+0        4.5    0      17.1
+-14.495  1.18   1.603  17    38.0
+40.77    6.065  1.658  20    57.3
+-20.385  0.19   1      20
+437.065  3.22   1.717  20    47.9
+-39.73   0      1      20";
+
+/// Wavelengths in nanometers of the Fraunhofer d, F and C lines defining the Abbe number.
+const D_LINE: f64 = 587.6;
+const F_LINE: f64 = 486.1;
+const C_LINE: f64 = 656.3;
+
+/// Wavelengths traced for the red, green and blue channels, in nanometers.
+const CHANNEL_WAVELENGTHS: [f64; 3] = [610.0, 550.0, 465.0];
+
+/// Rings of the film over which the exit pupil is bounded, and samples traced per ring.
+const EXIT_PUPIL_RINGS: usize = 32;
+const EXIT_PUPIL_SAMPLES: usize = 4096;
+
+/// Surface of a lens prescription, lengths being in millimeters.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LensElement {
+    /// Positive when the center of the sphere is on the film side, 0 for the aperture stop
+    pub curvature_radius: f64,
+    /// Distance to the next surface towards the film
+    pub thickness: f64,
+    /// Refractive index at the d line of the medium behind the surface, 1 for air
+    pub ior: f64,
+    /// Dispersion of that medium, None for none
+    pub abbe_number: Option<f64>,
+    pub aperture_radius: f64,
+}
+
+impl LensElement {
+    fn is_stop(&self) -> bool {
+        self.curvature_radius == 0.0
+    }
+
+    /// Index of the medium behind the surface, following Cauchy's equation fitted to the Abbe
+    /// number.
+    fn ior_at(&self, wavelength: Option<f64>) -> f64 {
+        match (wavelength, self.abbe_number) {
+            (Some(wavelength), Some(abbe_number)) => {
+                let b = (self.ior - 1.0)
+                    / abbe_number
+                    / (1.0 / (F_LINE * F_LINE) - 1.0 / (C_LINE * C_LINE));
+                let a = self.ior - b / (D_LINE * D_LINE);
+                a + b / (wavelength * wavelength)
+            }
+            _ => self.ior,
+        }
+    }
+}
+
+/// Spherical surfaces ordered from the scene to the film, the thickness of the last one being the
+/// distance to the film. In lens space the film is at z = 0 and the scene towards -z.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LensSystem {
+    pub elements: Vec<LensElement>,
+}
+
+impl LensSystem {
+    pub fn preset(name: &str) -> Option<LensSystem> {
+        match name {
+            "double-gauss" => LensSystem::parse(DOUBLE_GAUSS).ok(),
+            _ => None,
+        }
+    }
+
+    /// Loads a preset by name, or a prescription file otherwise.
+    pub fn load(name: &str) -> io::Result<LensSystem> {
+        match LensSystem::preset(name) {
+            Some(lens) => Ok(lens),
+            None => LensSystem::parse(&fs::read_to_string(name)?)
+                .map_err(|message| io::Error::new(io::ErrorKind::InvalidData, message)),
+        }
+    }
+
+    /// Reads lines of `radius thickness ior aperture [abbe]` as in pbrt, the aperture being a
+    /// diameter and an index of 0 meaning air. Empty lines and `#` comments are skipped.
+    pub fn parse(text: &str) -> Result<LensSystem, String> {
+        let mut elements = Vec::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = || {
+                format!(
+                    "line {}: expected 'radius thickness ior aperture [abbe]'",
+                    number + 1
+                )
+            };
+            let values: Vec<f64> = line
+                .split_whitespace()
+                .map(str::parse)
+                .collect::<Result<_, _>>()
+                .map_err(|_| invalid())?;
+            let (radius, thickness, ior, aperture, abbe_number) = match values.as_slice() {
+                [radius, thickness, ior, aperture] => (*radius, *thickness, *ior, *aperture, None),
+                [radius, thickness, ior, aperture, abbe] => {
+                    (*radius, *thickness, *ior, *aperture, Some(*abbe))
+                }
+                _ => return Err(invalid()),
+            };
+            if thickness < 0.0 || aperture <= 0.0 || abbe_number.is_some_and(|abbe| abbe <= 0.0) {
+                return Err(invalid());
+            }
+
+            elements.push(LensElement {
+                curvature_radius: radius,
+                thickness,
+                ior: if ior == 0.0 { 1.0 } else { ior },
+                abbe_number,
+                aperture_radius: aperture / 2.0,
+            });
+        }
+
+        if elements.is_empty() {
+            return Err("the lens has no element".to_string());
+        }
+        Ok(LensSystem { elements })
+    }
+
+    fn length(&self) -> f64 {
+        self.elements.iter().map(|element| element.thickness).sum()
+    }
+
+    fn rear(&self) -> &LensElement {
+        self.elements.last().unwrap()
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.elements
+            .iter()
+            .any(|element| element.abbe_number.is_some() && element.ior != 1.0)
+    }
+
+    fn scaled(&self, factor: f64) -> LensSystem {
+        let elements = self
+            .elements
+            .iter()
+            .map(|element| LensElement {
+                curvature_radius: element.curvature_radius * factor,
+                thickness: element.thickness * factor,
+                aperture_radius: element.aperture_radius * factor,
+                ..element.clone()
+            })
+            .collect();
+        LensSystem { elements }
+    }
+
+    /// Index of the medium in front of surface `index`, on the scene side.
+    fn ior_before(&self, index: usize, wavelength: Option<f64>) -> f64 {
+        match index {
+            0 => 1.0,
+            _ => self.elements[index - 1].ior_at(wavelength),
+        }
+    }
+
+    /// Crosses surface `index` at depth `z`, None when the ray misses its opening or is totally
+    /// reflected.
+    fn cross(
+        &self,
+        index: usize,
+        z: f64,
+        ray: &Ray,
+        eta_incident: f64,
+        eta_transmitted: f64,
+    ) -> Option<Ray> {
+        let element = &self.elements[index];
+        let (t, normal) = if element.is_stop() {
+            ((z - ray.origin.z) / ray.direction.z, None)
+        } else {
+            let (t, normal) = intersect_surface(element.curvature_radius, z, ray)?;
+            (t, Some(normal))
+        };
+        if t.is_nan() || t <= 0.0 {
+            return None;
+        }
+
+        let p = ray.at(t);
+        if p.x * p.x + p.y * p.y > element.aperture_radius * element.aperture_radius {
+            return None;
+        }
+        let direction = match normal {
+            Some(normal) => refract(
+                ray.direction.unit_vector(),
+                normal,
+                eta_incident / eta_transmitted,
+            )?,
+            None => ray.direction,
+        };
+        Some(Ray::new(p, direction))
+    }
+
+    /// Follows a ray leaving the film through every surface to the scene.
+    fn trace_from_film(&self, ray: &Ray, wavelength: Option<f64>) -> Option<Ray> {
+        let mut ray = Ray::new(ray.origin, ray.direction);
+        let mut z = 0.0;
+        for index in (0..self.elements.len()).rev() {
+            z -= self.elements[index].thickness;
+            let eta_incident = self.elements[index].ior_at(wavelength);
+            let eta_transmitted = self.ior_before(index, wavelength);
+            ray = self.cross(index, z, &ray, eta_incident, eta_transmitted)?;
+        }
+        Some(ray)
+    }
+
+    /// Follows a ray coming from the scene through the surfaces before `end`.
+    fn trace_from_scene(&self, ray: &Ray, end: usize) -> Option<Ray> {
+        let mut ray = Ray::new(ray.origin, ray.direction);
+        let mut z = -self.length();
+        for index in 0..end {
+            let eta_incident = self.ior_before(index, None);
+            let eta_transmitted = self.elements[index].ior_at(None);
+            ray = self.cross(index, z, &ray, eta_incident, eta_transmitted)?;
+            z += self.elements[index].thickness;
+        }
+        Some(ray)
+    }
+
+    /// Depths of the principal plane and focal point of the thick lens approximating the system,
+    /// for light coming from the scene and from the film.
+    fn cardinal_points(&self) -> Option<[(f64, f64); 2]> {
+        let height = 0.01
+            * self
+                .rear()
+                .aperture_radius
+                .min(self.elements[0].aperture_radius);
+        let from_scene = Ray::new(
+            Point3::new(height, 0.0, -self.length() - 1.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        );
+        let from_film = Ray::new(Point3::new(height, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+
+        let film_side = cardinal_points(
+            &from_scene,
+            &self.trace_from_scene(&from_scene, self.elements.len())?,
+        );
+        let scene_side = cardinal_points(&from_film, &self.trace_from_film(&from_film, None)?);
+        Some([scene_side, film_side])
+    }
+
+    pub fn focal_length(&self) -> Option<f64> {
+        let [_, (principal, focal)] = self.cardinal_points()?;
+        Some(focal - principal)
+    }
+
+    /// Distance from the rear surface to the film bringing the plane `distance` away from the
+    /// film in focus, the closest focus when it is nearer than the lens allows.
+    fn focused_rear_distance(&self, distance: f64) -> Option<f64> {
+        let [(principal, _), (principal_rear, focal_rear)] = self.cardinal_points()?;
+        let focal_length = focal_rear - principal_rear;
+
+        // Gaussian lens equation with the object and image distances adding up to `distance`
+        // minus the space between the principal planes
+        let image_distance = if distance.is_infinite() {
+            focal_length
+        } else {
+            let span = distance - (principal_rear - principal);
+            let c = (span * span - 4.0 * focal_length * span).max(0.0);
+            (span - c.sqrt()) / 2.0
+        };
+        Some(self.rear().thickness + principal_rear + image_distance)
+    }
+
+    /// Height at which a ray parallel to the axis at `height` crosses the aperture stop.
+    fn stop_height(&self, height: f64) -> Option<(usize, f64)> {
+        let stop = self.elements.iter().position(LensElement::is_stop)?;
+        let ray = Ray::new(
+            Point3::new(height, 0.0, -self.length() - 1.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        );
+        let ray = self.trace_from_scene(&ray, stop)?;
+        let z = -self.length()
+            + self.elements[..stop]
+                .iter()
+                .map(|element| element.thickness)
+                .sum::<f64>();
+        let p = ray.at((z - ray.origin.z) / ray.direction.z);
+        Some((stop, p.x))
+    }
+
+    /// Opens or closes the stop to get an entrance pupil of `radius`, as far as the lens design
+    /// allows.
+    fn with_entrance_pupil(mut self, radius: f64) -> LensSystem {
+        let height = 0.01 * self.elements[0].aperture_radius;
+        if let Some((stop, stop_height)) = self.stop_height(height) {
+            let stop = &mut self.elements[stop];
+            let stop_radius = radius * (stop_height / height).abs();
+            stop.aperture_radius = stop.aperture_radius.min(stop_radius);
+        }
+        self
+    }
+}
+
+/// Principal plane and focal point depths from a ray parallel to the axis and the ray leaving the
+/// lens for it.
+fn cardinal_points(incoming: &Ray, outgoing: &Ray) -> (f64, f64) {
+    let focal = outgoing.at(-outgoing.origin.x / outgoing.direction.x).z;
+    let principal = outgoing
+        .at((incoming.origin.x - outgoing.origin.x) / outgoing.direction.x)
+        .z;
+    (principal, focal)
+}
+
+/// Intersects the spherical surface whose vertex is at depth `z`, giving the normal facing the
+/// ray.
+fn intersect_surface(radius: f64, z: f64, ray: &Ray) -> Option<(f64, Vec3)> {
+    let oc = ray.origin - Point3::new(0.0, 0.0, z + radius);
+    let a = ray.direction.length_squared();
+    let half_b = oc.dot(ray.direction);
+    let c = oc.length_squared() - radius * radius;
+    let discriminant = half_b * half_b - a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    // The surface is the cap of the sphere closest to its vertex
+    let root = discriminant.sqrt();
+    let (t0, t1) = ((-half_b - root) / a, (-half_b + root) / a);
+    let t = if (ray.direction.z > 0.0) != (radius < 0.0) {
+        t0
+    } else {
+        t1
+    };
+    if t < 0.0 {
+        return None;
+    }
+
+    let normal = (oc + t * ray.direction).unit_vector();
+    if normal.dot(ray.direction) > 0.0 {
+        Some((t, -normal))
+    } else {
+        Some((t, normal))
+    }
+}
+
+/// Refracts the unit `direction` through a surface of normal `normal` facing it, None on total
+/// internal reflection.
+fn refract(direction: Vec3, normal: Vec3, eta: f64) -> Option<Vec3> {
+    let cos_incident = -direction.dot(normal);
+    let sin2_transmitted = eta * eta * (1.0 - cos_incident * cos_incident).max(0.0);
+    if sin2_transmitted >= 1.0 {
+        return None;
+    }
+    let cos_transmitted = (1.0 - sin2_transmitted).sqrt();
+    Some(eta * direction + (eta * cos_incident - cos_transmitted) * normal)
+}
+
+/// Van der Corput sequence in `base`.
+fn radical_inverse(base: usize, mut index: usize) -> f64 {
+    let mut inverse = 0.0;
+    let mut digit = 1.0 / base as f64;
+    while index > 0 {
+        inverse += (index % base) as f64 * digit;
+        index /= base;
+        digit /= base as f64;
+    }
+    inverse
+}
+
+/// Rectangle of the plane of the rear surface, as min and max corners.
+#[derive(Clone, Copy, Debug)]
+struct Bounds {
+    min: (f64, f64),
+    max: (f64, f64),
+}
+
+impl Bounds {
+    fn square(half_side: f64) -> Bounds {
+        Bounds {
+            min: (-half_side, -half_side),
+            max: (half_side, half_side),
+        }
+    }
+
+    fn area(&self) -> f64 {
+        (self.max.0 - self.min.0) * (self.max.1 - self.min.1)
+    }
+
+    fn lerp(&self, u0: f64, u1: f64) -> (f64, f64) {
+        (
+            self.min.0 + u0 * (self.max.0 - self.min.0),
+            self.min.1 + u1 * (self.max.1 - self.min.1),
+        )
+    }
+}
+
+/// Camera tracing its rays through a lens prescription, which gives the vignetting, distortion,
+/// focus breathing and chromatic aberrations of the real lens. The prescription is scaled to the
+/// focal length of the settings and its stop set for their f-number, the film spanning the
+/// sensor.
+///
+/// Rays head for the rear surface, or only for the bounds of the exit pupil seen from their ring
+/// of the film when they are computed, which wastes fewer rays on the blocked ones.
+pub struct LensCamera {
+    lens: LensSystem,
+    film_width: f64,
+    film_height: f64,
+    exit_pupil: Vec<Bounds>,
+    axial_area: f64,
+    dispersive: bool,
+    origin: Point3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    exposure: f64,
+}
+
+impl LensCamera {
+    pub fn new(
+        lookfrom: Point3,
+        lookat: Point3,
+        vup: Vec3,
+        lens: &LensSystem,
+        settings: &Photographic,
+        aspect_ratio: f64,
+        focus_dist: f64,
+    ) -> LensCamera {
+        let focus_dist = settings.focus_distance.unwrap_or(focus_dist);
+        let (film_width, film_height) = settings.film_size(aspect_ratio);
+
+        let mut lens = match lens.focal_length() {
+            Some(focal_length) => lens.scaled(settings.focal_length / focal_length),
+            None => lens.clone(),
+        }
+        .with_entrance_pupil(settings.focal_length / settings.f_number / 2.0);
+        // The film is where the plane in focus is imaged, distances being in millimeters
+        if let Some(distance) = lens.focused_rear_distance(focus_dist * 1000.0) {
+            lens.elements.last_mut().unwrap().thickness = distance;
+        }
+
+        let w = (lookfrom - lookat).unit_vector();
+        let u = (vup.cross_product(w)).unit_vector();
+        let v = w.cross_product(u);
+
+        let mut camera = LensCamera {
+            dispersive: lens.is_dispersive(),
+            exit_pupil: vec![Bounds::square(lens.rear().aperture_radius)],
+            axial_area: 0.0,
+            lens,
+            film_width,
+            film_height,
+            origin: lookfrom,
+            u,
+            v,
+            w,
+            exposure: settings.exposure(),
+        };
+        camera.axial_area = camera.axial_area();
+        camera
+    }
+
+    /// Bounds the exit pupil over rings of the film instead of sampling the whole rear surface.
+    pub fn with_exit_pupil_bounds(self) -> LensCamera {
+        let film_radius = self.film_width.hypot(self.film_height) / 2.0;
+        let exit_pupil = (0..EXIT_PUPIL_RINGS)
+            .map(|ring| {
+                self.bound_exit_pupil(
+                    ring as f64 / EXIT_PUPIL_RINGS as f64 * film_radius,
+                    (ring + 1) as f64 / EXIT_PUPIL_RINGS as f64 * film_radius,
+                )
+            })
+            .collect();
+        LensCamera { exit_pupil, ..self }
+    }
+
+    fn rear_z(&self) -> f64 {
+        -self.lens.rear().thickness
+    }
+
+    fn passes(&self, film: Point3, rear: (f64, f64)) -> bool {
+        let direction = Point3::new(rear.0, rear.1, self.rear_z()) - film;
+        self.lens
+            .trace_from_film(&Ray::new(film, direction), None)
+            .is_some()
+    }
+
+    /// Area of the rear surface through which light reaches the center of the film, which gets
+    /// a weight of one.
+    fn axial_area(&self) -> f64 {
+        let bounds = self.exit_pupil[0];
+        let n = 64;
+        let passing = (0..n * n)
+            .filter(|i| {
+                let (u0, u1) = ((i % n) as f64 + 0.5, (i / n) as f64 + 0.5);
+                let rear = bounds.lerp(u0 / n as f64, u1 / n as f64);
+                self.passes(Point3::new(0.0, 0.0, 0.0), rear)
+            })
+            .count();
+        (bounds.area() * passing as f64 / (n * n) as f64).max(f64::MIN_POSITIVE)
+    }
+
+    /// Bounds of the points of the rear plane through which light goes from film points between
+    /// `start` and `end` along the x axis.
+    fn bound_exit_pupil(&self, start: f64, end: f64) -> Bounds {
+        let search = Bounds::square(1.5 * self.lens.rear().aperture_radius);
+        let mut bounds: Option<Bounds> = None;
+
+        for i in 0..EXIT_PUPIL_SAMPLES {
+            let x = start + (i as f64 + 0.5) / EXIT_PUPIL_SAMPLES as f64 * (end - start);
+            let rear = search.lerp(radical_inverse(2, i), radical_inverse(3, i));
+            if self.passes(Point3::new(x, 0.0, 0.0), rear) {
+                bounds = Some(match bounds {
+                    Some(bounds) => Bounds {
+                        min: (bounds.min.0.min(rear.0), bounds.min.1.min(rear.1)),
+                        max: (bounds.max.0.max(rear.0), bounds.max.1.max(rear.1)),
+                    },
+                    None => Bounds {
+                        min: rear,
+                        max: rear,
+                    },
+                });
+            }
+        }
+
+        // Widened by the spacing of the samples to not miss the edges of the pupil
+        let margin = 2.0 * (search.max.0 - search.min.0) / (EXIT_PUPIL_SAMPLES as f64).sqrt();
+        match bounds {
+            Some(bounds) => Bounds {
+                min: (bounds.min.0 - margin, bounds.min.1 - margin),
+                max: (bounds.max.0 + margin, bounds.max.1 + margin),
+            },
+            None => search,
+        }
+    }
+
+    /// Point of the rear plane towards which a ray leaves `film`, and the area it was sampled in.
+    fn sample_exit_pupil(&self, film: Point3) -> (Point3, f64) {
+        let radius = film.x.hypot(film.y);
+        let film_radius = self.film_width.hypot(self.film_height) / 2.0;
+        let ring = ((radius / film_radius * self.exit_pupil.len() as f64) as usize)
+            .min(self.exit_pupil.len() - 1);
+        let bounds = self.exit_pupil[ring];
+        let (x, y) = bounds.lerp(random::random_double(), random::random_double());
+
+        // The bounds are computed along the x axis, turned towards the film point
+        let (sin, cos) = if radius > 0.0 {
+            (film.y / radius, film.x / radius)
+        } else {
+            (0.0, 1.0)
+        };
+        (
+            Point3::new(cos * x - sin * y, sin * x + cos * y, self.rear_z()),
+            bounds.area(),
+        )
+    }
+}
+
+impl Camera for LensCamera {
+    fn get_ray(&self, s: f64, t: f64) -> Option<Ray> {
+        self.get_weighted_ray(s, t).map(|(ray, _)| ray)
+    }
+
+    fn get_weighted_ray(&self, s: f64, t: f64) -> Option<(Ray, Color)> {
+        // The lens flips the image on the film
+        let film = Point3::new(
+            -(s - 0.5) * self.film_width,
+            -(t - 0.5) * self.film_height,
+            0.0,
+        );
+        let (rear, area) = self.sample_exit_pupil(film);
+
+        // Dispersive lenses trace one channel per ray
+        let (wavelength, mut weight) = if self.dispersive {
+            let (channel, weight) = Color::random_channel();
+            (Some(CHANNEL_WAVELENGTHS[channel]), weight)
+        } else {
+            (None, Color::new(1.0, 1.0, 1.0))
+        };
+
+        let direction = (rear - film).unit_vector();
+        let ray = self
+            .lens
+            .trace_from_film(&Ray::new(film, direction), wavelength)?;
+        weight = direction.z.powi(4) * area / self.axial_area * weight;
+
+        let origin = ray.origin / 1000.0;
+        let direction = ray.direction;
+        Some((
+            Ray::new(
+                self.origin + origin.x * self.u + origin.y * self.v + origin.z * self.w,
+                direction.x * self.u + direction.y * self.v + direction.z * self.w,
+            ),
+            weight,
+        ))
+    }
+
+    fn exposure(&self) -> f64 {
+        self.exposure
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::*;
+
+    /// Biconvex singlet of about 100 mm with its stop behind it.
+    const SINGLET: &str = "100 5 1.5 40
+-100 2 1 40
+0 95 0 10";
+
+    #[test]
+    fn parse_prescription() {
+        let lens = LensSystem::preset("double-gauss").unwrap();
+        assert_eq!(11, lens.elements.len());
+        assert!(lens.elements[5].is_stop());
+        assert_relative_eq!(8.55, lens.elements[5].aperture_radius);
+        assert_eq!(1.0, lens.elements[5].ior);
+        assert_eq!(Some(30.1), lens.elements[3].abbe_number);
+
+        assert!(LensSystem::parse("10 2 1.5").is_err());
+        assert!(LensSystem::parse("# nothing").is_err());
+        assert_eq!(
+            Err("line 2: expected 'radius thickness ior aperture [abbe]'".to_string()),
+            LensSystem::parse("10 2 1.5 4\n10 2 glass 4")
+        );
+    }
+
+    #[test]
+    fn dispersion() {
+        let glass = LensElement {
+            curvature_radius: 10.0,
+            thickness: 1.0,
+            ior: 1.5168,
+            abbe_number: Some(64.17),
+            aperture_radius: 5.0,
+        };
+        assert_relative_eq!(1.5168, glass.ior_at(Some(D_LINE)), epsilon = 1e-12);
+        assert_relative_eq!(
+            (1.5168 - 1.0) / 64.17,
+            glass.ior_at(Some(F_LINE)) - glass.ior_at(Some(C_LINE)),
+            epsilon = 1e-12
+        );
+        assert!(glass.ior_at(Some(450.0)) > glass.ior_at(Some(650.0)));
+    }
+
+    #[test]
+    fn thick_lens_focus() {
+        // The lensmaker's equation gives about 100 mm for the singlet
+        let lens = LensSystem::parse(SINGLET).unwrap();
+        let focal_length = lens.focal_length().unwrap();
+        assert_relative_eq!(100.0, focal_length, epsilon = 2.0);
+
+        // Paraxial rays from a point 1 m away meet at the film once focused
+        let mut lens = lens;
+        lens.elements[2].thickness = lens.focused_rear_distance(1000.0).unwrap();
+        for height in [0.5, 1.0, -1.0] {
+            let ray = Ray::new(
+                Point3::new(0.0, 0.0, -1000.0),
+                Vec3::new(height, 0.0, 1000.0 - lens.length()),
+            );
+            let ray = lens.trace_from_scene(&ray, lens.elements.len()).unwrap();
+            let film = ray.at(-ray.origin.z / ray.direction.z);
+            assert!(film.x.abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn scaled_to_settings() {
+        let lens = LensSystem::preset("double-gauss").unwrap();
+        assert_relative_eq!(50.0, lens.focal_length().unwrap(), epsilon = 2.0);
+
+        let settings = Photographic {
+            focal_length: 85.0,
+            f_number: 4.0,
+            ..Default::default()
+        };
+        let camera = LensCamera::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            &lens,
+            &settings,
+            1.5,
+            10.0,
+        );
+        assert_relative_eq!(85.0, camera.lens.focal_length().unwrap(), epsilon = 1e-6);
+
+        // Rays through the center of the film leave along the axis, the image edge seeing about
+        // as far as a thin lens of the same focal length
+        random::seed(3);
+        let center = (0..100).find_map(|_| camera.get_ray(0.5, 0.5)).unwrap();
+        assert!(center.direction.unit_vector().z < -0.999);
+        let edge = (0..100).find_map(|_| camera.get_ray(1.0, 0.5)).unwrap();
+        assert_relative_eq!(
+            18.0 / 85.0,
+            edge.direction.x / -edge.direction.z,
+            epsilon = 0.02
+        );
+
+        // The corners get less light, at least by the cosine to the fourth falloff
+        let light = |s, t| {
+            (0..10000)
+                .filter_map(|_| camera.get_weighted_ray(s, t))
+                .map(|(_, weight)| (weight.x + weight.y + weight.z) / 3.0)
+                .sum::<f64>()
+                / 10000.0
+        };
+        assert_relative_eq!(1.0, light(0.5, 0.5), epsilon = 0.05);
+        assert!(light(1.0, 1.0) < 0.92);
+    }
+
+    #[test]
+    fn exit_pupil_bounds() {
+        let lens = LensSystem::preset("double-gauss").unwrap();
+        let settings = Photographic {
+            f_number: 2.8,
+            ..Default::default()
+        };
+        let camera = LensCamera::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            &lens,
+            &settings,
+            1.5,
+            5.0,
+        );
+        let bounded = LensCamera::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            &lens,
+            &settings,
+            1.5,
+            5.0,
+        )
+        .with_exit_pupil_bounds();
+
+        // Fewer rays are blocked within the bounds, with the same light reaching the film
+        random::seed(4);
+        let samples = 20000;
+        for (s, t) in [(0.5, 0.5), (0.9, 0.8)] {
+            let mut passing = [0, 0];
+            let mut light = [0.0, 0.0];
+            for (camera, index) in [(&camera, 0), (&bounded, 1)] {
+                for _ in 0..samples {
+                    if let Some((_, weight)) = camera.get_weighted_ray(s, t) {
+                        passing[index] += 1;
+                        light[index] += (weight.x + weight.y + weight.z) / 3.0;
+                    }
+                }
+            }
+            assert!(passing[1] > passing[0]);
+            assert_relative_eq!(
+                light[0] / samples as f64,
+                light[1] / samples as f64,
+                max_relative = 0.05
+            );
+        }
+    }
+}
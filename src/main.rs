@@ -1,138 +1,362 @@
+mod accumulation;
+mod ambient_occlusion;
+mod animation;
+mod aov;
+mod aperture;
+mod atmosphere;
 mod camera;
+mod cli;
 mod color;
+mod cuboid;
+mod cv_camera;
+mod denoise;
+mod distortion;
+mod distribution;
+mod environment;
+mod exr;
+mod filter;
+mod hdr;
 mod hittable;
 mod hittable_list;
+mod image;
+mod integrator;
+mod lens_system;
 mod material;
+mod media_stack;
+mod medium;
+mod microfacet;
+mod onb;
+mod output;
+mod panoramic;
+mod pfm;
+mod physical_camera;
+mod random;
 mod ray;
+mod render;
+mod scene;
+mod sky;
 mod sphere;
+mod stats;
+mod stereo;
+mod subsurface;
 mod vec3;
+mod voxel_grid;
 
-use camera::Camera;
+use std::{
+    env,
+    fs::File,
+    io::{self, BufReader, BufWriter, Write},
+    path::Path,
+    process,
+    rc::Rc,
+};
+
+use accumulation::AccumulationBuffer;
+use animation::CameraAnimation;
+use aperture::{ApertureMask, ApertureShape};
+use atmosphere::{Air, Atmosphere, HeightFog};
+use cli::{BakeOptions, Command, MergeOptions, RenderOptions, UndistortOptions};
 use color::Color;
-use hittable::Hittable;
-use hittable_list::HittableList;
-use rand::Rng;
-use ray::Ray;
-use sphere::Sphere;
+use cv_camera::Calibration;
+use denoise::DenoiseSettings;
+use environment::EnvironmentMap;
+use image::Image;
+use lens_system::LensSystem;
+use medium::GridMedium;
+use render::RenderSettings;
+use sky::PreethamSky;
+use stereo::StereoCamera;
 use vec3::Point3;
+use voxel_grid::VoxelGrid;
 
-use crate::{
-    material::{Dielectric, Lambertian, Metal},
-    vec3::Vec3,
-};
+fn render_command(options: &RenderOptions) -> io::Result<()> {
+    let Some((first, last)) = options.frames else {
+        return render_frame(options, None);
+    };
+    let animation = match &options.animation {
+        Some(path) => Some(CameraAnimation::load(path, options.interpolation)?),
+        None => None,
+    };
 
-fn ray_color(ray: &Ray, world: &HittableList, depth: i32) -> Color {
-    if depth <= 0 {
-        return Color::new(0.0, 0.0, 0.0);
-    }
-    let rec = world.hit(ray, f64::INFINITY, 0.001);
+    for frame in first..=last {
+        let frame_path = |path: &Option<String>| {
+            path.as_ref()
+                .map(|pattern| output::frame_path(pattern, frame))
+        };
+        let frame_options = RenderOptions {
+            output: frame_path(&options.output),
+            accumulation: frame_path(&options.accumulation),
+            calibration: frame_path(&options.calibration),
+            // Seeds are set per frame to render the same images when resuming a sequence
+            seed: Some(options.seed.unwrap_or(0) ^ frame.wrapping_mul(0x9e37_79b9_7f4a_7c15)),
+            frames: Some((frame, frame)),
+            ..options.clone()
+        };
 
-    match rec {
-        Some(record) => {
-            let (attenuation, scattered, success) = record.material.scatter(ray, record);
+        let output = frame_options.output.as_deref().unwrap_or_default();
+        // Images only appear at their path once complete
+        if Path::new(output).exists() {
+            eprintln!("Skipping frame {}, {} exists", frame, output);
+            continue;
+        }
+        eprintln!("Rendering frame {} to {}", frame, output);
+        render_frame(
+            &frame_options,
+            animation.as_ref().map(|animation| (animation, frame)),
+        )?;
+    }
 
-            if success {
-                return attenuation * ray_color(&scattered, world, depth - 1);
-            }
+    Ok(())
+}
 
-            return Color::new(0.0, 0.0, 0.0);
+/// Renders a still image, or a frame of `animation`.
+fn render_frame(
+    options: &RenderOptions,
+    animation: Option<(&CameraAnimation, u64)>,
+) -> io::Result<()> {
+    let mut scene = scene::load(&options.scene, options.aspect_ratio())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "unknown scene"))?;
+    if let Some(name) = &options.lens {
+        let lens = LensSystem::load(name)
+            .map_err(|error| io::Error::new(error.kind(), format!("{}: {}", name, error)))?;
+        scene.view.lens = Some(Rc::new(lens));
+        scene.view.exit_pupil_bounds = options.exit_pupil;
+    }
+    if let Some(path) = &options.volume {
+        let [x0, y0, z0, x1, y1, z1] = options.volume_bounds;
+        let [red, green, blue] = options.volume_albedo;
+        scene.world.add(
+            GridMedium::new(
+                VoxelGrid::load(path)?,
+                Point3::new(x0, y0, z0),
+                Point3::new(x1, y1, z1),
+                options.volume_density,
+            )
+            .with_phase_function(Color::new(red, green, blue), options.volume_anisotropy)
+            .with_emission_scale(options.volume_emission),
+        );
+    }
+    scene.view.distortion = options.distortion;
+    if let Some(extrinsics) = &options.extrinsics {
+        extrinsics.place(&mut scene.view);
+    }
+    if let Some((animation, frame)) = animation {
+        animation.place(frame as f64, &mut scene.view);
+    }
+    if let Some(aperture) = options.aperture {
+        scene.view.aperture = aperture;
+    }
+    if let Some(path) = &options.aperture_mask {
+        let mask = ApertureMask::new(&Image::load(path)?);
+        scene.view.aperture_shape = ApertureShape::Mask(Rc::new(mask));
+    } else if let Some(blades) = options.aperture_blades {
+        scene.view.aperture_shape = ApertureShape::Polygon {
+            blades,
+            rotation: options.aperture_rotation,
+        };
+    }
+    scene.camera = match options.stereo {
+        Some(layout) => Box::new(StereoCamera::new(
+            &scene.view,
+            options.projection,
+            options.aspect_ratio(),
+            layout,
+            options.interpupillary_distance,
+            options
+                .convergence_distance
+                .unwrap_or((scene.view.lookat - scene.view.lookfrom).length()),
+        )),
+        None => scene
+            .view
+            .camera(options.projection, options.aspect_ratio()),
+    };
+    if let Some(path) = &options.environment {
+        scene.environment = Box::new(EnvironmentMap::new(
+            Image::load(path)?,
+            options.environment_rotation,
+            options.environment_intensity,
+        ));
+    }
+    if options.sky {
+        scene.environment = Box::new(PreethamSky::new(
+            options.sun_elevation,
+            options.sun_azimuth,
+            options.turbidity,
+            options.sun_radius,
+            options.environment_intensity,
+        ));
+    }
+    if options.atmosphere || options.fog > 0.0 {
+        let mut atmosphere = Atmosphere::new();
+        if options.atmosphere {
+            atmosphere = atmosphere.with_air(Air::new(
+                options.meters_per_unit,
+                options.altitude,
+                options.turbidity,
+            ));
         }
-        None => {
-            let unit_direction = ray.direction.unit_vector();
-            let t = 0.5 * (unit_direction.y + 1.0);
-
-            return (1.0 - t) * Color::new(1.0, 1.0, 1.0) + t * Color::new(0.5, 0.7, 1.0);
+        if options.fog > 0.0 {
+            atmosphere = atmosphere.with_fog(HeightFog::new(
+                options.fog,
+                options.fog_height,
+                options.fog_falloff,
+            ));
         }
+        scene.atmosphere = Some(atmosphere);
+    }
+
+    match options.seed {
+        Some(seed) => random::seed(seed),
+        None => random::seed(rand::random()),
+    }
+
+    let settings = RenderSettings {
+        image_width: options.image_width,
+        image_height: options.image_height(),
+        samples_per_pixel: options.samples_per_pixel,
+        max_depth: options.max_depth,
+        integrator: options.integrator,
+        filter: options.filter,
+        record_aovs: !options.aovs.is_empty() || options.denoise.is_some(),
+    };
+    let mut frame = render::render(&scene, &settings, options.scene_hash());
+
+    if let Some(path) = &options.calibration {
+        Calibration::new(
+            &scene.view,
+            options.projection,
+            settings.image_width,
+            settings.image_height,
+        )
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no pinhole calibration"))?
+        .write(path)?;
     }
+
+    // The accumulation buffer keeps the raw samples so that it can still be merged
+    if let Some(path) = &options.accumulation {
+        output::write_accumulation(&frame.beauty, path)?;
+    }
+    if let (Some(strength), Some(aovs)) = (options.denoise, frame.aovs.as_ref()) {
+        let settings = DenoiseSettings::with_strength(strength);
+        frame.beauty = denoise::denoise(&frame.beauty, aovs, &settings);
+    }
+
+    output::write_image(
+        &frame.beauty,
+        frame.aovs.as_ref(),
+        &options.aovs,
+        &options.output,
+    )
 }
 
-fn main() {
-    // Image
-    const ASPECT_RATIO: f64 = 3.0 / 2.0;
-    const IMAGE_WIDTH: i32 = 1200;
-    const IMAGE_HEIGHT: i32 = ((IMAGE_WIDTH as f64) / ASPECT_RATIO) as i32;
-    const SAMPLES_PER_PIXEL: i32 = 100;
-    const MAX_DEPTH: i32 = 50;
-
-    // World
-    let mut world = HittableList::new();
-
-    let mut rng = rand::thread_rng();
-
-    let ground_material = Lambertian::new(Color::new(0.5, 0.5, 0.5));
-    world.add(Sphere::new(
-        Point3::new(0.0, -1000.0, 0.0),
-        1000.0,
-        ground_material,
-    ));
-
-    for a in -11..11 {
-        for b in -11..11 {
-            let choose_mat = rng.gen::<f64>();
-            let center: Point3 = Point3::new(
-                a as f64 + 0.9 * rng.gen::<f64>(),
-                0.2,
-                b as f64 + 0.9 * rng.gen::<f64>(),
-            );
-
-            if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.9 {
-                if choose_mat < 0.8 {
-                    let albedo = Color::random() * Color::random();
-                    let material = Lambertian::new(albedo);
-                    world.add(Sphere::new(center, 0.2, material))
-                } else if choose_mat < 0.95 {
-                    let albedo = Color::random();
-                    let fuzz = rng.gen_range(0.0..0.5);
-                    let material = Metal::new(albedo, fuzz);
-                    world.add(Sphere::new(center, 0.2, material));
-                } else {
-                    let material = Dielectric::new(1.5);
-                    world.add(Sphere::new(center, 0.2, material));
-                }
-            }
+fn merge_command(options: &MergeOptions) -> io::Result<()> {
+    let mut merged: Option<AccumulationBuffer> = None;
+
+    for path in &options.inputs {
+        let buffer = AccumulationBuffer::read_from(&mut BufReader::new(File::open(path)?))
+            .map_err(|error| io::Error::new(error.kind(), format!("{}: {}", path, error)))?;
+
+        match merged.as_mut() {
+            Some(merged) => merged
+                .merge(&buffer)
+                .map_err(|error| io::Error::new(error.kind(), format!("{}: {}", path, error)))?,
+            None => merged = Some(buffer),
         }
     }
 
-    let material1 = Dielectric::new(1.5);
-    let material2 = Lambertian::new(Color::new(0.4, 0.2, 0.1));
-    let material3 = Metal::new(Color::new(0.7, 0.6, 0.5), 0.0);
+    match merged {
+        Some(buffer) => {
+            if let Some(path) = &options.accumulation {
+                output::write_accumulation(&buffer, path)?;
+            }
+            output::write_image(&buffer, None, &[], &options.output)
+        }
+        None => Ok(()),
+    }
+}
 
-    world.add(Sphere::new(Point3::new(0.0, 1.0, 0.0), 1.0, material1));
-    world.add(Sphere::new(Point3::new(-4.0, 1.0, 0.0), 1.0, material2));
-    world.add(Sphere::new(Point3::new(4.0, 1.0, 0.0), 1.0, material3));
+fn undistort_command(options: &UndistortOptions) -> io::Result<()> {
+    let image = Image::load(&options.input)?;
+    let tan_height = (options.fov.to_radians() / 2.0).tan();
+    let undistorted = distortion::undistort_image(&image, &options.distortion, tan_height);
 
-    let lookfrom = Point3::new(13.0, 2.0, 3.0);
-    let lookat = Point3::new(0.0, 0.0, 0.0);
+    let mut buffer = AccumulationBuffer::new(undistorted.width, undistorted.height, 0);
+    for y in 0..undistorted.height {
+        for x in 0..undistorted.width {
+            buffer.add_sample(x, y, undistorted.pixel(x, y), 1.0);
+        }
+    }
+    output::write_image(&buffer, None, &[], &options.output)
+}
 
-    // Camera
-    let camera = Camera::new(
-        lookfrom,
-        lookat,
-        Vec3::new(0.0, 1.0, 0.0),
-        120.0,
-        ASPECT_RATIO,
-        0.1,
-        10.0,
-    );
+fn bake_command(options: &BakeOptions) -> io::Result<()> {
+    let scene = scene::load(&options.scene, cli::ASPECT_RATIO)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "unknown scene"))?;
 
-    // Render
-    println!("P3\n{} {}\n255", IMAGE_WIDTH, IMAGE_HEIGHT);
+    match options.seed {
+        Some(seed) => random::seed(seed),
+        None => random::seed(rand::random()),
+    }
 
-    for i in (0..IMAGE_HEIGHT).rev() {
-        eprintln!("\rLines remaining: {}", i);
-        for j in 0..IMAGE_WIDTH {
-            let mut pixel_color = Color::new(0.0, 0.0, 0.0);
+    if let Some(path) = &options.vertices {
+        let vertices = ambient_occlusion::read_vertices(&mut BufReader::new(File::open(path)?))
+            .map_err(|error| io::Error::new(error.kind(), format!("{}: {}", path, error)))?;
+        let values = ambient_occlusion::bake_vertices(
+            &scene.world,
+            &vertices,
+            options.ao_distance,
+            options.samples,
+        );
 
-            for _ in 0..SAMPLES_PER_PIXEL {
-                let v = (rng.gen::<f64>() + i as f64) / (IMAGE_HEIGHT - 1) as f64;
-                let u = (rng.gen::<f64>() + j as f64) / (IMAGE_WIDTH - 1) as f64;
+        let mut out: Box<dyn Write> = match &options.output {
+            Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+            None => Box::new(BufWriter::new(io::stdout())),
+        };
+        return ambient_occlusion::write_values(&mut out, &values);
+    }
 
-                let r = camera.get_ray(u, v);
+    let object = scene.world.objects.get(options.object).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("the scene has no object {}", options.object),
+        )
+    })?;
+    let texture = ambient_occlusion::bake_texture(
+        &scene.world,
+        object.as_ref(),
+        options.texture_width,
+        options.texture_height,
+        options.ao_distance,
+        options.samples,
+    )
+    .ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("object {} has no texture coordinates", options.object),
+        )
+    })?;
 
-                pixel_color = pixel_color + ray_color(&r, &world, MAX_DEPTH);
-            }
+    output::write_image(&texture, None, &[], &options.output)
+}
 
-            pixel_color.write_color(SAMPLES_PER_PIXEL)
+fn main() {
+    let command = match cli::parse(env::args().skip(1)) {
+        Ok(command) => command,
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, cli::USAGE);
+            process::exit(2);
         }
+    };
+
+    let result = match command {
+        Command::Render(options) => render_command(&options),
+        Command::Merge(options) => merge_command(&options),
+        Command::Bake(options) => bake_command(&options),
+        Command::Undistort(options) => undistort_command(&options),
+    };
+
+    if let Err(error) = result {
+        eprintln!("error: {}", error);
+        process::exit(1);
     }
 }
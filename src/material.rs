@@ -1,9 +1,52 @@
-use rand::Rng;
+use std::f64::consts::PI;
 
-use crate::{color::Color, hittable::HitRecord, ray::Ray, vec3::Vec3};
+use crate::{
+    color::Color,
+    hittable::HitRecord,
+    media_stack::{Interior, MediaStack},
+    microfacet::{fresnel_conductor, fresnel_dielectric, Ggx},
+    onb::Onb,
+    random,
+    ray::Ray,
+    vec3::Vec3,
+};
 
 pub trait Material {
     fn scatter(&self, r_in: &Ray, hit_record: HitRecord) -> (Color, Ray, bool);
+
+    /// BSDF times the cosine between `direction` and the normal, along with the solid angle
+    /// density with which `scatter` picks `direction`. None for perfectly specular materials,
+    /// which can only be sampled.
+    fn evaluate(
+        &self,
+        _r_in: &Ray,
+        _hit_record: &HitRecord,
+        _direction: Vec3,
+    ) -> Option<(Color, f64)> {
+        None
+    }
+
+    /// Dielectric objects `scattered` travels inside of, those of the incoming ray unless the
+    /// material bounds one that it enters or leaves.
+    fn media(&self, r_in: &Ray, _hit_record: &HitRecord, _scattered: &Ray) -> MediaStack {
+        r_in.media
+    }
+
+    /// Whether scattering counts against the maximum depth of paths, false for the events of
+    /// random walks inside a volume, which end by themselves.
+    fn counts_as_bounce(&self) -> bool {
+        true
+    }
+
+    /// Base color of the surface, written to the albedo AOV.
+    fn albedo(&self) -> Color {
+        Color::new(1.0, 1.0, 1.0)
+    }
+
+    /// Radiance emitted from the hit point towards the incoming ray.
+    fn emitted(&self, _hit_record: &HitRecord) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
 }
 
 pub struct Lambertian {
@@ -17,7 +60,7 @@ impl Lambertian {
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, r_in: &Ray, hit_record: HitRecord) -> (Color, Ray, bool) {
+    fn scatter(&self, _r_in: &Ray, hit_record: HitRecord) -> (Color, Ray, bool) {
         let mut scatter_direction: Vec3 = hit_record.normal + Vec3::random_unit_vector();
 
         if scatter_direction.near_zero() {
@@ -26,80 +69,799 @@ impl Material for Lambertian {
 
         let scattered: Ray = Ray::new(hit_record.p, scatter_direction);
 
-        return (self.albedo, scattered, true);
+        (self.albedo, scattered, true)
+    }
+
+    fn evaluate(
+        &self,
+        _r_in: &Ray,
+        hit_record: &HitRecord,
+        direction: Vec3,
+    ) -> Option<(Color, f64)> {
+        // Scattered directions follow the cosine distribution
+        let cosine = hit_record.normal.dot(direction.unit_vector()).max(0.0);
+        Some((cosine / PI * self.albedo, cosine / PI))
+    }
+
+    fn albedo(&self) -> Color {
+        self.albedo
     }
 }
 
-pub struct Metal {
-    pub albedo: Color,
-    pub fuzz: f64,
+/// Metals of the scenes are conductors reflecting their albedo at normal incidence, their fuzz
+/// becoming the roughness.
+pub type Metal = Conductor;
+
+/// Names of the measured conductors of `Conductor::preset`.
+pub const CONDUCTORS: [&str; 4] = ["gold", "copper", "silver", "aluminium"];
+
+/// Metal reflecting light off GGX microfacets, with the Fresnel reflectance of its complex index
+/// of refraction `eta + i k` for each channel. Smooth conductors are perfect mirrors.
+pub struct Conductor {
+    eta: Color,
+    k: Color,
+    distribution: Ggx,
 }
 
-impl Metal {
-    pub fn new(albedo: Color, fuzz: f64) -> Metal {
-        Metal { albedo, fuzz }
+impl Conductor {
+    /// Conductor reflecting `albedo` at normal incidence and white at grazing angles, from the
+    /// mapping of Gulbrandsen with a white edge tint. `roughness` is the GGX alpha.
+    pub fn new(albedo: Color, roughness: f64) -> Conductor {
+        let ior = |reflectance: f64| {
+            let r = reflectance.clamp(0.0, 0.999);
+            ((1.0 - r) / (1.0 + r), 2.0 * r.sqrt() / (1.0 + r))
+        };
+        let (red, green, blue) = (ior(albedo.x), ior(albedo.y), ior(albedo.z));
+        Conductor::from_ior(
+            Color::new(red.0, green.0, blue.0),
+            Color::new(red.1, green.1, blue.1),
+            roughness,
+        )
     }
+
+    pub fn from_ior(eta: Color, k: Color, roughness: f64) -> Conductor {
+        Conductor {
+            eta,
+            k,
+            distribution: Ggx::new(roughness.max(0.0)),
+        }
+    }
+
+    /// Measured conductor among `CONDUCTORS`, its indices sampled at the wavelengths of the
+    /// channels.
+    pub fn preset(name: &str, roughness: f64) -> Option<Conductor> {
+        let (eta, k) = match name {
+            "gold" => ([0.143119, 0.374957, 1.44248], [3.98316, 2.38572, 1.60322]),
+            "copper" => ([0.200438, 0.924033, 1.10221], [3.91295, 2.45285, 2.14219]),
+            "silver" => ([0.155265, 0.116723, 0.138342], [4.82835, 3.12225, 2.14696]),
+            "aluminium" => ([1.65746, 0.880369, 0.521229], [9.22387, 6.26952, 4.837]),
+            _ => return None,
+        };
+        Some(Conductor::from_ior(
+            Color::new(eta[0], eta[1], eta[2]),
+            Color::new(k[0], k[1], k[2]),
+            roughness,
+        ))
+    }
+
+    fn fresnel(&self, cos_theta: f64) -> Color {
+        Color::new(
+            fresnel_conductor(cos_theta, self.eta.x, self.k.x),
+            fresnel_conductor(cos_theta, self.eta.y, self.k.y),
+            fresnel_conductor(cos_theta, self.eta.z, self.k.z),
+        )
+    }
+}
+
+/// Coordinates of `v` in the basis.
+fn to_local(frame: &Onb, v: Vec3) -> Vec3 {
+    Vec3::new(v.dot(frame.u), v.dot(frame.v), v.dot(frame.w))
 }
 
-impl Material for Metal {
+impl Material for Conductor {
     fn scatter(&self, r_in: &Ray, hit_record: HitRecord) -> (Color, Ray, bool) {
-        let reflected: Vec3 = r_in.direction.unit_vector().reflect(hit_record.normal);
-        let scattered: Ray = Ray::new(
-            hit_record.p,
-            reflected + self.fuzz * Vec3::random_in_unit_sphere(),
-        );
-        let success: bool = scattered.direction.dot(hit_record.normal) > 0.0;
+        let frame = Onb::new(hit_record.normal);
+        let wo = to_local(&frame, -r_in.direction.unit_vector());
+
+        let (wi, weight) = if self.distribution.is_smooth() {
+            (Vec3::new(-wo.x, -wo.y, wo.z), self.fresnel(wo.z))
+        } else {
+            // Reflection off a visible microfacet, weighted by the shadowing of the light
+            let h = self.distribution.sample_visible(wo);
+            let wi = 2.0 * wo.dot(h) * h - wo;
+            let weight = self.distribution.g2(wo, wi) / self.distribution.g1(wo);
+            (wi, weight * self.fresnel(wo.dot(h)))
+        };
+
+        let scattered = Ray::new(hit_record.p, frame.local(wi.x, wi.y, wi.z));
+        (weight, scattered, wo.z > 0.0 && wi.z > 0.0)
+    }
+
+    fn evaluate(
+        &self,
+        r_in: &Ray,
+        hit_record: &HitRecord,
+        direction: Vec3,
+    ) -> Option<(Color, f64)> {
+        if self.distribution.is_smooth() {
+            return None;
+        }
+
+        let frame = Onb::new(hit_record.normal);
+        let wo = to_local(&frame, -r_in.direction.unit_vector());
+        let wi = to_local(&frame, direction.unit_vector());
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return Some((Color::new(0.0, 0.0, 0.0), 0.0));
+        }
+
+        let h = (wo + wi).unit_vector();
+        let d = self.distribution.d(h);
+        let g2 = self.distribution.g2(wo, wi);
+        let pdf = self.distribution.visible_d(wo, h) / (4.0 * wo.dot(h));
+        Some((d * g2 / (4.0 * wo.z) * self.fresnel(wo.dot(h)), pdf))
+    }
 
-        return (self.albedo, scattered, success);
+    fn albedo(&self) -> Color {
+        self.fresnel(1.0)
     }
 }
 
+/// Glass refracting and reflecting light with the exact Fresnel reflectance of its index of
+/// refraction `ir`, perfectly smooth unless given a roughness, which frosts it with GGX microfacets.
+/// Tinted glass absorbs light along the distance it travels inside, following Beer-Lambert's law.
+///
+/// The index of refraction outside is that of the dielectric object the ray travels in, or of the
+/// air. Where dielectric objects overlap, like water filling a glass up to its walls, the object of
+/// highest priority fills the space.
 pub struct Dielectric {
     pub ir: f64,
+    distribution: Ggx,
+    absorption: Color,
+    priority: u32,
 }
 
 impl Dielectric {
     pub fn new(ir: f64) -> Dielectric {
-        Dielectric { ir }
+        Dielectric {
+            ir,
+            distribution: Ggx::new(0.0),
+            absorption: Color::new(0.0, 0.0, 0.0),
+            priority: 0,
+        }
+    }
+
+    /// Same glass frosted with microfacets, `roughness` being the GGX alpha.
+    pub fn with_roughness(mut self, roughness: f64) -> Dielectric {
+        self.distribution = Ggx::new(roughness.max(0.0));
+        self
+    }
+
+    /// Same glass absorbing light inside it with the coefficient of each channel, per unit of
+    /// distance.
+    pub fn with_absorption(mut self, absorption: Color) -> Dielectric {
+        self.absorption = absorption;
+        self
     }
 
-    fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
-        let r0: f64 = (1.0 - ref_idx) / (1.0 + ref_idx);
+    /// Same glass tinted to let `transmittance` of the light through `distance` units of it, a
+    /// distance of zero making it opaque unless clear.
+    pub fn with_transmittance(self, transmittance: Color, distance: f64) -> Dielectric {
+        let distance = distance.max(f64::EPSILON);
+        let absorption = |t: f64| -t.clamp(1e-6, 1.0).ln() / distance;
+        self.with_absorption(Color::new(
+            absorption(transmittance.x),
+            absorption(transmittance.y),
+            absorption(transmittance.z),
+        ))
+    }
+
+    /// Same glass filling the space it shares with objects of lower priority.
+    pub fn with_priority(mut self, priority: u32) -> Dielectric {
+        self.priority = priority;
+        self
+    }
+
+    /// Light left of the ray after crossing the glass to a hit on its inside. Rays keeping track
+    /// of the media they are in are instead absorbed along the way by the integrator.
+    fn transmittance(&self, r_in: &Ray, hit_record: &HitRecord) -> Color {
+        if hit_record.front_face || !r_in.media.is_empty() {
+            return Color::new(1.0, 1.0, 1.0);
+        }
+        let distance = hit_record.t * r_in.direction.length();
+        Color::new(
+            (-self.absorption.x * distance).exp(),
+            (-self.absorption.y * distance).exp(),
+            (-self.absorption.z * distance).exp(),
+        )
+    }
 
-        return r0 * r0 + (1.0 - r0 * r0) * (1.0 - cosine).powf(5.0);
+    /// Index of refraction on the other side of the surface relative to that of the ray.
+    fn eta(&self, r_in: &Ray, hit_record: &HitRecord) -> f64 {
+        let outside = r_in
+            .media
+            .remove(hit_record.object_id)
+            .current()
+            .map_or(1.0, |interior| interior.ir);
+        if hit_record.front_face {
+            self.ir / outside
+        } else {
+            outside / self.ir
+        }
     }
 }
 
 impl Material for Dielectric {
     fn scatter(&self, r_in: &Ray, hit_record: HitRecord) -> (Color, Ray, bool) {
-        let refraction_ratio: f64 = if hit_record.front_face {
-            1.0 / self.ir
+        // Inside an object of higher priority, the boundary is not there
+        if r_in.media.hides(hit_record.object_id, self.priority) {
+            let scattered = Ray::new(hit_record.p, r_in.direction);
+            return (Color::new(1.0, 1.0, 1.0), scattered, true);
+        }
+
+        let eta = self.eta(r_in, &hit_record);
+        let unit_direction: Vec3 = r_in.direction.unit_vector();
+
+        if self.distribution.is_smooth() {
+            let cos_theta: f64 = (-unit_direction.dot(hit_record.normal)).min(1.0);
+            let direction: Vec3 = if fresnel_dielectric(cos_theta, eta) > random::random_double() {
+                unit_direction.reflect(hit_record.normal)
+            } else {
+                unit_direction.refract(hit_record.normal, 1.0 / eta)
+            };
+
+            return (
+                self.transmittance(r_in, &hit_record),
+                Ray::new(hit_record.p, direction),
+                true,
+            );
+        }
+
+        // Reflection or refraction through a visible microfacet, chosen by its reflectance so that
+        // only the shadowing of the light weighs the path
+        let frame = Onb::new(hit_record.normal);
+        let wo = to_local(&frame, -unit_direction);
+        if wo.z <= 0.0 {
+            return (
+                Color::new(0.0, 0.0, 0.0),
+                Ray::new(hit_record.p, unit_direction),
+                false,
+            );
+        }
+        let h = self.distribution.sample_visible(wo);
+        let (wi, success) = if fresnel_dielectric(wo.dot(h), eta) > random::random_double() {
+            let wi = (-wo).reflect(h);
+            (wi, wi.z > 0.0)
         } else {
-            self.ir
+            // Never past the critical angle, whose reflectance is 1
+            let wi = (-wo).refract(h, 1.0 / eta);
+            (wi, wi.z < 0.0)
         };
+        let weight = self.distribution.g2(wo, wi) / self.distribution.g1(wo);
 
-        let unit_direction: Vec3 = r_in.direction.unit_vector();
+        let scattered = Ray::new(hit_record.p, frame.local(wi.x, wi.y, wi.z));
+        let transmittance = self.transmittance(r_in, &hit_record);
+        (weight * transmittance, scattered, success)
+    }
+
+    fn evaluate(
+        &self,
+        r_in: &Ray,
+        hit_record: &HitRecord,
+        direction: Vec3,
+    ) -> Option<(Color, f64)> {
+        if self.distribution.is_smooth() || r_in.media.hides(hit_record.object_id, self.priority) {
+            return None;
+        }
 
-        let cos_theta: f64 = (-unit_direction.dot(hit_record.normal)).min(1.0);
-        let sin_theta: f64 = (1.0 - cos_theta * cos_theta).sqrt();
+        let eta = self.eta(r_in, hit_record);
+        let frame = Onb::new(hit_record.normal);
+        let wo = to_local(&frame, -r_in.direction.unit_vector());
+        let wi = to_local(&frame, direction.unit_vector());
+        let black = Some((Color::new(0.0, 0.0, 0.0), 0.0));
+        if wo.z <= 0.0 || wi.z == 0.0 {
+            return black;
+        }
+
+        if wi.z > 0.0 {
+            let h = (wo + wi).unit_vector();
+            let reflectance = fresnel_dielectric(wo.dot(h), eta);
+            let f =
+                reflectance * self.distribution.d(h) * self.distribution.g2(wo, wi) / (4.0 * wo.z);
+            let pdf = reflectance * self.distribution.visible_d(wo, h) / (4.0 * wo.dot(h));
+            return Some((f * self.transmittance(r_in, hit_record), pdf));
+        }
+
+        // Microfacet refracting `wo` into `wi`, facing the side of the ray
+        let h = wo + eta * wi;
+        if h.length_squared() == 0.0 {
+            return black;
+        }
+        let h = if h.z < 0.0 { -h } else { h }.unit_vector();
+        if wo.dot(h) <= 0.0 || wi.dot(h) >= 0.0 {
+            return black;
+        }
 
-        let cannot_refract: bool = refraction_ratio * sin_theta > 1.0;
+        let refracted = 1.0 - fresnel_dielectric(wo.dot(h), eta);
+        let denominator = (wi.dot(h) + wo.dot(h) / eta).powi(2);
+        let jacobian = -wi.dot(h) / denominator;
+        let f = refracted
+            * self.distribution.d(h)
+            * self.distribution.g2(wo, wi)
+            * wo.dot(h)
+            * jacobian
+            / wo.z;
+        let pdf = refracted * self.distribution.visible_d(wo, h) * jacobian;
+        Some((f * self.transmittance(r_in, hit_record), pdf))
+    }
 
-        let direction: Vec3;
-        let mut rng = rand::thread_rng();
+    fn media(&self, r_in: &Ray, hit_record: &HitRecord, scattered: &Ray) -> MediaStack {
+        if scattered.direction.dot(hit_record.normal) >= 0.0 {
+            return r_in.media;
+        }
 
-        if cannot_refract
-            || Self::reflectance(cos_theta, refraction_ratio) > rng.gen_range(0.0..1.0)
-        {
-            direction = unit_direction.reflect(hit_record.normal)
+        if hit_record.front_face {
+            r_in.media.push(Interior {
+                object_id: hit_record.object_id,
+                ir: self.ir,
+                priority: self.priority,
+                absorption: self.absorption,
+            })
         } else {
-            direction = unit_direction.refract(hit_record.normal, refraction_ratio)
+            r_in.media.remove(hit_record.object_id)
         }
+    }
+}
+
+/// Phase function of participating media scattering light equally in every direction.
+pub struct Isotropic {
+    pub albedo: Color,
+}
+
+impl Isotropic {
+    pub fn new(albedo: Color) -> Isotropic {
+        Isotropic { albedo }
+    }
+}
 
-        return (
-            Color::new(1.0, 1.0, 1.0),
-            Ray::new(hit_record.p, direction),
+impl Material for Isotropic {
+    fn scatter(&self, _r_in: &Ray, hit_record: HitRecord) -> (Color, Ray, bool) {
+        (
+            self.albedo,
+            Ray::new(hit_record.p, Vec3::random_unit_vector()),
             true,
+        )
+    }
+
+    fn evaluate(
+        &self,
+        _r_in: &Ray,
+        _hit_record: &HitRecord,
+        _direction: Vec3,
+    ) -> Option<(Color, f64)> {
+        Some((self.albedo / (4.0 * PI), 1.0 / (4.0 * PI)))
+    }
+
+    fn albedo(&self) -> Color {
+        self.albedo
+    }
+}
+
+/// Henyey-Greenstein phase function, scattering light forward for a positive anisotropy `g` and
+/// backward for a negative one, `g` being the mean cosine of the scattering angle.
+pub struct HenyeyGreenstein {
+    pub albedo: Color,
+    pub g: f64,
+}
+
+impl HenyeyGreenstein {
+    pub fn new(albedo: Color, g: f64) -> HenyeyGreenstein {
+        HenyeyGreenstein { albedo, g }
+    }
+
+    /// Density of scattering by an angle of cosine `cos_theta` from the direction of propagation.
+    pub fn phase(&self, cos_theta: f64) -> f64 {
+        let g = self.g;
+        let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+        (1.0 - g * g) / (4.0 * PI * denominator * denominator.sqrt())
+    }
+
+    /// Samples a scattered direction by inverting the distribution of the phase function.
+    pub fn sample(&self, forward: Vec3) -> Vec3 {
+        let g = self.g;
+        let xi = random::random_double();
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * xi
+        } else {
+            let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * xi);
+            ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+        };
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * random::random_double();
+
+        Onb::new(forward).local(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+    }
+}
+
+impl Material for HenyeyGreenstein {
+    fn scatter(&self, r_in: &Ray, hit_record: HitRecord) -> (Color, Ray, bool) {
+        (
+            self.albedo,
+            Ray::new(hit_record.p, self.sample(r_in.direction)),
+            true,
+        )
+    }
+
+    fn evaluate(
+        &self,
+        r_in: &Ray,
+        _hit_record: &HitRecord,
+        direction: Vec3,
+    ) -> Option<(Color, f64)> {
+        let cos_theta = r_in.direction.unit_vector().dot(direction.unit_vector());
+        let phase = self.phase(cos_theta);
+        Some((phase * self.albedo, phase))
+    }
+
+    fn albedo(&self) -> Color {
+        self.albedo
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::Point3;
+    use approx::*;
+
+    #[test]
+    fn henyey_greenstein() {
+        random::seed(11);
+        let forward = Vec3::new(0.0, 0.6, 0.8);
+
+        for g in [-0.5, 0.0, 0.3, 0.9] {
+            let phase = HenyeyGreenstein::new(Color::new(1.0, 1.0, 1.0), g);
+
+            // Sampled directions have a mean cosine of g
+            let runs = 20000;
+            let mean_cosine = (0..runs)
+                .map(|_| phase.sample(forward).dot(forward))
+                .sum::<f64>()
+                / runs as f64;
+            assert_relative_eq!(g, mean_cosine, epsilon = 0.02);
+
+            // The phase function integrates to 1 over the sphere
+            let steps = 20000;
+            let integral: f64 = (0..steps)
+                .map(|i| {
+                    let cos_theta = -1.0 + 2.0 * (i as f64 + 0.5) / steps as f64;
+                    2.0 * PI * phase.phase(cos_theta) * 2.0 / steps as f64
+                })
+                .sum();
+            assert_relative_eq!(1.0, integral, epsilon = 1e-3);
+        }
+    }
+
+    fn hit_from(r_in: &Ray) -> HitRecord<'static> {
+        static SURFACE: Lambertian = Lambertian {
+            albedo: Color {
+                x: 0.5,
+                y: 0.5,
+                z: 0.5,
+            },
+        };
+        HitRecord::new(r_in.at(1.0), Vec3::new(0.0, 0.0, 1.0), 1.0, true, &SURFACE)
+    }
+
+    #[test]
+    fn conductor_reflectance() {
+        // Metals reflect their albedo at normal incidence
+        let albedo = Color::new(0.9, 0.6, 0.2);
+        let metal = Metal::new(albedo, 0.0);
+        assert_relative_eq!(albedo.x, metal.albedo().x, epsilon = 1e-9);
+        assert_relative_eq!(albedo.z, metal.albedo().z, epsilon = 1e-9);
+
+        for name in CONDUCTORS {
+            assert!(Conductor::preset(name, 0.1).is_some());
+        }
+        assert!(Conductor::preset("lead", 0.1).is_none());
+        let gold = Conductor::preset("gold", 0.0).unwrap().albedo();
+        let silver = Conductor::preset("silver", 0.0).unwrap().albedo();
+        assert!(gold.x > gold.y && gold.y > gold.z);
+        assert!(silver.z > 0.9);
+
+        // Smooth conductors mirror
+        let r_in = Ray::new(Point3::new(-1.0, 0.0, 1.0), Vec3::new(1.0, 0.0, -1.0));
+        let record = hit_from(&r_in);
+        let (_, scattered, success) = metal.scatter(&r_in, record);
+        assert!(success);
+        assert_relative_eq!(
+            0.0,
+            (scattered.direction - Vec3::new(1.0, 0.0, 1.0).unit_vector()).length(),
+            epsilon = 1e-9
+        );
+        assert!(metal
+            .evaluate(&r_in, &record, scattered.direction)
+            .is_none());
+    }
+
+    #[test]
+    fn rough_conductor() {
+        random::seed(47);
+        let r_in = Ray::new(Point3::new(-1.0, 0.2, 1.0), Vec3::new(1.0, -0.2, -1.0));
+        let record = hit_from(&r_in);
+
+        let mut energies = Vec::new();
+        for roughness in [0.1, 0.5, 1.0] {
+            // A conductor of very large extinction reflects everything off its microfacets
+            let mirror = Conductor::from_ior(
+                Color::new(1.0, 1.0, 1.0),
+                Color::new(1e4, 1e4, 1e4),
+                roughness,
+            );
+
+            let runs = 20000;
+            let mut energy = 0.0;
+            for _ in 0..runs {
+                let (weight, scattered, success) = mirror.scatter(&r_in, record);
+                if !success {
+                    continue;
+                }
+                energy += weight.y / runs as f64;
+
+                // Sampling matches the evaluated BSDF and density
+                let (f, pdf) = mirror
+                    .evaluate(&r_in, &record, scattered.direction)
+                    .unwrap();
+                assert_relative_eq!(weight.x, f.x / pdf, max_relative = 1e-6);
+            }
+            energies.push(energy);
+        }
+
+        // Only the masking and shadowing of the microfacets lose energy, up to 0.62 at 45° when
+        // the distribution is the roughest
+        assert_relative_eq!(0.981, energies[0], epsilon = 0.01);
+        assert_relative_eq!(0.683, energies[1], epsilon = 0.01);
+        assert_relative_eq!(0.379, energies[2], epsilon = 0.01);
+    }
+
+    #[test]
+    fn smooth_dielectric() {
+        random::seed(48);
+        let glass = Dielectric::new(1.5);
+
+        // Glass reflects 4% of the light at normal incidence
+        let r_in = Ray::new(Point3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let record = hit_from(&r_in);
+        let runs = 20000;
+        let reflected = (0..runs)
+            .filter(|_| glass.scatter(&r_in, record).1.direction.z > 0.0)
+            .count();
+        assert_relative_eq!(0.04, reflected as f64 / runs as f64, epsilon = 0.005);
+        assert!(glass.evaluate(&r_in, &record, r_in.direction).is_none());
+
+        // and everything past the critical angle from the inside
+        let r_in = Ray::new(Point3::new(-2.0, 0.0, -1.0), Vec3::new(2.0, 0.0, 1.0));
+        let record = HitRecord::new(r_in.at(1.0), Vec3::new(0.0, 0.0, 1.0), 1.0, false, &glass);
+        for _ in 0..100 {
+            let (weight, scattered, success) = glass.scatter(&r_in, record);
+            assert!(success && scattered.direction.z < 0.0);
+            assert_relative_eq!(1.0, weight.x);
+        }
+    }
+
+    #[test]
+    fn rough_dielectric() {
+        random::seed(48);
+        let glass = Dielectric::new(1.5);
+        // Light arriving from the outside, from the inside, and from the inside past the critical
+        // angle
+        let rays = [
+            Ray::new(Point3::new(-1.0, 0.2, 1.0), Vec3::new(1.0, -0.2, -1.0)),
+            Ray::new(Point3::new(-0.3, 0.2, -1.0), Vec3::new(0.3, -0.2, 1.0)),
+            Ray::new(Point3::new(-2.0, 0.0, -1.0), Vec3::new(2.0, 0.0, 1.0)),
+        ];
+
+        for (i, r_in) in rays.iter().enumerate() {
+            let front_face = i == 0;
+            let record = HitRecord::new(
+                r_in.at(1.0),
+                Vec3::new(0.0, 0.0, 1.0),
+                1.0,
+                front_face,
+                &glass,
+            );
+            let mut energies = Vec::new();
+            for roughness in [0.1, 0.5, 1.0] {
+                let frosted = Dielectric::new(1.5).with_roughness(roughness);
+
+                // White furnace: without absorption, only the shadowing of the microfacets loses
+                // energy
+                let runs = 20000;
+                let (mut energy, mut transmitted) = (0.0, 0.0);
+                for _ in 0..runs {
+                    let (weight, scattered, success) = frosted.scatter(r_in, record);
+                    if !success {
+                        continue;
+                    }
+                    energy += weight.y / runs as f64;
+                    if scattered.direction.dot(record.normal) < 0.0 {
+                        transmitted += weight.y / runs as f64;
+                    }
+
+                    // Sampling matches the evaluated BSDF and density
+                    let (f, pdf) = frosted
+                        .evaluate(r_in, &record, scattered.direction)
+                        .unwrap();
+                    assert_relative_eq!(weight.x, f.x / pdf, max_relative = 1e-6);
+                }
+                energies.push(energy);
+                assert!(energy <= 1.0);
+
+                // which the BSDF integrates to over the sphere, most of it transmitted unless past
+                // the critical angle
+                if roughness >= 0.5 {
+                    let steps = 400;
+                    let mut integral = 0.0;
+                    for j in 0..steps {
+                        let cos_theta = -1.0 + 2.0 * (j as f64 + 0.5) / steps as f64;
+                        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+                        for k in 0..2 * steps {
+                            let phi = PI * (k as f64 + 0.5) / steps as f64;
+                            let direction =
+                                Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+                            let (f, _) = frosted.evaluate(r_in, &record, direction).unwrap();
+                            integral += f.x * 2.0 * PI / (steps * steps) as f64;
+                        }
+                    }
+                    assert_relative_eq!(integral, energy, epsilon = 0.02);
+                }
+                if i < 2 {
+                    assert!(transmitted > 0.5 * energy);
+                } else if roughness < 0.5 {
+                    // Only the most tilted microfacets let light through
+                    assert!(transmitted < 0.05 * energy);
+                }
+            }
+
+            assert!(energies[0] > 0.95);
+            assert!(energies[1] < energies[0] && energies[2] < energies[1]);
+        }
+    }
+
+    #[test]
+    fn tinted_glass() {
+        random::seed(49);
+        let tint = Color::new(0.2, 0.5, 0.9);
+        let glass = Dielectric::new(1.5).with_transmittance(tint, 2.0);
+
+        // Light entering the glass is not absorbed yet
+        let r_in = Ray::new(Point3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let (weight, _, _) = glass.scatter(&r_in, hit_from(&r_in));
+        assert_relative_eq!(1.0, weight.x);
+
+        // but leaving it, along the distance traveled inside
+        for (distance, expected) in [(2.0, tint), (4.0, tint * tint)] {
+            let r_in = Ray::new(Point3::new(0.0, 0.0, -distance), Vec3::new(0.0, 0.0, 1.0));
+            let record = HitRecord::new(
+                Point3::new(0.0, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, 1.0),
+                distance,
+                false,
+                &glass,
+            );
+            let (weight, _, _) = glass.scatter(&r_in, record);
+            assert_relative_eq!(expected.x, weight.x, epsilon = 1e-9);
+            assert_relative_eq!(expected.y, weight.y, epsilon = 1e-9);
+            assert_relative_eq!(expected.z, weight.z, epsilon = 1e-9);
+
+            // as is the light reaching the inside of frosted glass
+            let frosted = Dielectric::new(1.5)
+                .with_roughness(0.3)
+                .with_transmittance(tint, 2.0);
+            let (weight, scattered, success) = frosted.scatter(&r_in, record);
+            if success {
+                let (f, pdf) = frosted
+                    .evaluate(&r_in, &record, scattered.direction)
+                    .unwrap();
+                assert_relative_eq!(weight.x, f.x / pdf, max_relative = 1e-6);
+                assert_relative_eq!(
+                    weight.z / weight.x,
+                    expected.z / expected.x,
+                    max_relative = 1e-6
+                );
+            }
+        }
+
+        // Tints over no distance stay finite
+        let thin = Dielectric::new(1.5).with_transmittance(Color::new(1.0, 0.5, 1.0), 0.0);
+        assert_relative_eq!(0.0, thin.absorption.x);
+        assert!(thin.absorption.y.is_finite() && thin.absorption.y > 0.0);
+        let r_in = Ray::new(Point3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 0.0, 1.0));
+        let record = HitRecord::new(r_in.at(1.0), Vec3::new(0.0, 0.0, 1.0), 1.0, false, &thin);
+        let (weight, _, _) = thin.scatter(&r_in, record);
+        assert_relative_eq!(1.0, weight.x);
+        assert_relative_eq!(0.0, weight.y);
+    }
+
+    #[test]
+    fn nested_dielectrics() {
+        random::seed(50);
+        let glass = Dielectric::new(1.5).with_priority(1);
+        let r_in = Ray::new(Point3::new(-1.0, 0.0, 1.0), Vec3::new(1.0, 0.0, -1.0));
+        let mut record = hit_from(&r_in);
+
+        // A ray entering glass keeps track of it
+        record.object_id = 4;
+        let (_, scattered, _) = (0..100)
+            .map(|_| glass.scatter(&r_in, record))
+            .find(|(_, scattered, _)| scattered.direction.z < 0.0)
+            .unwrap();
+        let media = glass.media(&r_in, &record, &scattered);
+        assert_relative_eq!(1.5, media.current().unwrap().ir);
+        let reflected = Ray::new(record.p, Vec3::new(1.0, 0.0, 1.0));
+        assert!(glass.media(&r_in, &record, &reflected).is_empty());
+
+        // so that an object of the same index inside it does not refract
+        let inside =
+            Ray::new(Point3::new(-1.0, 0.0, 1.0), Vec3::new(1.0, 0.0, -1.0)).with_media(media);
+        let same = Dielectric::new(1.5).with_priority(1);
+        record.object_id = 5;
+        for _ in 0..100 {
+            let (_, scattered, success) = same.scatter(&inside, record);
+            assert!(success);
+            assert_relative_eq!(
+                0.0,
+                (scattered.direction.unit_vector() - inside.direction.unit_vector()).length(),
+                epsilon = 1e-9
+            );
+        }
+
+        // while water refracts light less leaving it than leaving air
+        let water = Dielectric::new(1.33).with_priority(1);
+        let (_, scattered, _) = (0..100)
+            .map(|_| water.scatter(&inside, record))
+            .find(|(_, scattered, _)| scattered.direction.z < 0.0)
+            .unwrap();
+        let sin_theta = |direction: Vec3| direction.unit_vector().x;
+        assert_relative_eq!(
+            1.5 / 1.33,
+            sin_theta(scattered.direction) / sin_theta(inside.direction),
+            epsilon = 1e-9
+        );
+
+        // and its boundary is ignored where it overlaps glass of a higher priority
+        let water = Dielectric::new(1.33);
+        let (weight, scattered, success) = water.scatter(&inside, record);
+        assert!(success);
+        assert_relative_eq!(1.0, weight.x);
+        assert_relative_eq!(0.0, (scattered.direction - inside.direction).length());
+        assert!(water
+            .evaluate(&inside, &record, scattered.direction)
+            .is_none());
+        let media = water.media(&inside, &record, &scattered);
+        assert_eq!(4, media.current().unwrap().object_id);
+
+        // Leaving the glass, the water fills the space
+        let leaving =
+            Ray::new(Point3::new(-1.0, 0.0, -1.0), Vec3::new(1.0, 0.0, 1.0)).with_media(media);
+        let mut record = HitRecord::new(
+            leaving.at(1.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            1.0,
+            false,
+            &glass,
+        );
+        record.object_id = 4;
+        let (_, scattered, _) = (0..100)
+            .map(|_| glass.scatter(&leaving, record))
+            .find(|(_, scattered, _)| scattered.direction.z > 0.0)
+            .unwrap();
+        assert_relative_eq!(
+            1.5 / 1.33,
+            sin_theta(scattered.direction) / sin_theta(leaving.direction),
+            epsilon = 1e-9
         );
+        let media = glass.media(&leaving, &record, &scattered);
+        assert_relative_eq!(1.33, media.current().unwrap().ir);
     }
 }
@@ -0,0 +1,148 @@
+use crate::color::Color;
+
+/// Most dielectric objects a path can be inside of at once, those entered past it being ignored.
+const CAPACITY: usize = 4;
+
+/// Inside of a dielectric object, filled with glass or liquid.
+#[derive(Clone, Copy)]
+pub struct Interior {
+    pub object_id: usize,
+    pub ir: f64,
+    pub priority: u32,
+    pub absorption: Color,
+}
+
+/// Dielectric objects a path is inside of, in the order it entered them, following the nested
+/// dielectrics of Schmidt and Budge.
+///
+/// Where objects overlap, the one of highest priority fills the space, the boundaries of the others
+/// being ignored. Among objects of equal priority, the last entered does.
+#[derive(Clone, Copy, Default)]
+pub struct MediaStack {
+    interiors: [Option<Interior>; CAPACITY],
+}
+
+impl MediaStack {
+    pub fn is_empty(&self) -> bool {
+        self.interiors[0].is_none()
+    }
+
+    /// Stack of a path entering `interior`.
+    pub fn push(mut self, interior: Interior) -> MediaStack {
+        if let Some(slot) = self.interiors.iter_mut().find(|slot| slot.is_none()) {
+            *slot = Some(interior);
+        }
+        self
+    }
+
+    /// Stack of a path leaving the object `object_id`.
+    pub fn remove(mut self, object_id: usize) -> MediaStack {
+        let interiors = self
+            .interiors
+            .iter()
+            .flatten()
+            .filter(|interior| interior.object_id != object_id);
+        let mut remaining = [None; CAPACITY];
+        for (slot, interior) in remaining.iter_mut().zip(interiors) {
+            *slot = Some(*interior);
+        }
+        self.interiors = remaining;
+        self
+    }
+
+    /// Interior filling the space the path is in, None in the air.
+    pub fn current(&self) -> Option<Interior> {
+        self.interiors
+            .iter()
+            .flatten()
+            .fold(None, |current: Option<&Interior>, interior| match current {
+                Some(current) if current.priority > interior.priority => Some(current),
+                _ => Some(interior),
+            })
+            .copied()
+    }
+
+    /// Whether the boundary of an object of `priority` is hidden by another object of higher
+    /// priority the path is inside of.
+    pub fn hides(&self, object_id: usize, priority: u32) -> bool {
+        self.interiors
+            .iter()
+            .flatten()
+            .any(|interior| interior.object_id != object_id && interior.priority > priority)
+    }
+
+    /// Light left of a ray traveling `distance` through the current interior, following
+    /// Beer-Lambert's law.
+    pub fn transmittance(&self, distance: f64) -> Color {
+        match self.current() {
+            Some(interior) => Color::new(
+                (-interior.absorption.x * distance).exp(),
+                (-interior.absorption.y * distance).exp(),
+                (-interior.absorption.z * distance).exp(),
+            ),
+            None => Color::new(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::*;
+
+    fn interior(object_id: usize, ir: f64, priority: u32) -> Interior {
+        Interior {
+            object_id,
+            ir,
+            priority,
+            absorption: Color::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    fn id(interior: Option<Interior>) -> Option<usize> {
+        interior.map(|interior| interior.object_id)
+    }
+
+    #[test]
+    fn nesting() {
+        let (glass, water, ice) = (
+            interior(0, 1.5, 2),
+            interior(1, 1.33, 1),
+            interior(2, 1.31, 1),
+        );
+        let air = MediaStack::default();
+        assert!(air.is_empty());
+        assert!(air.current().is_none());
+
+        // Inside the walls of a glass overlapping the water, the glass fills the space
+        let stack = air.push(glass).push(water);
+        assert_eq!(Some(0), id(stack.current()));
+        assert!(stack.hides(water.object_id, water.priority));
+        assert!(!stack.hides(glass.object_id, glass.priority));
+
+        // and once out of them the water, then the last entered of equal priority
+        let stack = stack.remove(glass.object_id);
+        assert_eq!(Some(1), id(stack.current()));
+        assert_eq!(Some(2), id(stack.push(ice).current()));
+        assert!(!stack.push(ice).hides(ice.object_id, ice.priority));
+        assert_eq!(Some(1), id(stack.push(ice).remove(2).current()));
+        assert!(stack.remove(water.object_id).is_empty());
+
+        // Objects entered past the capacity are ignored
+        let full = (0..CAPACITY + 1).fold(air, |stack, id| stack.push(interior(id, 1.5, 0)));
+        assert_eq!(CAPACITY - 1, full.current().unwrap().object_id);
+    }
+
+    #[test]
+    fn absorption() {
+        let mut tinted = interior(0, 1.5, 0);
+        tinted.absorption = Color::new(1.0, 0.5, 0.0);
+        let stack = MediaStack::default().push(tinted);
+
+        let transmittance = stack.transmittance(2.0);
+        assert_relative_eq!((-2.0_f64).exp(), transmittance.x);
+        assert_relative_eq!((-1.0_f64).exp(), transmittance.y);
+        assert_relative_eq!(1.0, transmittance.z);
+        assert_relative_eq!(1.0, MediaStack::default().transmittance(2.0).x);
+    }
+}
@@ -0,0 +1,389 @@
+use crate::{
+    color::Color,
+    cuboid,
+    hittable::{HitRecord, Hittable},
+    material::{HenyeyGreenstein, Isotropic, Material},
+    random,
+    ray::Ray,
+    vec3::{Point3, Vec3},
+    voxel_grid::VoxelGrid,
+};
+
+/// Participating medium of constant density filling a closed boundary, like fog or smoke.
+///
+/// Rays crossing it collide with it at a distance following the exponential distribution of its
+/// density, which is the extinction coefficient, the albedo splitting the collisions between
+/// scattering and absorption. The boundary only delimits the volume.
+pub struct ConstantMedium<H: Hittable> {
+    boundary: H,
+    neg_inv_density: f64,
+    phase_function: Isotropic,
+}
+
+impl<H: Hittable> ConstantMedium<H> {
+    /// `density` is the extinction coefficient, scattering and absorption together, the inverse of
+    /// the mean free path in scene units, and `albedo` the fraction of the collisions scattering
+    /// the light rather than absorbing it.
+    pub fn new(boundary: H, density: f64, albedo: Color) -> ConstantMedium<H> {
+        ConstantMedium {
+            boundary,
+            neg_inv_density: -1.0 / density,
+            phase_function: Isotropic::new(albedo),
+        }
+    }
+}
+
+impl<H: Hittable> ConstantMedium<H> {
+    /// Part of the ray between `t_min` and `t_max` inside the boundary.
+    fn interval(&self, ray: &Ray, t_max: f64, t_min: f64) -> Option<(f64, f64)> {
+        // Entry and exit of the boundary along the whole line, so that rays may start inside
+        let entry = self.boundary.hit(ray, f64::INFINITY, f64::NEG_INFINITY)?;
+        let exit = self.boundary.hit(ray, f64::INFINITY, entry.t + 0.0001)?;
+
+        let start = entry.t.max(t_min);
+        let end = exit.t.min(t_max);
+        (start < end).then_some((start, end))
+    }
+}
+
+impl<H: Hittable> Hittable for ConstantMedium<H> {
+    fn hit(&self, ray: &Ray, t_max: f64, t_min: f64) -> Option<HitRecord<'_>> {
+        let (start, end) = self.interval(ray, t_max, t_min)?;
+
+        let ray_length = ray.direction.length();
+        let distance_inside = (end - start) * ray_length;
+        let hit_distance = self.neg_inv_density * (1.0 - random::random_double()).ln();
+        if hit_distance > distance_inside {
+            return None;
+        }
+
+        let t = start + hit_distance / ray_length;
+        let mut record = HitRecord::new(
+            ray.at(t),
+            Vec3::new(1.0, 0.0, 0.0),
+            t,
+            true,
+            &self.phase_function,
+        );
+        // Phase functions do not depend on the normal, which only faces the ray for the AOVs
+        record.normal = -ray.direction.unit_vector();
+
+        Some(record)
+    }
+
+    fn transmittance(&self, ray: &Ray, t_max: f64, t_min: f64) -> f64 {
+        match self.interval(ray, t_max, t_min) {
+            Some((start, end)) => {
+                ((end - start) * ray.direction.length() / self.neg_inv_density).exp()
+            }
+            None => 1.0,
+        }
+    }
+}
+
+/// Participating medium whose density varies over a voxel grid stretched between two corners, like
+/// clouds or fire.
+///
+/// Collisions are sampled by delta tracking and transmittances estimated by ratio tracking, both
+/// against the largest density of the grid, which keeps them unbiased. Scattering follows a
+/// Henyey-Greenstein phase function, and every collision emits the radiance of the grid along with
+/// the black body radiation of its temperature, so that the emission follows the density whatever
+/// the albedo.
+pub struct GridMedium {
+    grid: VoxelGrid,
+    min: Point3,
+    max: Point3,
+    density_scale: f64,
+    majorant: f64,
+    phase_function: HenyeyGreenstein,
+    emission_scale: f64,
+}
+
+impl GridMedium {
+    /// `density_scale` converts the densities of the grid to extinction coefficients, in inverse
+    /// scene units.
+    pub fn new(grid: VoxelGrid, a: Point3, b: Point3, density_scale: f64) -> GridMedium {
+        let majorant = grid.max_density() * density_scale;
+        GridMedium {
+            grid,
+            min: Point3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)),
+            max: Point3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)),
+            density_scale,
+            majorant,
+            phase_function: HenyeyGreenstein::new(Color::new(1.0, 1.0, 1.0), 0.0),
+            emission_scale: 1.0,
+        }
+    }
+
+    /// Scatters the fraction `albedo` of the collisions with the anisotropy `g`.
+    pub fn with_phase_function(mut self, albedo: Color, g: f64) -> GridMedium {
+        self.phase_function = HenyeyGreenstein::new(albedo, g);
+        self
+    }
+
+    /// Scales the emission and the black body radiation, which are normalized to a peak of 1.
+    pub fn with_emission_scale(mut self, scale: f64) -> GridMedium {
+        self.emission_scale = scale;
+        self
+    }
+
+    fn local(&self, p: Point3) -> Vec3 {
+        (p - self.min) / (self.max - self.min)
+    }
+
+    fn extinction(&self, p: Point3) -> f64 {
+        self.density_scale * self.grid.density(self.local(p))
+    }
+
+    /// Part of the ray between `t_min` and `t_max` inside the grid.
+    fn interval(&self, ray: &Ray, t_max: f64, t_min: f64) -> Option<(f64, f64)> {
+        let ((entry, _), (exit, _)) = cuboid::slabs(self.min, self.max, ray)?;
+        let start = entry.max(t_min);
+        let end = exit.min(t_max);
+        (start < end && self.majorant > 0.0).then_some((start, end))
+    }
+
+    /// Tentative collisions along the ray, exponentially distributed with the majorant density.
+    fn next_collision(&self, t: f64, ray_length: f64) -> f64 {
+        t - (1.0 - random::random_double()).ln() / (self.majorant * ray_length)
+    }
+}
+
+impl Hittable for GridMedium {
+    fn hit(&self, ray: &Ray, t_max: f64, t_min: f64) -> Option<HitRecord<'_>> {
+        let (start, end) = self.interval(ray, t_max, t_min)?;
+        let ray_length = ray.direction.length();
+
+        // Delta tracking, rejecting the collisions with the null density filling up to the majorant
+        let mut t = start;
+        loop {
+            t = self.next_collision(t, ray_length);
+            if t >= end {
+                return None;
+            }
+            if random::random_double() * self.majorant < self.extinction(ray.at(t)) {
+                break;
+            }
+        }
+
+        // The medium is the material of its collisions, emitting where it is hot
+        let mut record = HitRecord::new(ray.at(t), Vec3::new(1.0, 0.0, 0.0), t, true, self);
+        record.normal = -ray.direction.unit_vector();
+        Some(record)
+    }
+
+    fn transmittance(&self, ray: &Ray, t_max: f64, t_min: f64) -> f64 {
+        let Some((start, end)) = self.interval(ray, t_max, t_min) else {
+            return 1.0;
+        };
+        let ray_length = ray.direction.length();
+
+        // Ratio tracking, multiplying the probabilities of the tentative collisions being null
+        let mut transmittance = 1.0;
+        let mut t = start;
+        loop {
+            t = self.next_collision(t, ray_length);
+            if t >= end {
+                return transmittance;
+            }
+            transmittance *= 1.0 - self.extinction(ray.at(t)) / self.majorant;
+        }
+    }
+}
+
+impl Material for GridMedium {
+    fn scatter(&self, r_in: &Ray, hit_record: HitRecord) -> (Color, Ray, bool) {
+        self.phase_function.scatter(r_in, hit_record)
+    }
+
+    fn evaluate(
+        &self,
+        r_in: &Ray,
+        hit_record: &HitRecord,
+        direction: Vec3,
+    ) -> Option<(Color, f64)> {
+        self.phase_function.evaluate(r_in, hit_record, direction)
+    }
+
+    fn albedo(&self) -> Color {
+        self.phase_function.albedo
+    }
+
+    fn emitted(&self, hit_record: &HitRecord) -> Color {
+        let p = self.local(hit_record.p);
+        let radiance = self.grid.emission(p) + Color::blackbody(self.grid.temperature(p));
+        self.emission_scale * radiance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cuboid::Cuboid, material::Lambertian, sphere::Sphere, vec3::Point3};
+    use approx::*;
+
+    fn slab(density: f64) -> ConstantMedium<Cuboid<Lambertian>> {
+        ConstantMedium::new(
+            Cuboid::new(
+                Point3::new(-10.0, -10.0, 0.0),
+                Point3::new(10.0, 10.0, 2.0),
+                Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+            ),
+            density,
+            Color::new(1.0, 1.0, 1.0),
+        )
+    }
+
+    #[test]
+    fn transmittance_follows_beer_lambert() {
+        random::seed(3);
+        let medium = slab(0.5);
+        // The direction is not normalized, distances being measured in scene units
+        let ray = Ray::new(Point3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 0.0, 4.0));
+
+        let runs = 20000;
+        let mut transmitted = 0;
+        let mut mean_depth = 0.0;
+        for _ in 0..runs {
+            match medium.hit(&ray, f64::INFINITY, 0.001) {
+                Some(record) => {
+                    assert!(record.p.z >= 0.0 && record.p.z <= 2.0);
+                    mean_depth += record.p.z;
+                }
+                None => transmitted += 1,
+            }
+        }
+
+        let transmittance = transmitted as f64 / runs as f64;
+        assert_relative_eq!((-0.5f64 * 2.0).exp(), transmittance, epsilon = 0.01);
+
+        // Scattering is denser near the entry
+        let scattered = (runs - transmitted) as f64;
+        assert!(mean_depth / scattered < 1.0);
+    }
+
+    #[test]
+    fn constant_transmittance() {
+        let medium = slab(0.5);
+        let ray = Ray::new(Point3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 0.0, 4.0));
+        assert_relative_eq!(
+            (-1.0f64).exp(),
+            medium.transmittance(&ray, f64::INFINITY, 0.001)
+        );
+        assert_relative_eq!((-0.5f64).exp(), medium.transmittance(&ray, 0.5, 0.001));
+
+        let above = Ray::new(Point3::new(0.0, 0.0, 3.0), Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(1.0, medium.transmittance(&above, f64::INFINITY, 0.001));
+    }
+
+    /// Grid filling the unit cube whose density rises linearly along x, from 0 to 2.
+    fn ramp() -> GridMedium {
+        let density = (0..8 * 8 * 8)
+            .map(|index| 2.0 * ((index % 8) as f64 + 0.5) / 8.0)
+            .collect();
+        GridMedium::new(
+            VoxelGrid::new(8, 8, 8, density),
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 1.0, 1.0),
+            1.0,
+        )
+    }
+
+    #[test]
+    fn ratio_tracking() {
+        random::seed(7);
+        let medium = ramp();
+        let ray = Ray::new(Point3::new(-1.0, 0.5, 0.5), Vec3::new(2.0, 0.0, 0.0));
+
+        // The optical depth of the ramp is the integral of its density, clamped at the outer
+        // voxel centers: 1
+        let expected = (-1.0f64).exp();
+        let runs = 20000;
+        let mean = (0..runs)
+            .map(|_| medium.transmittance(&ray, f64::INFINITY, 0.001))
+            .sum::<f64>()
+            / runs as f64;
+        assert_relative_eq!(expected, mean, epsilon = 0.01);
+
+        // Delta tracking lets the same fraction of the rays through
+        let transmitted = (0..runs)
+            .filter(|_| medium.hit(&ray, f64::INFINITY, 0.001).is_none())
+            .count();
+        assert_relative_eq!(expected, transmitted as f64 / runs as f64, epsilon = 0.01);
+
+        // Along y, the density is constant
+        let across = Ray::new(Point3::new(0.25, -1.0, 0.5), Vec3::new(0.0, 1.0, 0.0));
+        let density = medium.extinction(Point3::new(0.25, 0.5, 0.5));
+        let mean = (0..runs)
+            .map(|_| medium.transmittance(&across, f64::INFINITY, 0.001))
+            .sum::<f64>()
+            / runs as f64;
+        assert_relative_eq!((-density).exp(), mean, epsilon = 0.01);
+    }
+
+    #[test]
+    fn grid_emission() {
+        let grid = VoxelGrid::new(1, 1, 1, vec![1.0])
+            .with_temperature(vec![2000.0])
+            .with_emission(vec![Color::new(0.0, 1.0, 0.0)]);
+        let medium = GridMedium::new(
+            grid,
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 1.0, 1.0),
+            100.0,
+        )
+        .with_phase_function(Color::new(0.5, 0.5, 0.5), 0.8)
+        .with_emission_scale(2.0);
+
+        let ray = Ray::new(Point3::new(0.5, 0.5, -1.0), Vec3::new(0.0, 0.0, 1.0));
+        let record = medium.hit(&ray, f64::INFINITY, 0.001).unwrap();
+        let expected = 2.0 * (Color::new(0.0, 1.0, 0.0) + Color::blackbody(2000.0));
+        let emitted = record.material.emitted(&record);
+        assert_relative_eq!(expected.x, emitted.x);
+        assert_relative_eq!(expected.y, emitted.y);
+        assert_relative_eq!(0.5, record.material.albedo().z);
+    }
+
+    #[test]
+    fn emission_without_absorption() {
+        // Hot grids glow with the default options, which scatter all the light
+        let grid = VoxelGrid::new(1, 1, 1, vec![1.0]).with_temperature(vec![1500.0]);
+        let medium = GridMedium::new(
+            grid,
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 1.0, 1.0),
+            100.0,
+        );
+
+        let ray = Ray::new(Point3::new(0.5, 0.5, -1.0), Vec3::new(0.0, 0.0, 1.0));
+        let record = medium.hit(&ray, f64::INFINITY, 0.001).unwrap();
+        assert_relative_eq!(1.0, record.material.albedo().x);
+        assert!(record.material.emitted(&record).x > 0.0);
+    }
+
+    #[test]
+    fn rays_inside_and_range() {
+        random::seed(5);
+        let medium = ConstantMedium::new(
+            Sphere::new(
+                Point3::new(0.0, 0.0, 0.0),
+                1.0,
+                Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+            ),
+            1000.0,
+            Color::new(0.0, 0.0, 0.0),
+        );
+
+        // A dense medium scatters right where a ray starting inside it leaves its origin
+        let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let record = medium.hit(&ray, f64::INFINITY, 0.001).unwrap();
+        assert!(record.t < 0.05);
+        assert!(record.front_face);
+        assert_relative_eq!(-1.0, record.normal.x);
+
+        // Nothing is hit outside of the range of the ray or of the boundary
+        assert!(medium.hit(&ray, f64::INFINITY, 1.5).is_none());
+        let away = Ray::new(Point3::new(2.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(medium.hit(&away, f64::INFINITY, 0.001).is_none());
+    }
+}
@@ -0,0 +1,239 @@
+use std::f64::consts::PI;
+
+use crate::{random, vec3::Vec3};
+
+/// Roughness below which surfaces are treated as perfectly smooth.
+pub const SMOOTH_ROUGHNESS: f64 = 1e-3;
+
+/// GGX (Trowbridge-Reitz) distribution of microfacet normals, with the Smith masking-shadowing
+/// of its height-correlated form.
+///
+/// Directions are in the local frame of the surface, whose normal is +z.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ggx {
+    pub alpha: f64,
+}
+
+impl Ggx {
+    pub fn new(alpha: f64) -> Ggx {
+        Ggx { alpha }
+    }
+
+    pub fn is_smooth(&self) -> bool {
+        self.alpha < SMOOTH_ROUGHNESS
+    }
+
+    /// Density of microfacet normals `h` per projected area.
+    pub fn d(&self, h: Vec3) -> f64 {
+        if h.z <= 0.0 {
+            return 0.0;
+        }
+        let a2 = self.alpha * self.alpha;
+        let t = (h.x * h.x + h.y * h.y) / a2 + h.z * h.z;
+        1.0 / (PI * a2 * t * t)
+    }
+
+    fn lambda(&self, w: Vec3) -> f64 {
+        let cos2 = w.z * w.z;
+        if cos2 == 0.0 {
+            return f64::INFINITY;
+        }
+        let tan2 = (1.0 - cos2).max(0.0) / cos2;
+        0.5 * ((1.0 + self.alpha * self.alpha * tan2).sqrt() - 1.0)
+    }
+
+    /// Fraction of the microfacets visible from `w`.
+    pub fn g1(&self, w: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// Fraction of the microfacets visible from both `wo` and `wi`.
+    pub fn g2(&self, wo: Vec3, wi: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Density of the normals visible from `wo`, those sampled by `sample_visible`.
+    pub fn visible_d(&self, wo: Vec3, h: Vec3) -> f64 {
+        if wo.z <= 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) * wo.dot(h).max(0.0) * self.d(h) / wo.z
+    }
+
+    /// Samples a microfacet normal visible from `wo`, which must be above the surface, following
+    /// Heitz.
+    pub fn sample_visible(&self, wo: Vec3) -> Vec3 {
+        // Stretch to the configuration of a hemisphere of unit roughness
+        let v = Vec3::new(self.alpha * wo.x, self.alpha * wo.y, wo.z).unit_vector();
+        let length2 = v.x * v.x + v.y * v.y;
+        let t1 = if length2 > 0.0 {
+            Vec3::new(-v.y, v.x, 0.0) / length2.sqrt()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = v.cross_product(t1);
+
+        // Point of the projected disk, part of it hidden behind the hemisphere
+        let r = random::random_double().sqrt();
+        let phi = 2.0 * PI * random::random_double();
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + v.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let n = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * v;
+
+        Vec3::new(self.alpha * n.x, self.alpha * n.y, n.z.max(0.0)).unit_vector()
+    }
+}
+
+/// Fresnel reflectance of a conductor of complex index of refraction `eta + i k` for unpolarized
+/// light, at an angle of cosine `cos_theta` to the normal.
+pub fn fresnel_conductor(cos_theta: f64, eta: f64, k: f64) -> f64 {
+    let cos2 = cos_theta.clamp(0.0, 1.0).powi(2);
+    let sin2 = 1.0 - cos2;
+    let t0 = eta * eta - k * k - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+
+    let t1 = a2_plus_b2 + cos2;
+    let t2 = 2.0 * cos2.sqrt() * a;
+    let s = (t1 - t2) / (t1 + t2);
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let p = s * (t3 - t4) / (t3 + t4);
+
+    0.5 * (s + p)
+}
+
+/// Fresnel reflectance of the boundary of a dielectric for unpolarized light, at an angle of cosine
+/// `cos_theta` to the normal on the side of the incident light, `eta` being the index of the other
+/// side relative to that one. Total internal reflection reflects everything.
+pub fn fresnel_dielectric(cos_theta: f64, eta: f64) -> f64 {
+    let cos_i = cos_theta.clamp(0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+
+    let parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    0.5 * (parallel * parallel + perpendicular * perpendicular)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::*;
+
+    /// Unit direction at polar angle `theta` and azimuth `phi`.
+    fn direction(theta: f64, phi: f64) -> Vec3 {
+        Vec3::new(
+            theta.sin() * phi.cos(),
+            theta.sin() * phi.sin(),
+            theta.cos(),
+        )
+    }
+
+    #[test]
+    fn projected_area() {
+        // The microfacets project onto the macro surface, and onto any view of it
+        for alpha in [0.05, 0.3, 1.0] {
+            let ggx = Ggx::new(alpha);
+            let wo = direction(1.0, 0.4);
+            let (steps, mut area, mut visible) = (1000, 0.0, 0.0);
+            for i in 0..steps {
+                // Denser near the normal, where the narrow distributions peak
+                let u = (i as f64 + 0.5) / steps as f64;
+                let theta = 0.5 * PI * u * u;
+                let d_theta = PI * u / steps as f64;
+                for j in 0..64 {
+                    let phi = 2.0 * PI * (j as f64 + 0.5) / 64.0;
+                    let h = direction(theta, phi);
+                    let solid_angle = theta.sin() * d_theta * 2.0 * PI / 64.0;
+                    area += ggx.d(h) * h.z * solid_angle;
+                    visible += ggx.visible_d(wo, h) * solid_angle;
+                }
+            }
+            assert_relative_eq!(1.0, area, epsilon = 0.01);
+            assert_relative_eq!(1.0, visible, epsilon = 0.01);
+        }
+    }
+
+    #[test]
+    fn visible_normals() {
+        random::seed(47);
+        let ggx = Ggx::new(0.5);
+        let wo = direction(1.2, 2.0);
+
+        // Sampled normals face the view, with the mean of their visible distribution
+        let runs = 50000;
+        let mut mean = Vec3::new(0.0, 0.0, 0.0);
+        for _ in 0..runs {
+            let h = ggx.sample_visible(wo);
+            assert!(h.z >= 0.0 && wo.dot(h) >= 0.0);
+            mean = mean + h / runs as f64;
+        }
+
+        let (steps, mut expected) = (400, Vec3::new(0.0, 0.0, 0.0));
+        for i in 0..steps {
+            let theta = 0.5 * PI * (i as f64 + 0.5) / steps as f64;
+            for j in 0..steps {
+                let phi = 2.0 * PI * (j as f64 + 0.5) / steps as f64;
+                let h = direction(theta, phi);
+                let solid_angle = theta.sin() * PI * PI / (steps * steps) as f64;
+                expected = expected + ggx.visible_d(wo, h) * solid_angle * h;
+            }
+        }
+        assert_relative_eq!(expected.x, mean.x, epsilon = 0.01);
+        assert_relative_eq!(expected.y, mean.y, epsilon = 0.01);
+        assert_relative_eq!(expected.z, mean.z, epsilon = 0.01);
+    }
+
+    #[test]
+    fn masking() {
+        let ggx = Ggx::new(0.4);
+        let normal = Vec3::new(0.0, 0.0, 1.0);
+        assert_relative_eq!(1.0, ggx.g1(normal));
+        assert_relative_eq!(0.0, ggx.g1(direction(0.5 * PI, 0.0)), epsilon = 1e-6);
+
+        let (wo, wi) = (direction(1.0, 0.0), direction(0.7, 2.0));
+        assert!(ggx.g2(wo, wi) <= ggx.g1(wo).min(ggx.g1(wi)));
+        assert!(ggx.g2(wo, wi) >= ggx.g1(wo) * ggx.g1(wi));
+    }
+
+    #[test]
+    fn conductor_fresnel() {
+        // Without extinction, that of a dielectric at normal incidence
+        assert_relative_eq!(0.04, fresnel_conductor(1.0, 1.5, 0.0), epsilon = 1e-9);
+        let expected = ((0.2_f64 - 1.0).powi(2) + 9.0) / ((0.2_f64 + 1.0).powi(2) + 9.0);
+        assert_relative_eq!(expected, fresnel_conductor(1.0, 0.2, 3.0), epsilon = 1e-9);
+
+        // Every conductor mirrors at grazing angles
+        assert_relative_eq!(1.0, fresnel_conductor(0.0, 0.2, 3.0), epsilon = 1e-9);
+        assert!(fresnel_conductor(0.5, 0.2, 3.0) > 0.0);
+    }
+
+    #[test]
+    fn dielectric_fresnel() {
+        assert_relative_eq!(0.04, fresnel_dielectric(1.0, 1.5), epsilon = 1e-9);
+        assert_relative_eq!(0.04, fresnel_dielectric(1.0, 1.0 / 1.5), epsilon = 1e-9);
+        assert_relative_eq!(1.0, fresnel_dielectric(0.0, 1.5), epsilon = 1e-9);
+        assert_relative_eq!(0.0, fresnel_dielectric(0.6, 1.0), epsilon = 1e-9);
+
+        // No reflectance at Brewster's angle for the parallel polarization, half of it remains
+        let brewster = 1.5_f64.atan();
+        let perpendicular = ((brewster.cos() - 1.5 * brewster.sin())
+            / (brewster.cos() + 1.5 * brewster.sin()))
+        .powi(2);
+        assert_relative_eq!(
+            0.5 * perpendicular,
+            fresnel_dielectric(brewster.cos(), 1.5),
+            epsilon = 1e-9
+        );
+
+        // Total internal reflection past the critical angle inside the glass
+        let critical = (1.0_f64 / 1.5).asin();
+        assert_relative_eq!(1.0, fresnel_dielectric((critical + 0.01).cos(), 1.0 / 1.5));
+        assert!(fresnel_dielectric((critical - 0.01).cos(), 1.0 / 1.5) < 1.0);
+    }
+}
@@ -0,0 +1,55 @@
+use crate::vec3::Vec3;
+
+/// Orthonormal basis whose `w` axis is a given direction.
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    pub fn new(w: Vec3) -> Onb {
+        let w = w.unit_vector();
+        let a = if w.x.abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = w.cross_product(a).unit_vector();
+        let u = w.cross_product(v);
+
+        Onb { u, v, w }
+    }
+
+    /// Converts coordinates in the basis to world space.
+    pub fn local(&self, a: f64, b: f64, c: f64) -> Vec3 {
+        a * self.u + b * self.v + c * self.w
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::*;
+
+    #[test]
+    fn orthonormal() {
+        for w in [
+            Vec3::new(0.0, 0.0, 2.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(-0.3, 0.5, 0.8),
+        ] {
+            let onb = Onb::new(w);
+            assert_relative_eq!(1.0, onb.u.length(), epsilon = 1e-9);
+            assert_relative_eq!(1.0, onb.v.length(), epsilon = 1e-9);
+            assert_relative_eq!(0.0, onb.u.dot(onb.v), epsilon = 1e-9);
+            assert_relative_eq!(0.0, onb.u.dot(onb.w), epsilon = 1e-9);
+            assert_relative_eq!(0.0, onb.v.dot(onb.w), epsilon = 1e-9);
+            assert_relative_eq!(
+                1.0,
+                onb.local(0.0, 0.0, 1.0).dot(w.unit_vector()),
+                epsilon = 1e-9
+            );
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use crate::{
+    accumulation::AccumulationBuffer,
+    aov::{Aov, AovBuffer},
+    exr::{self, Channel},
+    pfm,
+};
+
+fn create_output(path: &Option<String>) -> io::Result<Box<dyn Write>> {
+    match path {
+        Some(path) => Ok(Box::new(BufWriter::new(File::create(path)?))),
+        None => Ok(Box::new(BufWriter::new(io::stdout()))),
+    }
+}
+
+fn extension(path: &Option<String>) -> Option<&str> {
+    path.as_deref()
+        .and_then(|path| Path::new(path).extension())
+        .and_then(|extension| extension.to_str())
+}
+
+fn beauty_planes(buffer: &AccumulationBuffer) -> Vec<Vec<f32>> {
+    let mut planes = vec![Vec::new(); 3];
+    for y in 0..buffer.height {
+        for x in 0..buffer.width {
+            let color = buffer.pixel(x, y);
+            planes[0].push(color.x as f32);
+            planes[1].push(color.y as f32);
+            planes[2].push(color.z as f32);
+        }
+    }
+    planes
+}
+
+/// Path of the image of an AOV written next to the beauty image: `image.ppm` gives
+/// `image.normal.pfm` for the normals.
+pub fn aov_path(output: &str, aov: Aov) -> String {
+    let path = Path::new(output);
+    let stem = path.with_extension("");
+    format!("{}.{}.pfm", stem.display(), aov.name())
+}
+
+/// Path of a frame of a sequence, replacing the last run of `#` in `pattern` by the frame number
+/// padded with zeros to its length: `shot_###.exr` gives `shot_007.exr` for the frame 7.
+pub fn frame_path(pattern: &str, frame: u64) -> String {
+    match pattern.rfind('#') {
+        Some(end) => {
+            let start = pattern[..end].trim_end_matches('#').len();
+            let width = end + 1 - start;
+            format!(
+                "{}{:0width$}{}",
+                &pattern[..start],
+                frame,
+                &pattern[end + 1..],
+                width = width
+            )
+        }
+        None => pattern.to_string(),
+    }
+}
+
+/// Path the image is written to before being renamed to `output` once complete.
+fn partial_path(output: &str) -> String {
+    format!("{}.partial", output)
+}
+
+/// Writes the beauty image in the format given by the extension of `output` (PPM by default, PFM
+/// or EXR). The selected AOVs become layers of an EXR image, or separate PFM images otherwise.
+///
+/// The beauty image is written last under a temporary name, then renamed, so that an image found
+/// at `output` is complete along with its AOVs even if the render was interrupted.
+pub fn write_image(
+    beauty: &AccumulationBuffer,
+    aovs: Option<&AovBuffer>,
+    selected: &[Aov],
+    output: &Option<String>,
+) -> io::Result<()> {
+    let width = beauty.width;
+    let height = beauty.height;
+
+    if let (Some(aovs), Some(output), false) = (aovs, output, extension(output) == Some("exr")) {
+        for aov in selected {
+            let mut file = BufWriter::new(File::create(aov_path(output, *aov))?);
+            let values = pfm::interleave(&aovs.planes(*aov));
+            pfm::write_pfm(&mut file, width, height, aov.channels().len(), &values)?;
+            file.flush()?;
+        }
+    }
+
+    let partial = output.as_deref().map(partial_path);
+    let mut out = create_output(&partial)?;
+    match extension(output) {
+        Some("exr") => {
+            let mut channels: Vec<Channel> = ["R", "G", "B"]
+                .iter()
+                .zip(beauty_planes(beauty))
+                .map(|(name, values)| Channel {
+                    name: name.to_string(),
+                    values,
+                })
+                .collect();
+
+            if let Some(aovs) = aovs {
+                for aov in selected {
+                    for (component, values) in aov.channels().iter().zip(aovs.planes(*aov)) {
+                        channels.push(Channel {
+                            name: format!("{}.{}", aov.name(), component),
+                            values,
+                        });
+                    }
+                }
+            }
+
+            exr::write_exr(&mut out, width, height, channels)?;
+        }
+        Some("pfm") => {
+            let values = pfm::interleave(&beauty_planes(beauty));
+            pfm::write_pfm(&mut out, width, height, 3, &values)?;
+        }
+        _ => beauty.write_ppm(&mut out)?,
+    }
+    out.flush()?;
+    drop(out);
+
+    if let (Some(partial), Some(output)) = (partial, output) {
+        fs::rename(partial, output)?;
+    }
+    Ok(())
+}
+
+pub fn write_accumulation(buffer: &AccumulationBuffer, path: &str) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    buffer.write_to(&mut file)?;
+    file.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aov_paths() {
+        assert_eq!(
+            "out/image.normal.pfm",
+            aov_path("out/image.ppm", Aov::Normal)
+        );
+        assert_eq!("image.object_id.pfm", aov_path("image", Aov::ObjectId));
+    }
+
+    #[test]
+    fn frame_paths() {
+        assert_eq!("shot_007.exr", frame_path("shot_###.exr", 7));
+        assert_eq!("#take/12.ppm", frame_path("#take/#.ppm", 12));
+        assert_eq!("frame1234.pfm", frame_path("frame##.pfm", 1234));
+        assert_eq!("still.ppm", frame_path("still.ppm", 3));
+    }
+
+    #[test]
+    fn complete_images() {
+        let directory = std::env::temp_dir().join(format!("ray-tracing-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let output = directory.join("frame.ppm").display().to_string();
+
+        let beauty = AccumulationBuffer::new(2, 2, 0);
+        let aovs = AovBuffer::new(2, 2);
+        write_image(&beauty, Some(&aovs), &[Aov::Depth], &Some(output.clone())).unwrap();
+
+        // Only the renamed image is left, next to its AOVs
+        assert!(Path::new(&output).exists());
+        assert!(Path::new(&aov_path(&output, Aov::Depth)).exists());
+        assert!(!Path::new(&partial_path(&output)).exists());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,227 @@
+use std::f64::consts::PI;
+
+use crate::{
+    camera::Camera,
+    ray::Ray,
+    vec3::{Point3, Vec3},
+};
+
+/// Field of view of the fisheye projections by default, in degrees.
+pub const DEFAULT_FISHEYE_FOV: f64 = 180.0;
+
+/// Mappings of the whole image to the sphere of directions around the camera.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Panorama {
+    /// Longitude along the width and latitude along the height, best with a 2:1 image
+    Equirectangular,
+    /// Angle from the view direction proportional to the distance from the image center
+    EquidistantFisheye { fov: f64 },
+    /// Equal areas of the image cover equal solid angles
+    EquisolidFisheye { fov: f64 },
+    /// Faces +X, -X, +Y on the top row and -Y, +Z, -Z on the bottom one, each laid out like an
+    /// OpenGL cube map face, best with a 3:2 image
+    CubeMap,
+}
+
+impl Panorama {
+    /// Direction of the point (s, t) of the image in the camera frame, x pointing right, y up and
+    /// z behind the camera. None outside of the image circle of the fisheyes.
+    pub fn direction(&self, s: f64, t: f64, aspect_ratio: f64) -> Option<Vec3> {
+        match *self {
+            Panorama::Equirectangular => {
+                let longitude = (s - 0.5) * 2.0 * PI;
+                let latitude = (t - 0.5) * PI;
+                Some(Vec3::new(
+                    latitude.cos() * longitude.sin(),
+                    latitude.sin(),
+                    -latitude.cos() * longitude.cos(),
+                ))
+            }
+            Panorama::EquidistantFisheye { fov } => {
+                fisheye(s, t, aspect_ratio, |r| r * fov.to_radians() / 2.0)
+            }
+            Panorama::EquisolidFisheye { fov } => fisheye(s, t, aspect_ratio, |r| {
+                2.0 * (r * (fov.to_radians() / 4.0).sin()).asin()
+            }),
+            Panorama::CubeMap => Some(cube_map_direction(s, t)),
+        }
+    }
+}
+
+/// The image circle fits the shorter side of the image, `theta` mapping the distance from its
+/// center (1 on the circle) to the angle from the view direction.
+fn fisheye(s: f64, t: f64, aspect_ratio: f64, theta: impl Fn(f64) -> f64) -> Option<Vec3> {
+    let (mut x, mut y) = (2.0 * s - 1.0, 2.0 * t - 1.0);
+    if aspect_ratio > 1.0 {
+        x *= aspect_ratio;
+    } else {
+        y /= aspect_ratio;
+    }
+
+    let r = (x * x + y * y).sqrt();
+    if r > 1.0 {
+        return None;
+    }
+    let theta = theta(r);
+    let phi = y.atan2(x);
+
+    Some(Vec3::new(
+        theta.sin() * phi.cos(),
+        theta.sin() * phi.sin(),
+        -theta.cos(),
+    ))
+}
+
+fn cube_map_direction(s: f64, t: f64) -> Vec3 {
+    let column = ((s * 3.0) as usize).min(2);
+    let row = if t >= 0.5 { 0 } else { 1 };
+
+    // Coordinates in [-1, 1] on the face, b going down
+    let a = 2.0 * (s * 3.0 - column as f64) - 1.0;
+    let b = 2.0 * ((1.0 - t) * 2.0 - row as f64) - 1.0;
+
+    let direction = match row * 3 + column {
+        0 => Vec3::new(1.0, -b, -a),
+        1 => Vec3::new(-1.0, -b, a),
+        2 => Vec3::new(a, 1.0, b),
+        3 => Vec3::new(a, -1.0, -b),
+        4 => Vec3::new(a, -b, 1.0),
+        _ => Vec3::new(-a, -b, -1.0),
+    };
+    direction.unit_vector()
+}
+
+/// Pinhole camera seeing all around it through a `Panorama`.
+pub struct PanoramicCamera {
+    origin: Point3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    panorama: Panorama,
+    aspect_ratio: f64,
+}
+
+impl PanoramicCamera {
+    pub fn new(
+        lookfrom: Point3,
+        lookat: Point3,
+        vup: Vec3,
+        panorama: Panorama,
+        aspect_ratio: f64,
+    ) -> PanoramicCamera {
+        let w = (lookfrom - lookat).unit_vector();
+        let u = (vup.cross_product(w)).unit_vector();
+        let v = w.cross_product(u);
+
+        PanoramicCamera {
+            origin: lookfrom,
+            u,
+            v,
+            w,
+            panorama,
+            aspect_ratio,
+        }
+    }
+}
+
+impl Camera for PanoramicCamera {
+    fn get_ray(&self, s: f64, t: f64) -> Option<Ray> {
+        let local = self.panorama.direction(s, t, self.aspect_ratio)?;
+        let direction = local.x * self.u + local.y * self.v + local.z * self.w;
+
+        Some(Ray::new(self.origin, direction))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::*;
+
+    macro_rules! assert_vec3_equal {
+        ($expected:expr, $actual:expr) => {
+            let tolerance = 0.0001;
+            assert_relative_eq!($expected.x, $actual.x, epsilon = tolerance);
+            assert_relative_eq!($expected.y, $actual.y, epsilon = tolerance);
+            assert_relative_eq!($expected.z, $actual.z, epsilon = tolerance);
+        };
+    }
+
+    #[test]
+    fn equirectangular() {
+        let panorama = Panorama::Equirectangular;
+        let direction = |s, t| panorama.direction(s, t, 2.0).unwrap();
+
+        assert_vec3_equal!(Vec3::new(0.0, 0.0, -1.0), direction(0.5, 0.5));
+        assert_vec3_equal!(Vec3::new(1.0, 0.0, 0.0), direction(0.75, 0.5));
+        assert_vec3_equal!(Vec3::new(0.0, 0.0, 1.0), direction(0.0, 0.5));
+        assert_vec3_equal!(Vec3::new(0.0, 1.0, 0.0), direction(0.3, 1.0));
+    }
+
+    #[test]
+    fn fisheyes() {
+        for panorama in [
+            Panorama::EquidistantFisheye { fov: 180.0 },
+            Panorama::EquisolidFisheye { fov: 180.0 },
+        ] {
+            // The center looks ahead and the edge of the circle sideways, with a 180 degrees
+            // field of view
+            let center = panorama.direction(0.5, 0.5, 1.5).unwrap();
+            let edge = panorama.direction(0.5, 1.0, 1.5).unwrap();
+            assert_vec3_equal!(Vec3::new(0.0, 0.0, -1.0), center);
+            assert_vec3_equal!(Vec3::new(0.0, 1.0, 0.0), edge);
+
+            // The corners are outside of the image circle
+            assert!(panorama.direction(0.0, 0.0, 1.5).is_none());
+        }
+
+        // Halfway to the edge is 45 degrees for the equidistant projection, more for the
+        // equisolid one
+        let halfway = |panorama: Panorama| {
+            let direction = panorama.direction(0.75, 0.5, 1.0).unwrap();
+            direction.x.atan2(-direction.z).to_degrees()
+        };
+        assert_relative_eq!(
+            45.0,
+            halfway(Panorama::EquidistantFisheye { fov: 180.0 }),
+            epsilon = 1e-9
+        );
+        assert_relative_eq!(
+            2.0 * (0.5 * (PI / 4.0).sin()).asin().to_degrees(),
+            halfway(Panorama::EquisolidFisheye { fov: 180.0 }),
+            epsilon = 1e-9
+        );
+    }
+
+    #[test]
+    fn cube_map_faces() {
+        let direction = |s, t| Panorama::CubeMap.direction(s, t, 1.5).unwrap();
+
+        // Centers of the faces
+        assert_vec3_equal!(Vec3::new(1.0, 0.0, 0.0), direction(1.0 / 6.0, 0.75));
+        assert_vec3_equal!(Vec3::new(-1.0, 0.0, 0.0), direction(0.5, 0.75));
+        assert_vec3_equal!(Vec3::new(0.0, 1.0, 0.0), direction(5.0 / 6.0, 0.75));
+        assert_vec3_equal!(Vec3::new(0.0, -1.0, 0.0), direction(1.0 / 6.0, 0.25));
+        assert_vec3_equal!(Vec3::new(0.0, 0.0, 1.0), direction(0.5, 0.25));
+        assert_vec3_equal!(Vec3::new(0.0, 0.0, -1.0), direction(5.0 / 6.0, 0.25));
+
+        // The top left corner of the +X face, OpenGL style
+        let corner = Vec3::new(1.0, 1.0, 1.0).unit_vector();
+        assert_vec3_equal!(corner, direction(0.0, 1.0));
+    }
+
+    #[test]
+    fn camera_frame() {
+        let camera = PanoramicCamera::new(
+            Point3::new(1.0, 2.0, 3.0),
+            Point3::new(1.0, 2.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Panorama::Equirectangular,
+            2.0,
+        );
+        let ray = camera.get_ray(0.5, 0.5).unwrap();
+
+        assert_vec3_equal!(Point3::new(1.0, 2.0, 3.0), ray.origin);
+        assert_vec3_equal!(Vec3::new(0.0, 0.0, -1.0), ray.direction);
+    }
+}
@@ -0,0 +1,179 @@
+use std::io::{self, BufRead, Write};
+
+use crate::{color::Color, image::Image};
+
+/// Largest width or height accepted when reading, bounding the allocation a header can request.
+const MAX_DIMENSION: usize = 1 << 16;
+
+/// Writes a Portable Float Map from interleaved values, `channels` being 1 (grayscale) or 3 (RGB)
+/// and rows starting from the top.
+pub fn write_pfm(
+    out: &mut impl Write,
+    width: usize,
+    height: usize,
+    channels: usize,
+    values: &[f32],
+) -> io::Result<()> {
+    let kind = if channels == 1 { "Pf" } else { "PF" };
+    // A negative scale marks little-endian data
+    write!(out, "{}\n{} {}\n-1.0\n", kind, width, height)?;
+
+    // Scanlines are stored from the bottom of the image to the top
+    for row in values.chunks(width * channels).rev() {
+        for value in row {
+            out.write_all(&value.to_le_bytes())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads a Portable Float Map, grayscale images being expanded to RGB.
+pub fn read_pfm(input: &mut impl BufRead) -> io::Result<Image> {
+    let kind = read_token(input)?;
+    let channels = match kind.as_str() {
+        "PF" => 3,
+        "Pf" => 1,
+        _ => return Err(invalid_data("not a PFM image")),
+    };
+    let width: usize = parse_token(&read_token(input)?)?;
+    let height: usize = parse_token(&read_token(input)?)?;
+    let scale: f64 = parse_token(&read_token(input)?)?;
+    if !(1..=MAX_DIMENSION).contains(&width) || !(1..=MAX_DIMENSION).contains(&height) {
+        return Err(invalid_data("unsupported PFM image dimensions"));
+    }
+    let length = width
+        .checked_mul(height)
+        .and_then(|pixels| pixels.checked_mul(channels))
+        .ok_or_else(|| invalid_data("unsupported PFM image dimensions"))?;
+
+    let mut values = vec![0.0; length];
+    let mut bytes = [0u8; 4];
+    for value in values.iter_mut() {
+        input.read_exact(&mut bytes)?;
+        *value = if scale < 0.0 {
+            f32::from_le_bytes(bytes)
+        } else {
+            f32::from_be_bytes(bytes)
+        };
+    }
+
+    // Scanlines are stored from the bottom of the image to the top
+    let mut pixels = Vec::with_capacity(width * height);
+    for row in values.chunks(width * channels).rev() {
+        for pixel in row.chunks(channels) {
+            let color = if channels == 3 {
+                Color::new(pixel[0] as f64, pixel[1] as f64, pixel[2] as f64)
+            } else {
+                Color::new(pixel[0] as f64, pixel[0] as f64, pixel[0] as f64)
+            };
+            pixels.push(color);
+        }
+    }
+
+    Ok(Image {
+        width,
+        height,
+        pixels,
+    })
+}
+
+/// Reads a whitespace delimited header token and the single whitespace character following it.
+fn read_token(input: &mut impl BufRead) -> io::Result<String> {
+    let mut token = String::new();
+    let mut byte = [0u8; 1];
+    loop {
+        input.read_exact(&mut byte)?;
+        if byte[0].is_ascii_whitespace() {
+            if token.is_empty() {
+                continue;
+            }
+            return Ok(token);
+        }
+        token.push(byte[0] as char);
+    }
+}
+
+fn parse_token<T: std::str::FromStr>(token: &str) -> io::Result<T> {
+    token
+        .parse()
+        .map_err(|_| invalid_data(&format!("invalid PFM header value '{}'", token)))
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Interleaves planes of channel values, the layout expected by `write_pfm`.
+pub fn interleave(planes: &[Vec<f32>]) -> Vec<f32> {
+    let length = planes.first().map_or(0, Vec::len);
+    let mut values = Vec::with_capacity(length * planes.len());
+    for index in 0..length {
+        for plane in planes {
+            values.push(plane[index]);
+        }
+    }
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_grayscale() {
+        let mut bytes = Vec::new();
+        write_pfm(&mut bytes, 2, 2, 1, &[1.0, 2.0, 3.0, 4.0]).unwrap();
+
+        let header = b"Pf\n2 2\n-1.0\n";
+        assert_eq!(header, &bytes[..header.len()]);
+        let data: Vec<f32> = bytes[header.len()..]
+            .chunks(4)
+            .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+        assert_eq!(vec![3.0, 4.0, 1.0, 2.0], data);
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut bytes = Vec::new();
+        write_pfm(&mut bytes, 2, 1, 3, &[1.0, 2.0, 3.0, 0.5, 0.25, 0.125]).unwrap();
+        let image = read_pfm(&mut bytes.as_slice()).unwrap();
+
+        assert_eq!(2, image.width);
+        assert_eq!(1, image.height);
+        assert_eq!(3.0, image.pixel(0, 0).z);
+        assert_eq!(0.25, image.pixel(1, 0).y);
+    }
+
+    #[test]
+    fn read_grayscale_rows() {
+        let mut bytes = Vec::new();
+        write_pfm(&mut bytes, 1, 2, 1, &[1.0, 2.0]).unwrap();
+        let image = read_pfm(&mut bytes.as_slice()).unwrap();
+
+        assert_eq!(1.0, image.pixel(0, 0).x);
+        assert_eq!(2.0, image.pixel(0, 1).z);
+    }
+
+    #[test]
+    fn read_invalid() {
+        for text in [
+            "",
+            "P6\n1 1\n-1.0\n",
+            "PF\n0 1\n-1.0\n",
+            "Pf\n1 0\n-1.0\n",
+            "PF\n18446744073709551615 18446744073709551615\n-1.0\n",
+            "Pf\n1 1\nbright\n",
+            "Pf\n2 1\n-1.0\n\0\0\0\0",
+        ] {
+            assert!(read_pfm(&mut text.as_bytes()).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn interleave_planes() {
+        let planes = vec![vec![1.0, 2.0], vec![3.0, 4.0], vec![5.0, 6.0]];
+        assert_eq!(vec![1.0, 3.0, 5.0, 2.0, 4.0, 6.0], interleave(&planes));
+    }
+}
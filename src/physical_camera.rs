@@ -0,0 +1,227 @@
+use crate::{
+    aperture::ApertureShape,
+    camera::Camera,
+    ray::Ray,
+    vec3::{Point3, Vec3},
+};
+
+/// Exposure value at ISO 100 rendered without scaling the radiance: the sunny 16 rule (f/16,
+/// 1/125 s at ISO 100) for the daylight scenes.
+pub const REFERENCE_EV100: f64 = 15.0;
+
+/// Camera settings in photographic terms. Lengths of the lens and sensor are in millimeters, the
+/// scene being in meters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Photographic {
+    pub focal_length: f64,
+    pub sensor_width: f64,
+    pub sensor_height: f64,
+    pub f_number: f64,
+    /// In seconds
+    pub shutter_time: f64,
+    pub iso: f64,
+    /// Distance to the plane in focus, that of the view when None
+    pub focus_distance: Option<f64>,
+}
+
+impl Default for Photographic {
+    fn default() -> Self {
+        Photographic::DEFAULT
+    }
+}
+
+impl Photographic {
+    /// A 50 mm lens on a full frame sensor, exposed for daylight.
+    pub const DEFAULT: Photographic = Photographic {
+        focal_length: 50.0,
+        sensor_width: 36.0,
+        sensor_height: 24.0,
+        f_number: 16.0,
+        shutter_time: 1.0 / 125.0,
+        iso: 100.0,
+        focus_distance: None,
+    };
+
+    /// Exposure value of the settings, normalized to ISO 100.
+    pub fn ev100(&self) -> f64 {
+        (self.f_number * self.f_number / self.shutter_time * 100.0 / self.iso).log2()
+    }
+
+    /// Factor applied to the radiance, each stop above `REFERENCE_EV100` halving it.
+    pub fn exposure(&self) -> f64 {
+        2.0_f64.powf(REFERENCE_EV100 - self.ev100())
+    }
+
+    /// Size of the part of the sensor covered by an image, which spans the sensor along its width
+    /// when it is wider than the sensor and along its height otherwise.
+    pub fn film_size(&self, aspect_ratio: f64) -> (f64, f64) {
+        if aspect_ratio >= self.sensor_width / self.sensor_height {
+            (self.sensor_width, self.sensor_width / aspect_ratio)
+        } else {
+            (self.sensor_height * aspect_ratio, self.sensor_height)
+        }
+    }
+
+    /// Tangents of the half fields of view across the width and height of an image.
+    pub fn half_fov_tangents(&self, aspect_ratio: f64) -> (f64, f64) {
+        let (width, height) = self.film_size(aspect_ratio);
+        (
+            width / (2.0 * self.focal_length),
+            height / (2.0 * self.focal_length),
+        )
+    }
+
+    /// Radius of the entrance pupil in meters.
+    pub fn aperture_radius(&self) -> f64 {
+        self.focal_length / self.f_number / 2.0 / 1000.0
+    }
+}
+
+/// Thin lens camera driven by photographic settings: the focal length and sensor give the field
+/// of view, the f-number the depth of field, and the f-number, shutter time and ISO the exposure.
+pub struct PhysicalCamera {
+    origin: Point3,
+    lower_left_corner: Point3,
+    horizontal: Vec3,
+    vertical: Vec3,
+    u: Vec3,
+    v: Vec3,
+    lens_radius: f64,
+    aperture_shape: ApertureShape,
+    exposure: f64,
+}
+
+impl PhysicalCamera {
+    pub fn new(
+        lookfrom: Point3,
+        lookat: Point3,
+        vup: Vec3,
+        settings: &Photographic,
+        aspect_ratio: f64,
+        focus_dist: f64,
+    ) -> PhysicalCamera {
+        let focus_dist = settings.focus_distance.unwrap_or(focus_dist);
+        let (tan_width, tan_height) = settings.half_fov_tangents(aspect_ratio);
+
+        let w = (lookfrom - lookat).unit_vector();
+        let u = (vup.cross_product(w)).unit_vector();
+        let v = w.cross_product(u);
+
+        // The image is mapped onto the plane in focus
+        let horizontal = 2.0 * focus_dist * tan_width * u;
+        let vertical = 2.0 * focus_dist * tan_height * v;
+        let lower_left_corner = lookfrom - horizontal / 2.0 - vertical / 2.0 - focus_dist * w;
+
+        PhysicalCamera {
+            origin: lookfrom,
+            lower_left_corner,
+            horizontal,
+            vertical,
+            u,
+            v,
+            lens_radius: settings.aperture_radius(),
+            aperture_shape: ApertureShape::Circular,
+            exposure: settings.exposure(),
+        }
+    }
+
+    pub fn with_aperture_shape(self, aperture_shape: ApertureShape) -> PhysicalCamera {
+        PhysicalCamera {
+            aperture_shape,
+            ..self
+        }
+    }
+}
+
+impl Camera for PhysicalCamera {
+    fn get_ray(&self, s: f64, t: f64) -> Option<Ray> {
+        let (x, y) = self.aperture_shape.sample();
+        let offset = self.lens_radius * (self.u * x + self.v * y);
+
+        Some(Ray::new(
+            self.origin + offset,
+            self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin - offset,
+        ))
+    }
+
+    fn exposure(&self) -> f64 {
+        self.exposure
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::*;
+
+    #[test]
+    fn sunny_sixteen() {
+        let settings = Photographic::default();
+        assert_relative_eq!(REFERENCE_EV100, settings.ev100(), epsilon = 0.05);
+        assert_relative_eq!(1.0, settings.exposure(), epsilon = 0.05);
+
+        // Opening two stops or doubling the ISO twice gives four times more light
+        let wider = Photographic {
+            f_number: 8.0,
+            ..settings
+        };
+        let faster = Photographic {
+            iso: 400.0,
+            ..settings
+        };
+        assert_relative_eq!(4.0 * settings.exposure(), wider.exposure(), epsilon = 1e-9);
+        assert_relative_eq!(4.0 * settings.exposure(), faster.exposure(), epsilon = 1e-9);
+    }
+
+    #[test]
+    fn field_of_view() {
+        // A 50 mm lens on a full frame sensor sees about 39.6 degrees horizontally
+        let settings = Photographic::default();
+        let (tan_width, tan_height) = settings.half_fov_tangents(1.5);
+        assert_relative_eq!(39.6, 2.0 * tan_width.atan().to_degrees(), epsilon = 0.05);
+        assert_relative_eq!(27.0, 2.0 * tan_height.atan().to_degrees(), epsilon = 0.05);
+
+        // A square image spans the height of the sensor
+        let (tan_width, tan_height) = settings.half_fov_tangents(1.0);
+        assert_relative_eq!(tan_width, tan_height);
+        assert_relative_eq!(24.0 / 100.0, tan_height);
+
+        let camera = PhysicalCamera::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            &settings,
+            1.5,
+            5.0,
+        );
+        let edge = camera.get_ray(1.0, 0.5).unwrap().direction;
+        assert_relative_eq!(18.0 / 50.0, edge.x / -edge.z, epsilon = 1e-3);
+    }
+
+    #[test]
+    fn depth_of_field() {
+        // 50 mm at f/2 is a 25 mm wide pupil
+        let settings = Photographic {
+            f_number: 2.0,
+            focus_distance: Some(3.0),
+            ..Default::default()
+        };
+        assert_relative_eq!(0.0125, settings.aperture_radius());
+
+        // Every ray through a pixel meets on the plane in focus
+        let camera = PhysicalCamera::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            &settings,
+            1.5,
+            10.0,
+        );
+        let first = camera.get_ray(0.3, 0.6).unwrap();
+        let second = camera.get_ray(0.3, 0.6).unwrap();
+        assert!((first.origin - second.origin).length() > 0.0);
+        let focus = |ray: &Ray| ray.at(-3.0 / ray.direction.z);
+        assert_relative_eq!(focus(&first).x, focus(&second).x, epsilon = 1e-9);
+        assert_relative_eq!(focus(&first).y, focus(&second).y, epsilon = 1e-9);
+    }
+}
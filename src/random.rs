@@ -0,0 +1,45 @@
+use std::cell::RefCell;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+thread_local! {
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
+
+/// Reseeds the generator used by every sampling routine, making renders reproducible.
+pub fn seed(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
+/// Returns a random number in [0, 1).
+pub fn random_double() -> f64 {
+    RNG.with(|rng| rng.borrow_mut().gen())
+}
+
+pub fn random_range(min: f64, max: f64) -> f64 {
+    RNG.with(|rng| rng.borrow_mut().gen_range(min..max))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_sequence() {
+        seed(42);
+        let first: Vec<f64> = (0..8).map(|_| random_double()).collect();
+        seed(42);
+        let second: Vec<f64> = (0..8).map(|_| random_double()).collect();
+
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn range() {
+        seed(7);
+        for _ in 0..100 {
+            let value = random_range(-2.0, 3.0);
+            assert!((-2.0..3.0).contains(&value));
+        }
+    }
+}
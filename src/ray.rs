@@ -1,13 +1,35 @@
-use crate::vec3::{Point3, Vec3};
+use crate::{
+    media_stack::MediaStack,
+    vec3::{Point3, Vec3},
+};
 
 pub struct Ray {
     pub origin: Point3,
     pub direction: Vec3,
+    /// Color channel the path is restricted to, inside media sampled differently per channel.
+    pub channel: Option<usize>,
+    /// Dielectric objects the ray travels inside of, empty in the air.
+    pub media: MediaStack,
 }
 
 impl Ray {
     pub fn new(origin: Point3, direction: Vec3) -> Ray {
-        Ray { origin, direction }
+        Ray {
+            origin,
+            direction,
+            channel: None,
+            media: MediaStack::default(),
+        }
+    }
+
+    pub fn with_channel(mut self, channel: Option<usize>) -> Ray {
+        self.channel = channel;
+        self
+    }
+
+    pub fn with_media(mut self, media: MediaStack) -> Ray {
+        self.media = media;
+        self
     }
 
     pub fn at(&self, t: f64) -> Vec3 {
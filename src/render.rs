@@ -0,0 +1,127 @@
+use crate::{
+    accumulation::AccumulationBuffer, aov::AovBuffer, color::Color, filter::Filter,
+    integrator::Integrator, random, scene::Scene,
+};
+
+pub struct RenderSettings {
+    pub image_width: usize,
+    pub image_height: usize,
+    pub samples_per_pixel: u32,
+    pub max_depth: i32,
+    pub integrator: Integrator,
+    pub filter: Filter,
+    pub record_aovs: bool,
+}
+
+pub struct Frame {
+    pub beauty: AccumulationBuffer,
+    pub aovs: Option<AovBuffer>,
+}
+
+pub fn render(scene: &Scene, settings: &RenderSettings, scene_hash: u64) -> Frame {
+    let width = settings.image_width;
+    let height = settings.image_height;
+    let mut beauty = AccumulationBuffer::new(width, height, scene_hash);
+    let mut aovs = settings.record_aovs.then(|| AovBuffer::new(width, height));
+
+    // Only radiance is exposed, the other integrators giving fractions and debugging values
+    let exposure = match settings.integrator {
+        Integrator::PathTracer => scene.camera.exposure(),
+        _ => 1.0,
+    };
+
+    for i in (0..height).rev() {
+        eprintln!("\rLines remaining: {}", i);
+        let row = height - 1 - i;
+        for j in 0..width {
+            for _ in 0..settings.samples_per_pixel {
+                let dv = random::random_double();
+                let du = random::random_double();
+                let v = (dv + i as f64) / (height - 1) as f64;
+                let u = (du + j as f64) / (width - 1) as f64;
+
+                let color = match scene.camera.get_weighted_ray(u, v) {
+                    Some((r, weight)) => {
+                        let (color, record) =
+                            settings.integrator.sample(&r, scene, settings.max_depth);
+                        if let Some(aovs) = aovs.as_mut() {
+                            aovs.add_sample(j, row, &r, record.as_ref());
+                        }
+                        exposure * weight * color
+                    }
+                    None => Color::new(0.0, 0.0, 0.0),
+                };
+
+                // Film positions are measured from the top left corner
+                let x = j as f64 + du;
+                let y = (row + 1) as f64 - dv;
+                beauty.splat(x, y, color, &settings.filter);
+            }
+        }
+    }
+
+    Frame { beauty, aovs }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{camera::Camera, integrator::DEFAULT_AO_DISTANCE, ray::Ray, scene};
+    use approx::*;
+
+    /// Camera of a scene exposed four times brighter.
+    struct Overexposed(Box<dyn Camera>);
+
+    impl Camera for Overexposed {
+        fn get_ray(&self, s: f64, t: f64) -> Option<Ray> {
+            self.0.get_ray(s, t)
+        }
+
+        fn exposure(&self) -> f64 {
+            4.0
+        }
+    }
+
+    fn render_with(integrator: Integrator, overexposed: bool) -> AccumulationBuffer {
+        let mut scene = scene::load("materials", 1.0).unwrap();
+        if overexposed {
+            scene.camera = Box::new(Overexposed(scene.camera));
+        }
+        let settings = RenderSettings {
+            image_width: 4,
+            image_height: 4,
+            samples_per_pixel: 4,
+            max_depth: 5,
+            integrator,
+            filter: Filter::Box { radius: 0.5 },
+            record_aovs: false,
+        };
+
+        random::seed(36);
+        render(&scene, &settings, 0).beauty
+    }
+
+    #[test]
+    fn exposure() {
+        // Radiance is exposed
+        let path = render_with(Integrator::PathTracer, false);
+        let exposed = render_with(Integrator::PathTracer, true);
+        assert_relative_eq!(
+            4.0 * path.pixel(1, 2).y,
+            exposed.pixel(1, 2).y,
+            max_relative = 1e-9
+        );
+
+        // but the occlusion stays a fraction
+        let ao = Integrator::AmbientOcclusion {
+            max_distance: DEFAULT_AO_DISTANCE,
+        };
+        let (plain, exposed) = (render_with(ao, false), render_with(ao, true));
+        for y in 0..4 {
+            for x in 0..4 {
+                assert_relative_eq!(plain.pixel(x, y).x, exposed.pixel(x, y).x);
+                assert!(exposed.pixel(x, y).x <= 1.0);
+            }
+        }
+    }
+}
@@ -0,0 +1,369 @@
+use crate::{
+    aperture::ApertureShape,
+    atmosphere::Atmosphere,
+    camera::{Camera, Projection, View},
+    color::Color,
+    cuboid::Cuboid,
+    distortion::Distortion,
+    environment::{Environment, SkyGradient},
+    hittable_list::HittableList,
+    material::{Conductor, Dielectric, Lambertian, Metal, CONDUCTORS},
+    medium::ConstantMedium,
+    random,
+    sphere::Sphere,
+    subsurface::Subsurface,
+    vec3::{Point3, Vec3},
+};
+
+pub struct Scene {
+    pub world: HittableList,
+    pub view: View,
+    pub camera: Box<dyn Camera>,
+    pub environment: Box<dyn Environment>,
+    pub atmosphere: Option<Atmosphere>,
+}
+
+pub const SCENES: [&str; 7] = [
+    "final",
+    "glass",
+    "materials",
+    "metals",
+    "smoke",
+    "subsurface",
+    "water",
+];
+
+/// Builds a scene from its name, one of `SCENES`.
+pub fn load(name: &str, aspect_ratio: f64) -> Option<Scene> {
+    let (world, view) = match name {
+        "final" => (final_scene(), final_view()),
+        "glass" => (glass_spheres(), smoke_view()),
+        "materials" => (material_spheres(), material_spheres_view()),
+        "metals" => (metal_spheres(), smoke_view()),
+        "smoke" => (smoke_scene(), smoke_view()),
+        "subsurface" => (subsurface_scene(), smoke_view()),
+        "water" => (water_glass(), smoke_view()),
+        _ => return None,
+    };
+
+    Some(Scene {
+        world,
+        camera: view.camera(Projection::Perspective, aspect_ratio),
+        view,
+        environment: Box::new(SkyGradient),
+        atmosphere: None,
+    })
+}
+
+/// Seed of the random sphere layout, fixed so that every render of the scene is of the same world.
+pub const SCENE_SEED: u64 = 0x5eed;
+
+pub fn final_scene() -> HittableList {
+    random::seed(SCENE_SEED);
+
+    let mut world = HittableList::new();
+
+    let ground_material = Lambertian::new(Color::new(0.5, 0.5, 0.5));
+    world.add(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        ground_material,
+    ));
+
+    for a in -11..11 {
+        for b in -11..11 {
+            let choose_mat = random::random_double();
+            let center: Point3 = Point3::new(
+                a as f64 + 0.9 * random::random_double(),
+                0.2,
+                b as f64 + 0.9 * random::random_double(),
+            );
+
+            if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.9 {
+                if choose_mat < 0.8 {
+                    let albedo = Color::random() * Color::random();
+                    let material = Lambertian::new(albedo);
+                    world.add(Sphere::new(center, 0.2, material))
+                } else if choose_mat < 0.95 {
+                    let albedo = Color::random();
+                    let fuzz = random::random_range(0.0, 0.5);
+                    let material = Metal::new(albedo, fuzz);
+                    world.add(Sphere::new(center, 0.2, material));
+                } else {
+                    let material = Dielectric::new(1.5);
+                    world.add(Sphere::new(center, 0.2, material));
+                }
+            }
+        }
+    }
+
+    let material1 = Dielectric::new(1.5);
+    let material2 = Lambertian::new(Color::new(0.4, 0.2, 0.1));
+    let material3 = Metal::new(Color::new(0.7, 0.6, 0.5), 0.0);
+
+    world.add(Sphere::new(Point3::new(0.0, 1.0, 0.0), 1.0, material1));
+    world.add(Sphere::new(Point3::new(-4.0, 1.0, 0.0), 1.0, material2));
+    world.add(Sphere::new(Point3::new(4.0, 1.0, 0.0), 1.0, material3));
+
+    world
+}
+
+pub fn final_view() -> View {
+    View {
+        lookfrom: Point3::new(13.0, 2.0, 3.0),
+        lookat: Point3::new(0.0, 0.0, 0.0),
+        vup: Vec3::new(0.0, 1.0, 0.0),
+        vfov: 120.0,
+        aperture: 0.1,
+        aperture_shape: ApertureShape::Circular,
+        focus_dist: 10.0,
+        lens: None,
+        exit_pupil_bounds: false,
+        distortion: Distortion::default(),
+    }
+}
+
+/// Small scene with one sphere of each material, quick enough to render in tests.
+pub fn material_spheres() -> HittableList {
+    let mut world = HittableList::new();
+
+    world.add(Sphere::new(
+        Point3::new(0.0, -100.5, -1.0),
+        100.0,
+        Lambertian::new(Color::new(0.8, 0.8, 0.0)),
+    ));
+    world.add(Sphere::new(
+        Point3::new(0.0, 0.0, -1.0),
+        0.5,
+        Lambertian::new(Color::new(0.1, 0.2, 0.5)),
+    ));
+    world.add(Sphere::new(
+        Point3::new(-1.0, 0.0, -1.0),
+        0.5,
+        Dielectric::new(1.5),
+    ));
+    world.add(Sphere::new(
+        Point3::new(1.0, 0.0, -1.0),
+        0.5,
+        Metal::new(Color::new(0.8, 0.6, 0.2), 0.0),
+    ));
+
+    world
+}
+
+pub fn material_spheres_view() -> View {
+    View {
+        lookfrom: Point3::new(0.0, 0.0, 1.0),
+        lookat: Point3::new(0.0, 0.0, -1.0),
+        vup: Vec3::new(0.0, 1.0, 0.0),
+        vfov: 90.0,
+        aperture: 0.0,
+        aperture_shape: ApertureShape::Circular,
+        focus_dist: 2.0,
+        lens: None,
+        exit_pupil_bounds: false,
+        distortion: Distortion::default(),
+    }
+}
+
+/// Measured conductors, polished in the front row and rough behind.
+pub fn metal_spheres() -> HittableList {
+    let mut world = HittableList::new();
+
+    world.add(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+    ));
+
+    for (z, roughness) in [(1.0, 0.05), (-1.5, 0.3)] {
+        for (i, name) in CONDUCTORS.into_iter().enumerate() {
+            let x = 2.2 * (i as f64 - 1.5);
+            let material = Conductor::preset(name, roughness).unwrap();
+            world.add(Sphere::new(Point3::new(x, 1.0, z), 1.0, material));
+        }
+    }
+
+    world
+}
+
+/// Glass spheres, from smooth to frosted, in front of colored spheres blurred through them, and
+/// spheres of tinted glass darkening as they grow thicker.
+pub fn glass_spheres() -> HittableList {
+    let mut world = HittableList::new();
+
+    world.add(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+    ));
+
+    let colors = [
+        Color::new(0.8, 0.1, 0.1),
+        Color::new(0.9, 0.7, 0.1),
+        Color::new(0.1, 0.6, 0.2),
+        Color::new(0.1, 0.3, 0.8),
+    ];
+    for (i, roughness) in [0.0, 0.05, 0.15, 0.4].into_iter().enumerate() {
+        let x = 2.2 * (i as f64 - 1.5);
+        let glass = Dielectric::new(1.5).with_roughness(roughness);
+        world.add(Sphere::new(Point3::new(x, 1.0, 1.0), 1.0, glass));
+        world.add(Sphere::new(
+            Point3::new(x, 0.5, -2.5),
+            0.5,
+            Lambertian::new(colors[i]),
+        ));
+
+        let radius = 0.2 + 0.15 * i as f64;
+        let tinted = Dielectric::new(1.5).with_transmittance(Color::new(0.3, 0.7, 0.5), 1.0);
+        world.add(Sphere::new(
+            Point3::new(0.7 * x, radius, 3.2),
+            radius,
+            tinted,
+        ));
+    }
+
+    world
+}
+
+/// Box of dark smoke and sphere of white smoke, next to a glass sphere holding a thin fog.
+pub fn smoke_scene() -> HittableList {
+    let mut world = HittableList::new();
+
+    world.add(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+    ));
+    world.add(ConstantMedium::new(
+        Cuboid::new(
+            Point3::new(-3.5, 0.0, -1.0),
+            Point3::new(-1.5, 2.0, 1.0),
+            Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+        ),
+        2.0,
+        Color::new(0.0, 0.0, 0.0),
+    ));
+    world.add(ConstantMedium::new(
+        Sphere::new(
+            Point3::new(0.0, 1.0, 0.0),
+            1.0,
+            Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+        ),
+        2.0,
+        Color::new(1.0, 1.0, 1.0),
+    ));
+    world.add(Sphere::new(
+        Point3::new(2.5, 1.0, 0.0),
+        1.0,
+        Dielectric::new(1.5),
+    ));
+    world.add(ConstantMedium::new(
+        Sphere::new(
+            Point3::new(2.5, 1.0, 0.0),
+            0.99,
+            Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+        ),
+        0.5,
+        Color::new(0.2, 0.4, 0.9),
+    ));
+
+    world
+}
+
+pub fn smoke_view() -> View {
+    View {
+        lookfrom: Point3::new(0.0, 2.5, 9.0),
+        lookat: Point3::new(0.0, 0.8, 0.0),
+        vup: Vec3::new(0.0, 1.0, 0.0),
+        // About 40 degrees at this focus distance
+        vfov: 146.0,
+        aperture: 0.0,
+        aperture_shape: ApertureShape::Circular,
+        focus_dist: 9.0,
+        lens: None,
+        exit_pupil_bounds: false,
+        distortion: Distortion::default(),
+    }
+}
+
+/// Marble, skin and wax, by random walks in the front row and by the dipole approximation behind.
+pub fn subsurface_scene() -> HittableList {
+    let mut world = HittableList::new();
+
+    world.add(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+    ));
+
+    let materials = [
+        (Color::new(0.9, 0.9, 0.85), Color::new(0.05, 0.04, 0.03)),
+        (Color::new(0.85, 0.6, 0.5), Color::new(0.1, 0.04, 0.02)),
+        (Color::new(0.9, 0.7, 0.3), Color::new(0.15, 0.1, 0.05)),
+    ];
+    for (z, dipole) in [(1.0, false), (-1.5, true)] {
+        for (i, (albedo, mean_free_path)) in materials.into_iter().enumerate() {
+            let x = 2.5 * (i as f64 - 1.0);
+            let boundary = Sphere::new(
+                Point3::new(x, 1.0, z),
+                1.0,
+                Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+            );
+            let material = Subsurface::new(boundary, albedo, mean_free_path, 1.4);
+            if dipole {
+                world.add(material.with_dipole());
+            } else {
+                world.add(material);
+            }
+        }
+    }
+
+    world
+}
+
+/// Glass of water holding a ball half under its surface, next to a bubble of water inside a glass
+/// sphere.
+///
+/// The glass is a block whose hollow is carved by air of higher priority, and the water overlaps
+/// the walls so that no gap of air is left between them, its priority being the highest.
+pub fn water_glass() -> HittableList {
+    let mut world = HittableList::new();
+
+    world.add(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+    ));
+
+    let water = || Dielectric::new(1.33).with_transmittance(Color::new(0.85, 0.95, 0.97), 1.0);
+    world.add(Cuboid::new(
+        Point3::new(-2.0, 0.0, -1.0),
+        Point3::new(0.0, 2.5, 1.0),
+        Dielectric::new(1.5).with_priority(1),
+    ));
+    world.add(Cuboid::new(
+        Point3::new(-1.85, 0.15, -0.85),
+        Point3::new(-0.15, 2.6, 0.85),
+        Dielectric::new(1.0).with_priority(2),
+    ));
+    world.add(Cuboid::new(
+        Point3::new(-1.87, 0.13, -0.87),
+        Point3::new(-0.13, 1.6, 0.87),
+        water().with_priority(3),
+    ));
+    world.add(Sphere::new(
+        Point3::new(-1.0, 1.6, 0.0),
+        0.45,
+        Lambertian::new(Color::new(0.9, 0.3, 0.1)),
+    ));
+
+    world.add(Sphere::new(
+        Point3::new(1.8, 1.0, 0.0),
+        1.0,
+        Dielectric::new(1.5),
+    ));
+    world.add(Sphere::new(Point3::new(1.8, 1.0, 0.0), 0.6, water()));
+
+    world
+}
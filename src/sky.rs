@@ -0,0 +1,306 @@
+use std::f64::consts::PI;
+
+use crate::{color::Color, environment::Environment, onb::Onb, random, vec3::Vec3};
+
+/// Sky luminances are in kcd/m², this brings a clear zenith to about 1.
+const SKY_SCALE: f64 = 0.1;
+/// Luminance of the sun outside of the atmosphere, in kcd/m².
+const SUN_LUMINANCE: f64 = 1.9e6;
+/// Angular radius of the sun seen from the earth, in degrees.
+pub const SUN_ANGULAR_RADIUS: f64 = 0.27;
+/// Wavelengths standing for the red, green and blue channels, in micrometers.
+const WAVELENGTHS: [f64; 3] = [0.65, 0.57, 0.475];
+
+/// Coefficients of the Perez sky luminance distribution.
+struct Perez {
+    a: f64,
+    b: f64,
+    c: f64,
+    d: f64,
+    e: f64,
+}
+
+impl Perez {
+    fn new(turbidity: f64, coefficients: [[f64; 2]; 5]) -> Perez {
+        let [a, b, c, d, e] = coefficients.map(|[t, constant]| t * turbidity + constant);
+        Perez { a, b, c, d, e }
+    }
+
+    /// Relative value at view zenith angle `theta` and angle to the sun `gamma`.
+    fn evaluate(&self, cos_theta: f64, gamma: f64) -> f64 {
+        let cos_gamma = gamma.cos();
+        (1.0 + self.a * (self.b / cos_theta).exp())
+            * (1.0 + self.c * (self.d * gamma).exp() + self.e * cos_gamma * cos_gamma)
+    }
+}
+
+/// Daylight sky of the Preetham, Shirley and Smits analytic model, along with a sun disk of
+/// finite angular radius which is importance sampled for direct lighting.
+pub struct PreethamSky {
+    sun_direction: Vec3,
+    sun_radiance: Color,
+    cos_sun_radius: f64,
+    intensity: f64,
+    zenith: [f64; 3],
+    perez: [Perez; 3],
+    /// Perez value at the zenith, normalizing the distributions
+    zenith_perez: [f64; 3],
+}
+
+impl PreethamSky {
+    /// Angles are in degrees, the azimuth turning from -z towards +x.
+    pub fn new(
+        sun_elevation: f64,
+        sun_azimuth: f64,
+        turbidity: f64,
+        sun_angular_radius: f64,
+        intensity: f64,
+    ) -> PreethamSky {
+        let elevation = sun_elevation.to_radians();
+        let azimuth = sun_azimuth.to_radians();
+        let sun_direction = Vec3::new(
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+            -elevation.cos() * azimuth.cos(),
+        );
+
+        // Zenith angle of the sun, kept above the horizon where the model is defined
+        let theta_s = (PI / 2.0 - elevation).clamp(0.0, PI / 2.0 - 1e-3);
+        let t = turbidity;
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+
+        let polynomial = |c: [[f64; 4]; 3]| {
+            let angles = [theta_s.powi(3), theta_s.powi(2), theta_s, 1.0];
+            let row = |r: [f64; 4]| r.iter().zip(angles).map(|(c, a)| c * a).sum::<f64>();
+            t * t * row(c[0]) + t * row(c[1]) + row(c[2])
+        };
+        let zenith_x = polynomial([
+            [0.00166, -0.00375, 0.00209, 0.0],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        ]);
+        let zenith_y = polynomial([
+            [0.00275, -0.00610, 0.00317, 0.0],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        ]);
+
+        let perez = [
+            Perez::new(
+                t,
+                [
+                    [0.1787, -1.4630],
+                    [-0.3554, 0.4275],
+                    [-0.0227, 5.3251],
+                    [0.1206, -2.5771],
+                    [-0.0670, 0.3703],
+                ],
+            ),
+            Perez::new(
+                t,
+                [
+                    [-0.0193, -0.2592],
+                    [-0.0665, 0.0008],
+                    [-0.0004, 0.2125],
+                    [-0.0641, -0.8989],
+                    [-0.0033, 0.0452],
+                ],
+            ),
+            Perez::new(
+                t,
+                [
+                    [-0.0167, -0.2608],
+                    [-0.0950, 0.0092],
+                    [-0.0079, 0.2102],
+                    [-0.0441, -1.6537],
+                    [-0.0109, 0.0529],
+                ],
+            ),
+        ];
+        let zenith_perez = [0, 1, 2].map(|i| perez[i].evaluate(1.0, theta_s));
+
+        // Keep the irradiance of the sun when its disk is enlarged
+        let cos_sun_radius = sun_angular_radius.to_radians().cos();
+        let solid_angle_ratio =
+            (1.0 - SUN_ANGULAR_RADIUS.to_radians().cos()) / (1.0 - cos_sun_radius).max(1e-12);
+        let sun_radiance = if sun_elevation > -sun_angular_radius {
+            SUN_LUMINANCE * SKY_SCALE * solid_angle_ratio * sun_transmittance(theta_s, turbidity)
+        } else {
+            Color::new(0.0, 0.0, 0.0)
+        };
+
+        PreethamSky {
+            sun_direction,
+            sun_radiance,
+            cos_sun_radius,
+            intensity,
+            zenith: [zenith_luminance.max(0.0), zenith_x, zenith_y],
+            perez,
+            zenith_perez,
+        }
+    }
+
+    /// Radiance of the sun disk, already attenuated by the atmosphere.
+    pub fn sun_radiance(&self) -> Color {
+        self.intensity * self.sun_radiance
+    }
+
+    fn sun_probability(&self) -> f64 {
+        if self.sun_radiance.near_zero() {
+            0.0
+        } else {
+            0.5
+        }
+    }
+
+    fn sky_radiance(&self, direction: Vec3) -> Color {
+        // The model only covers the upper hemisphere, the horizon is extended below it
+        let cos_theta = direction.y.max(0.01);
+        let gamma = direction.dot(self.sun_direction).clamp(-1.0, 1.0).acos();
+
+        let [luminance, x, y] = [0, 1, 2].map(|i| {
+            self.zenith[i] * self.perez[i].evaluate(cos_theta, gamma) / self.zenith_perez[i]
+        });
+
+        self.intensity * SKY_SCALE * xyy_to_rgb(x, y, luminance)
+    }
+}
+
+impl Environment for PreethamSky {
+    fn radiance(&self, direction: Vec3) -> Color {
+        let mut radiance = self.sky_radiance(direction);
+        if direction.dot(self.sun_direction) >= self.cos_sun_radius {
+            radiance = radiance + self.sun_radiance();
+        }
+        radiance
+    }
+
+    fn sample(&self) -> Option<(Vec3, Color, f64)> {
+        let direction = if random::random_double() < self.sun_probability() {
+            // Uniform direction in the cone of the sun disk
+            let cos_theta = 1.0 - random::random_double() * (1.0 - self.cos_sun_radius);
+            let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+            let phi = 2.0 * PI * random::random_double();
+            Onb::new(self.sun_direction).local(
+                sin_theta * phi.cos(),
+                sin_theta * phi.sin(),
+                cos_theta,
+            )
+        } else {
+            // Cosine weighted direction of the upper hemisphere
+            let r = random::random_double().sqrt();
+            let phi = 2.0 * PI * random::random_double();
+            let z = (1.0 - r * r).max(0.0).sqrt();
+            Onb::new(Vec3::new(0.0, 1.0, 0.0)).local(r * phi.cos(), r * phi.sin(), z)
+        };
+
+        let pdf = self.pdf(direction);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some((direction, self.radiance(direction), pdf))
+    }
+
+    fn pdf(&self, direction: Vec3) -> f64 {
+        let sun_probability = self.sun_probability();
+        let mut pdf = (1.0 - sun_probability) * direction.y.max(0.0) / PI;
+        if direction.dot(self.sun_direction) >= self.cos_sun_radius {
+            pdf += sun_probability / (2.0 * PI * (1.0 - self.cos_sun_radius));
+        }
+        pdf
+    }
+}
+
+/// Fraction of the sunlight reaching the ground through Rayleigh and aerosol scattering, for a
+/// sun at zenith angle `theta_s` in radians.
+pub fn sun_transmittance(theta_s: f64, turbidity: f64) -> Color {
+    let relative_optical_mass =
+        1.0 / (theta_s.cos() + 0.15 * (93.885 - theta_s.to_degrees()).powf(-1.253));
+    let beta = 0.04608 * turbidity - 0.04586;
+
+    let [r, g, b] = WAVELENGTHS.map(|lambda: f64| {
+        let rayleigh = (-relative_optical_mass * 0.008735 * lambda.powf(-4.08)).exp();
+        let aerosol = (-relative_optical_mass * beta * lambda.powf(-1.3)).exp();
+        rayleigh * aerosol
+    });
+
+    Color::new(r, g, b)
+}
+
+/// Converts a CIE xyY color to linear sRGB.
+pub fn xyy_to_rgb(x: f64, y: f64, luminance: f64) -> Color {
+    if y <= 0.0 {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+    let big_x = x * luminance / y;
+    let big_z = (1.0 - x - y) * luminance / y;
+
+    Color::new(
+        3.2406 * big_x - 1.5372 * luminance - 0.4986 * big_z,
+        -0.9689 * big_x + 1.8758 * luminance + 0.0415 * big_z,
+        0.0557 * big_x - 0.2040 * luminance + 1.0570 * big_z,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::environment::luminance;
+    use approx::*;
+
+    #[test]
+    fn white_point() {
+        // The D65 white point gives equal channels
+        let color = xyy_to_rgb(0.3127, 0.3290, 1.0);
+        assert_relative_eq!(1.0, color.x, epsilon = 0.01);
+        assert_relative_eq!(1.0, color.y, epsilon = 0.01);
+        assert_relative_eq!(1.0, color.z, epsilon = 0.01);
+    }
+
+    #[test]
+    fn brighter_towards_the_sun() {
+        let sky = PreethamSky::new(30.0, 0.0, 3.0, SUN_ANGULAR_RADIUS, 1.0);
+        let towards = sky.sky_radiance(Vec3::new(0.0, 0.6, -1.0).unit_vector());
+        let away = sky.sky_radiance(Vec3::new(0.0, 0.6, 1.0).unit_vector());
+
+        assert!(luminance(towards) > luminance(away));
+        // A clear sky is blue away from the sun
+        assert!(away.z > away.x);
+    }
+
+    #[test]
+    fn sunset_is_redder() {
+        let noon = PreethamSky::new(80.0, 0.0, 3.0, SUN_ANGULAR_RADIUS, 1.0).sun_radiance();
+        let sunset = PreethamSky::new(3.0, 0.0, 3.0, SUN_ANGULAR_RADIUS, 1.0).sun_radiance();
+
+        assert!(sunset.x / sunset.z > noon.x / noon.z);
+        assert!(luminance(sunset) < luminance(noon));
+    }
+
+    #[test]
+    fn sun_below_horizon() {
+        let sky = PreethamSky::new(-10.0, 0.0, 3.0, SUN_ANGULAR_RADIUS, 1.0);
+        assert!(sky.sun_radiance().near_zero());
+        assert_eq!(0.0, sky.sun_probability());
+    }
+
+    #[test]
+    fn sample_pdf() {
+        let sky = PreethamSky::new(45.0, 60.0, 2.5, 2.0, 1.0);
+        random::seed(11);
+
+        let mut sun_samples = 0;
+        for _ in 0..200 {
+            let (direction, radiance, pdf) = sky.sample().unwrap();
+            assert_relative_eq!(pdf, sky.pdf(direction), max_relative = 1e-9);
+            assert_relative_eq!(luminance(radiance), luminance(sky.radiance(direction)));
+            if direction.dot(sky.sun_direction) >= sky.cos_sun_radius {
+                sun_samples += 1;
+            }
+        }
+
+        assert!(sun_samples > 70 && sun_samples < 130);
+    }
+}
@@ -1,3 +1,5 @@
+use std::f64::consts::PI;
+
 use crate::{
     hittable::{HitRecord, Hittable},
     material::Material,
@@ -21,7 +23,7 @@ impl<T: Material> Sphere<T> {
 }
 
 impl<T: Material> Hittable for Sphere<T> {
-    fn hit(&self, ray: &crate::ray::Ray, t_max: f64, t_min: f64) -> Option<HitRecord> {
+    fn hit(&self, ray: &crate::ray::Ray, t_max: f64, t_min: f64) -> Option<HitRecord<'_>> {
         let oc: Vec3 = ray.origin - self.center;
         let a = ray.direction.length_squared();
         let half_b = oc.dot(ray.direction);
@@ -45,15 +47,39 @@ impl<T: Material> Hittable for Sphere<T> {
         let outward_normal = (ray.at(root) - self.center) / self.radius;
         let front_face = ray.direction.dot(outward_normal) < 0.0;
 
-        let rec = HitRecord::new(
+        let mut rec = HitRecord::new(
             ray.at(root),
             outward_normal,
             root,
             front_face,
             &self.material,
         );
+        (rec.u, rec.v) = Self::uv(outward_normal);
+
+        Some(rec)
+    }
+
+    fn surface_point(&self, u: f64, v: f64) -> Option<(Point3, Vec3)> {
+        let theta = v * PI;
+        let phi = u * 2.0 * PI;
+        let normal = Vec3::new(
+            -phi.cos() * theta.sin(),
+            -theta.cos(),
+            phi.sin() * theta.sin(),
+        );
+
+        Some((self.center + self.radius * normal, normal))
+    }
+}
+
+impl<T: Material> Sphere<T> {
+    /// Coordinates of a point of the unit sphere, u going around the y axis from x = -1 and v
+    /// from the bottom pole to the top one.
+    fn uv(p: Point3) -> (f64, f64) {
+        let theta = (-p.y).clamp(-1.0, 1.0).acos();
+        let phi = (-p.z).atan2(p.x) + PI;
 
-        return Some(rec);
+        (phi / (2.0 * PI), theta / PI)
     }
 }
 
@@ -141,6 +167,33 @@ mod tests {
         }
     }
 
+    #[test]
+    fn uv() {
+        let cases = [
+            (Point3::new(1.0, 0.0, 0.0), (0.5, 0.5)),
+            (Point3::new(0.0, 1.0, 0.0), (0.5, 1.0)),
+            (Point3::new(0.0, -1.0, 0.0), (0.5, 0.0)),
+            (Point3::new(-1.0, 0.0, 0.0), (0.0, 0.5)),
+            (Point3::new(0.0, 0.0, 1.0), (0.25, 0.5)),
+            (Point3::new(0.0, 0.0, -1.0), (0.75, 0.5)),
+        ];
+
+        for (point, (u, v)) in cases {
+            let result = Sphere::<Lambertian>::uv(point);
+            assert_relative_eq!(u, result.0, epsilon = 0.0001);
+            assert_relative_eq!(v, result.1, epsilon = 0.0001);
+
+            let sphere = Sphere::new(
+                Point3::new(1.0, 2.0, 3.0),
+                2.0,
+                Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+            );
+            let (p, normal) = sphere.surface_point(u, v).unwrap();
+            assert_vec3_equal!(point, normal);
+            assert_vec3_equal!(Point3::new(1.0, 2.0, 3.0) + 2.0 * point, p);
+        }
+    }
+
     #[test]
     fn hit_none_range() {
         let origin = Point3::new(0.0, 0.0, 0.0);
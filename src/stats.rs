@@ -0,0 +1,14 @@
+use std::cell::Cell;
+
+thread_local! {
+    static INTERSECTION_TESTS: Cell<u64> = const { Cell::new(0) };
+}
+
+pub fn count_intersection_test() {
+    INTERSECTION_TESTS.with(|tests| tests.set(tests.get() + 1));
+}
+
+/// Number of ray-object intersection tests made by the current thread so far.
+pub fn intersection_tests() -> u64 {
+    INTERSECTION_TESTS.with(|tests| tests.get())
+}
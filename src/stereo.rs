@@ -0,0 +1,238 @@
+use crate::{
+    camera::{Camera, Projection, View},
+    color::Color,
+    ray::Ray,
+    vec3::Vec3,
+};
+
+/// Average distance between the eyes of an adult, in meters.
+pub const DEFAULT_INTERPUPILLARY_DISTANCE: f64 = 0.064;
+
+/// How the images of both eyes share the output.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StereoLayout {
+    /// Left eye on the left half
+    SideBySide,
+    /// Left eye on the top half
+    TopBottom,
+}
+
+impl StereoLayout {
+    pub const ALL: [StereoLayout; 2] = [StereoLayout::SideBySide, StereoLayout::TopBottom];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            StereoLayout::SideBySide => "side-by-side",
+            StereoLayout::TopBottom => "top-bottom",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<StereoLayout> {
+        StereoLayout::ALL
+            .into_iter()
+            .find(|layout| layout.name() == name)
+    }
+}
+
+/// Pair of eyes around a view. Planar projections move the eyes along the right axis of the
+/// camera, while panoramic ones move them on a circle, perpendicular to the horizontal direction
+/// of every ray, which gives omni-directional stereo with the equirectangular projection.
+///
+/// Rays of both eyes meet at the convergence distance, objects there appearing at the depth of
+/// the screen. An infinite distance keeps the eyes parallel.
+pub struct StereoCamera {
+    camera: Box<dyn Camera>,
+    layout: StereoLayout,
+    omnidirectional: bool,
+    half_distance: f64,
+    convergence_distance: f64,
+    right: Vec3,
+    up: Vec3,
+    forward: Vec3,
+}
+
+impl StereoCamera {
+    pub fn new(
+        view: &View,
+        projection: Projection,
+        aspect_ratio: f64,
+        layout: StereoLayout,
+        interpupillary_distance: f64,
+        convergence_distance: f64,
+    ) -> StereoCamera {
+        let eye_aspect_ratio = match layout {
+            StereoLayout::SideBySide => aspect_ratio / 2.0,
+            StereoLayout::TopBottom => aspect_ratio * 2.0,
+        };
+
+        let w = (view.lookfrom - view.lookat).unit_vector();
+        let u = (view.vup.cross_product(w)).unit_vector();
+        let v = w.cross_product(u);
+
+        StereoCamera {
+            camera: view.camera(projection, eye_aspect_ratio),
+            layout,
+            omnidirectional: matches!(projection, Projection::Panoramic(_)),
+            half_distance: interpupillary_distance / 2.0,
+            convergence_distance,
+            right: u,
+            up: v,
+            forward: -w,
+        }
+    }
+
+    /// Direction in which the eyes are apart when looking along `direction`.
+    fn eye_axis(&self, direction: Vec3) -> Vec3 {
+        if !self.omnidirectional {
+            return self.right;
+        }
+
+        let horizontal = direction - direction.dot(self.up) * self.up;
+        if horizontal.near_zero() {
+            self.right
+        } else {
+            horizontal.unit_vector().cross_product(self.up)
+        }
+    }
+}
+
+impl Camera for StereoCamera {
+    fn get_ray(&self, s: f64, t: f64) -> Option<Ray> {
+        self.get_weighted_ray(s, t).map(|(ray, _)| ray)
+    }
+
+    fn get_weighted_ray(&self, s: f64, t: f64) -> Option<(Ray, Color)> {
+        // Coordinates within the half of the image of an eye, -1 for the left one
+        let (side, s, t) = match self.layout {
+            StereoLayout::SideBySide if s < 0.5 => (-1.0, 2.0 * s, t),
+            StereoLayout::SideBySide => (1.0, 2.0 * s - 1.0, t),
+            StereoLayout::TopBottom if t >= 0.5 => (-1.0, s, 2.0 * t - 1.0),
+            StereoLayout::TopBottom => (1.0, s, 2.0 * t),
+        };
+
+        let (ray, weight) = self.camera.get_weighted_ray(s, t)?;
+        let origin = ray.origin + side * self.half_distance * self.eye_axis(ray.direction);
+        if self.convergence_distance.is_infinite() {
+            return Some((Ray::new(origin, ray.direction), weight));
+        }
+
+        // Point of the ray of the center eye at the convergence distance, measured along the
+        // view direction for planar projections and along the ray for panoramic ones
+        let depth = if self.omnidirectional {
+            ray.direction.length()
+        } else {
+            ray.direction.dot(self.forward)
+        };
+        let target = ray.at(self.convergence_distance / depth);
+
+        Some((Ray::new(origin, target - origin), weight))
+    }
+
+    fn exposure(&self) -> f64 {
+        self.camera.exposure()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        aperture::ApertureShape, distortion::Distortion, panoramic::Panorama, vec3::Point3,
+    };
+    use approx::*;
+
+    macro_rules! assert_vec3_equal {
+        ($expected:expr, $actual:expr) => {
+            let tolerance = 0.0001;
+            assert_relative_eq!($expected.x, $actual.x, epsilon = tolerance);
+            assert_relative_eq!($expected.y, $actual.y, epsilon = tolerance);
+            assert_relative_eq!($expected.z, $actual.z, epsilon = tolerance);
+        };
+    }
+
+    fn view() -> View {
+        View {
+            lookfrom: Point3::new(0.0, 0.0, 0.0),
+            lookat: Point3::new(0.0, 0.0, -1.0),
+            vup: Vec3::new(0.0, 1.0, 0.0),
+            vfov: 90.0,
+            aperture: 0.0,
+            aperture_shape: ApertureShape::Circular,
+            focus_dist: 1.0,
+            lens: None,
+            exit_pupil_bounds: false,
+            distortion: Distortion::default(),
+        }
+    }
+
+    #[test]
+    fn side_by_side_converges() {
+        let camera = StereoCamera::new(
+            &view(),
+            Projection::Perspective,
+            2.0,
+            StereoLayout::SideBySide,
+            0.1,
+            4.0,
+        );
+
+        // Centers of both halves look at the same point at the convergence distance
+        let left = camera.get_ray(0.25, 0.5).unwrap();
+        let right = camera.get_ray(0.75, 0.5).unwrap();
+        assert_vec3_equal!(Point3::new(-0.05, 0.0, 0.0), left.origin);
+        assert_vec3_equal!(Point3::new(0.05, 0.0, 0.0), right.origin);
+        assert_vec3_equal!(Point3::new(0.0, 0.0, -4.0), left.at(1.0));
+        assert_vec3_equal!(Point3::new(0.0, 0.0, -4.0), right.at(1.0));
+
+        // Every eye keeps the field of view of the camera with a square half
+        let top = camera.get_ray(0.25, 1.0).unwrap().direction;
+        assert_relative_eq!(1.0, top.y / -top.z, epsilon = 0.05);
+    }
+
+    #[test]
+    fn parallel_eyes() {
+        let camera = StereoCamera::new(
+            &view(),
+            Projection::Perspective,
+            0.5,
+            StereoLayout::TopBottom,
+            0.1,
+            f64::INFINITY,
+        );
+
+        let left = camera.get_ray(0.5, 0.75).unwrap();
+        let right = camera.get_ray(0.5, 0.25).unwrap();
+        assert_vec3_equal!(Point3::new(-0.05, 0.0, 0.0), left.origin);
+        assert_vec3_equal!(Point3::new(0.05, 0.0, 0.0), right.origin);
+        assert_vec3_equal!(left.direction, right.direction);
+    }
+
+    #[test]
+    fn omnidirectional_stereo() {
+        let camera = StereoCamera::new(
+            &view(),
+            Projection::Panoramic(Panorama::Equirectangular),
+            1.0,
+            StereoLayout::TopBottom,
+            0.1,
+            f64::INFINITY,
+        );
+
+        // Looking ahead the eyes are apart along x, looking right along z
+        let ahead = camera.get_ray(0.5, 0.75).unwrap();
+        assert_vec3_equal!(Point3::new(-0.05, 0.0, 0.0), ahead.origin);
+        let right = camera.get_ray(0.75, 0.75).unwrap();
+        assert_vec3_equal!(Vec3::new(1.0, 0.0, 0.0), right.direction);
+        assert_vec3_equal!(Point3::new(0.0, 0.0, -0.05), right.origin);
+        let behind = camera.get_ray(0.0, 0.25).unwrap();
+        assert_vec3_equal!(Point3::new(-0.05, 0.0, 0.0), behind.origin);
+    }
+
+    #[test]
+    fn parse_names() {
+        for layout in StereoLayout::ALL {
+            assert_eq!(Some(layout), StereoLayout::parse(layout.name()));
+        }
+        assert_eq!(None, StereoLayout::parse("anaglyph"));
+    }
+}
@@ -0,0 +1,514 @@
+use std::f64::consts::PI;
+
+use crate::{
+    color::Color,
+    hittable::{HitRecord, Hittable},
+    material::{Dielectric, Material},
+    onb::Onb,
+    random,
+    ray::Ray,
+    vec3::{Point3, Vec3},
+};
+
+/// Radius beyond which the diffusion profile is cut, in diffusion lengths of the most
+/// translucent channel.
+const PROFILE_CUTOFF: f64 = 16.0;
+/// Largest number of crossings of the boundary considered along a probe ray.
+const MAX_PROBE_CROSSINGS: usize = 8;
+
+fn map(color: Color, f: impl Fn(f64) -> f64) -> Color {
+    Color::new(f(color.x), f(color.y), f(color.z))
+}
+
+fn zip(a: Color, b: Color, f: impl Fn(f64, f64) -> f64) -> Color {
+    Color::new(f(a.x, b.x), f(a.y, b.y), f(a.z, b.z))
+}
+
+fn mean(color: Color) -> f64 {
+    (color.x + color.y + color.z) / 3.0
+}
+
+fn channel(color: Color, index: usize) -> f64 {
+    match index {
+        0 => color.x,
+        1 => color.y,
+        _ => color.z,
+    }
+}
+
+/// Translucent material scattering light below its surface, like skin, wax, marble or milk.
+///
+/// Light refracts through a smooth boundary, then either walks randomly through the volume it
+/// encloses, which must be closed, until it leaves it, or with the dipole fast path leaves the
+/// surface right away at a point sampled from a diffusion profile.
+pub struct Subsurface<H: Hittable> {
+    boundary: H,
+    albedo: Color,
+    interface: Dielectric,
+    walk: RandomWalk,
+    dipole: Option<Dipole>,
+}
+
+impl<H: Hittable> Subsurface<H> {
+    /// `albedo` is the color of the material seen from afar, after any number of scattering
+    /// events, and `mean_free_path` the average distance light travels between them for each
+    /// channel, in scene units. The material of the boundary is ignored.
+    pub fn new(boundary: H, albedo: Color, mean_free_path: Color, ior: f64) -> Subsurface<H> {
+        let albedo = map(albedo, |a| a.clamp(0.0, 0.999));
+        let extinction = map(mean_free_path, |distance| 1.0 / distance.max(1e-6));
+        Subsurface {
+            boundary,
+            albedo,
+            interface: Dielectric::new(ior),
+            walk: RandomWalk {
+                extinction,
+                scattering: zip(extinction, albedo, |sigma, a| {
+                    sigma * single_scattering_albedo(a)
+                }),
+            },
+            dipole: None,
+        }
+    }
+
+    /// Replaces the random walk with the dipole diffusion approximation, much faster for dense
+    /// materials but assuming the surface flat on the scale of the mean free path.
+    pub fn with_dipole(mut self) -> Subsurface<H> {
+        self.dipole = Some(Dipole::new(
+            self.albedo,
+            map(self.walk.extinction, |sigma| 1.0 / sigma),
+            self.interface.ir,
+        ));
+        self
+    }
+
+    /// Samples the point where light entering at `record` leaves the surface, by probing the
+    /// boundary along one of three axes around the normal, with its outward normal and the
+    /// weight of the sample.
+    fn sample_exit(&self, record: &HitRecord, dipole: &Dipole) -> Option<(Point3, Vec3, Color)> {
+        let frame = Onb::new(record.geometric_normal);
+        let axes = [(frame.w, 0.5), (frame.u, 0.25), (frame.v, 0.25)];
+        let xi = random::random_double();
+        let (axis, a, b) = if xi < 0.5 {
+            (frame.w, frame.u, frame.v)
+        } else if xi < 0.75 {
+            (frame.u, frame.v, frame.w)
+        } else {
+            (frame.v, frame.w, frame.u)
+        };
+
+        let radius = dipole.sample_radius();
+        let phi = 2.0 * PI * random::random_double();
+        let height = (dipole.max_radius * dipole.max_radius - radius * radius).sqrt();
+        let probe = Ray::new(
+            record.p + radius * (phi.cos() * a + phi.sin() * b) + height * axis,
+            -axis,
+        );
+        // Any of the crossings of the boundary along the probe
+        let mut crossings = Vec::new();
+        let mut t_min = 0.0;
+        while crossings.len() < MAX_PROBE_CROSSINGS {
+            match self.boundary.hit(&probe, 2.0 * height, t_min) {
+                Some(crossing) => {
+                    t_min = crossing.t + 0.0001;
+                    crossings.push(crossing);
+                }
+                None => break,
+            }
+        }
+        if crossings.is_empty() {
+            return None;
+        }
+        let index =
+            ((random::random_double() * crossings.len() as f64) as usize).min(crossings.len() - 1);
+        let exit = crossings[index];
+
+        // Density of reaching the exit point along any of the axes
+        let offset = exit.p - record.p;
+        let pdf: f64 = axes
+            .iter()
+            .map(|(axis, probability)| {
+                let along = offset.dot(*axis);
+                let planar = (offset.length_squared() - along * along).max(0.0).sqrt();
+                probability * dipole.radius_pdf(planar) * exit.geometric_normal.dot(*axis).abs()
+            })
+            .sum();
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some((
+            exit.p,
+            exit.geometric_normal,
+            crossings.len() as f64 * dipole.profile(offset.length()) / pdf,
+        ))
+    }
+}
+
+impl<H: Hittable> Hittable for Subsurface<H> {
+    fn hit(&self, ray: &Ray, t_max: f64, t_min: f64) -> Option<HitRecord<'_>> {
+        let mut record = self.boundary.hit(ray, f64::INFINITY, t_min)?;
+
+        if self.dipole.is_none() && !record.front_face {
+            // From inside, the walk scatters before reaching the boundary or crosses it
+            let t = (self.walk.sample_distance(ray.channel) / ray.direction.length()).max(t_min);
+            if t < record.t {
+                if t > t_max {
+                    return None;
+                }
+                let mut event =
+                    HitRecord::new(ray.at(t), Vec3::new(1.0, 0.0, 0.0), t, true, &self.walk);
+                // Phase functions do not depend on the normal, which only faces the ray for the AOVs
+                event.normal = -ray.direction.unit_vector();
+                return Some(event);
+            }
+        }
+
+        if record.t > t_max {
+            return None;
+        }
+        record.material = self;
+        Some(record)
+    }
+}
+
+impl<H: Hittable> Material for Subsurface<H> {
+    fn scatter(&self, r_in: &Ray, hit_record: HitRecord) -> (Color, Ray, bool) {
+        let (_, interface_ray, _) = self.interface.scatter(r_in, hit_record);
+        let reflected = interface_ray.direction.dot(hit_record.normal) > 0.0;
+
+        if !hit_record.front_face {
+            // The walk reaches the boundary, refracting out of it or reflecting back inside
+            let distance = hit_record.t * r_in.direction.length();
+            let weight = self.walk.crossing_weight(distance, r_in.channel);
+            let channel = if reflected { r_in.channel } else { None };
+            return (weight, interface_ray.with_channel(channel), true);
+        }
+        if reflected {
+            return (Color::new(1.0, 1.0, 1.0), interface_ray, true);
+        }
+
+        match &self.dipole {
+            Some(dipole) => match self.sample_exit(&hit_record, dipole) {
+                Some((p, normal, weight)) => {
+                    let mut direction = normal + Vec3::random_unit_vector();
+                    if direction.near_zero() {
+                        direction = normal;
+                    }
+                    (weight, Ray::new(p, direction), true)
+                }
+                None => (Color::new(0.0, 0.0, 0.0), interface_ray, false),
+            },
+            None => {
+                // A single channel keeps the weights of long walks bounded
+                let (channel, weight) = Color::random_channel();
+                (weight, interface_ray.with_channel(Some(channel)), true)
+            }
+        }
+    }
+
+    fn albedo(&self) -> Color {
+        self.albedo
+    }
+}
+
+/// Single scattering albedo whose random walk through a semi-infinite slab gives the
+/// multiple scattering `albedo`, from the fit of Chiang, Kutz and Burley.
+fn single_scattering_albedo(albedo: f64) -> f64 {
+    let s = 4.09712 + 4.20863 * albedo
+        - (9.59217 + 41.6808 * albedo + 17.7126 * albedo * albedo).sqrt();
+    1.0 - s * s
+}
+
+/// Isotropic scattering inside the volume. Paths entering it follow a single channel, while
+/// others sample distances along a channel picked at random and weigh them for all channels.
+struct RandomWalk {
+    extinction: Color,
+    scattering: Color,
+}
+
+impl RandomWalk {
+    fn transmittance(&self, distance: f64) -> Color {
+        map(self.extinction, |sigma| (-sigma * distance).exp())
+    }
+
+    fn sample_distance(&self, path_channel: Option<usize>) -> f64 {
+        let index = path_channel.unwrap_or_else(|| Color::random_channel().0);
+        -(1.0 - random::random_double()).ln() / channel(self.extinction, index)
+    }
+
+    /// Weight of a path crossing `distance` without scattering.
+    fn crossing_weight(&self, distance: f64, path_channel: Option<usize>) -> Color {
+        if path_channel.is_some() {
+            return Color::new(1.0, 1.0, 1.0);
+        }
+        let transmittance = self.transmittance(distance);
+        transmittance / mean(transmittance)
+    }
+}
+
+impl Material for RandomWalk {
+    fn scatter(&self, r_in: &Ray, hit_record: HitRecord) -> (Color, Ray, bool) {
+        let weight = match r_in.channel {
+            Some(_) => self.albedo(),
+            None => {
+                let transmittance = self.transmittance(hit_record.t * r_in.direction.length());
+                self.scattering * transmittance / mean(self.extinction * transmittance)
+            }
+        };
+        let scattered =
+            Ray::new(hit_record.p, Vec3::random_unit_vector()).with_channel(r_in.channel);
+
+        // Russian roulette ends the walk, which the maximum depth leaves unbounded
+        let survival = match r_in.channel {
+            Some(index) => channel(weight, index),
+            None => weight.x.max(weight.y).max(weight.z),
+        }
+        .min(1.0);
+        if random::random_double() >= survival {
+            return (Color::new(0.0, 0.0, 0.0), scattered, false);
+        }
+        (weight / survival, scattered, true)
+    }
+
+    fn counts_as_bounce(&self) -> bool {
+        false
+    }
+
+    fn albedo(&self) -> Color {
+        self.scattering / self.extinction
+    }
+}
+
+/// Diffusion profile of light entering a semi-infinite medium, from a real and a virtual point
+/// source (Jensen et al.).
+struct Dipole {
+    reduced_albedo: Color,
+    transport: Color,
+    z_real: Color,
+    z_virtual: Color,
+    max_radius: f64,
+}
+
+impl Dipole {
+    fn new(albedo: Color, mean_free_path: Color, ior: f64) -> Dipole {
+        // Internal reflection at the boundary, from the diffuse Fresnel reflectance
+        let fdr = -1.440 / (ior * ior) + 0.710 / ior + 0.668 + 0.0636 * ior;
+        let a = (1.0 + fdr) / (1.0 - fdr);
+
+        let reduced_albedo = map(albedo, |albedo| invert_reflectance(albedo, a));
+        let extinction = map(mean_free_path, |distance| 1.0 / distance);
+        let transport = zip(extinction, reduced_albedo, |sigma, alpha| {
+            (3.0 * (1.0 - alpha) * sigma * sigma).sqrt()
+        });
+        let z_real = map(extinction, |sigma| 1.0 / sigma);
+        let z_virtual = map(extinction, |sigma| (1.0 + 4.0 * a / 3.0) / sigma);
+        let min_transport = transport.x.min(transport.y).min(transport.z);
+
+        Dipole {
+            reduced_albedo,
+            transport,
+            z_real,
+            z_virtual,
+            max_radius: PROFILE_CUTOFF / min_transport,
+        }
+    }
+
+    /// Radiant exitance at `radius` from the entry point, per unit of entering flux.
+    fn profile(&self, radius: f64) -> Color {
+        let source = |z: f64, sigma: f64| {
+            let d = (radius * radius + z * z).sqrt();
+            z * (1.0 + sigma * d) * (-sigma * d).exp() / (d * d * d)
+        };
+        let real = zip(self.z_real, self.transport, source);
+        let virtual_source = zip(self.z_virtual, self.transport, source);
+        self.reduced_albedo * (real + virtual_source) / (4.0 * PI)
+    }
+
+    /// Samples a radius from the exponential falloff of a channel picked at random.
+    fn sample_radius(&self) -> f64 {
+        let (index, _) = Color::random_channel();
+        let sigma = channel(self.transport, index);
+        let cut = 1.0 - (-sigma * self.max_radius).exp();
+        -(1.0 - random::random_double() * cut).ln() / sigma
+    }
+
+    /// Area density of sampling a point at `radius` on the plane of the probe.
+    fn radius_pdf(&self, radius: f64) -> f64 {
+        if radius <= 0.0 || radius >= self.max_radius {
+            return 0.0;
+        }
+        mean(map(self.transport, |sigma| {
+            let cut = 1.0 - (-sigma * self.max_radius).exp();
+            sigma * (-sigma * radius).exp() / (cut * 2.0 * PI * radius)
+        }))
+    }
+}
+
+/// Fraction of the entering light leaving a semi-infinite medium of reduced albedo `alpha`, `a`
+/// accounting for internal reflection.
+fn total_reflectance(alpha: f64, a: f64) -> f64 {
+    let root = (3.0 * (1.0 - alpha)).sqrt();
+    0.5 * alpha * (1.0 + (-4.0 / 3.0 * a * root).exp()) * (-root).exp()
+}
+
+/// Reduced albedo of a medium of total reflectance `albedo`, by bisection.
+fn invert_reflectance(albedo: f64, a: f64) -> f64 {
+    let (mut low, mut high) = (0.0, 1.0);
+    for _ in 0..50 {
+        let alpha = 0.5 * (low + high);
+        if total_reflectance(alpha, a) < albedo {
+            low = alpha;
+        } else {
+            high = alpha;
+        }
+    }
+    0.5 * (low + high)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{material::Lambertian, sphere::Sphere};
+    use approx::*;
+
+    fn ball(albedo: f64, radius: f64) -> Subsurface<Sphere<Lambertian>> {
+        Subsurface::new(
+            Sphere::new(
+                Point3::new(0.0, 0.0, 0.0),
+                radius,
+                Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+            ),
+            Color::new(albedo, albedo, albedo),
+            Color::new(0.01, 0.02, 0.04),
+            1.0,
+        )
+    }
+
+    #[test]
+    fn albedo_inversion() {
+        assert_relative_eq!(0.0, single_scattering_albedo(0.0), epsilon = 1e-4);
+        assert_relative_eq!(1.0, single_scattering_albedo(1.0), epsilon = 1e-4);
+        // Multiple scattering brightens the material
+        assert!(single_scattering_albedo(0.5) > 0.5);
+
+        for albedo in [0.1, 0.5, 0.9] {
+            let alpha = invert_reflectance(albedo, 2.0);
+            assert_relative_eq!(albedo, total_reflectance(alpha, 2.0), epsilon = 1e-9);
+        }
+    }
+
+    #[test]
+    fn profile_integrates_to_albedo() {
+        let dipole = Dipole::new(Color::new(0.8, 0.5, 0.2), Color::new(0.1, 0.2, 0.4), 1.3);
+
+        // Over the plane, in rings of growing width
+        let mut total = Color::new(0.0, 0.0, 0.0);
+        let steps = 200000;
+        for i in 0..steps {
+            let (r0, r1) = (
+                dipole.max_radius * (i as f64 / steps as f64).powi(3),
+                dipole.max_radius * ((i + 1) as f64 / steps as f64).powi(3),
+            );
+            let r = 0.5 * (r0 + r1);
+            total = total + dipole.profile(r) * (2.0 * PI * r * (r1 - r0));
+        }
+        assert_relative_eq!(0.8, total.x, epsilon = 0.01);
+        assert_relative_eq!(0.5, total.y, epsilon = 0.01);
+        assert_relative_eq!(0.2, total.z, epsilon = 0.01);
+    }
+
+    #[test]
+    fn walk_events() {
+        let ball = ball(0.8, 1.0);
+        let outside = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let entry = ball.hit(&outside, f64::INFINITY, 0.001).unwrap();
+        assert!(entry.front_face);
+        assert_relative_eq!(4.0, entry.t);
+
+        // Inside, rays scatter well before crossing the two units to the far side
+        let inside = Ray::new(Point3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut scattered = 0;
+        for _ in 0..1000 {
+            let record = ball.hit(&inside, f64::INFINITY, 0.001).unwrap();
+            if record.t < 2.0 - 1e-9 {
+                scattered += 1;
+            }
+        }
+        assert!(scattered > 950);
+    }
+
+    #[test]
+    fn walk_weights() {
+        let walk = ball(0.8, 1.0).walk;
+
+        // Averaged over the sampled distances, the weights of crossing and scattering match the
+        // transmittance and the fraction of the light scattered before a distance
+        let distance = 0.03;
+        let (mut crossed, mut scattered) = (Color::new(0.0, 0.0, 0.0), Color::new(0.0, 0.0, 0.0));
+        let samples = 200000;
+        let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        for _ in 0..samples {
+            let t = walk.sample_distance(None);
+            if t >= distance {
+                crossed = crossed + walk.crossing_weight(distance, None);
+            } else {
+                let record = HitRecord::new(ray.at(t), Vec3::new(1.0, 0.0, 0.0), t, true, &walk);
+                scattered = scattered + walk.scatter(&ray, record).0;
+            }
+        }
+
+        let transmittance = walk.transmittance(distance);
+        let expected_scattered =
+            walk.scattering / walk.extinction * (Color::new(1.0, 1.0, 1.0) - transmittance);
+        for (expected, actual) in [
+            (transmittance, crossed / samples as f64),
+            (expected_scattered, scattered / samples as f64),
+        ] {
+            assert_relative_eq!(expected.x, actual.x, epsilon = 0.01);
+            assert_relative_eq!(expected.z, actual.z, epsilon = 0.01);
+        }
+
+        // Along a single channel, the sampled distances need no weighting and walks continue
+        // with the albedo of the channel as probability
+        let green = ray.with_channel(Some(1));
+        let record = HitRecord::new(green.at(0.01), Vec3::new(1.0, 0.0, 0.0), 0.01, true, &walk);
+        let mut continued = 0;
+        for _ in 0..samples {
+            let (weight, scattered, success) = walk.scatter(&green, record);
+            if success {
+                continued += 1;
+                assert_relative_eq!(1.0, weight.y);
+                assert_eq!(Some(1), scattered.channel);
+            }
+        }
+        assert_relative_eq!(
+            walk.albedo().y,
+            continued as f64 / samples as f64,
+            epsilon = 0.01
+        );
+        assert_relative_eq!(1.0, walk.crossing_weight(distance, Some(1)).x);
+    }
+
+    #[test]
+    fn dipole_exits() {
+        // Large enough for the surface to be flat under the diffusion profile
+        let ball = ball(0.8, 100.0).with_dipole();
+        let ray = Ray::new(Point3::new(0.0, 0.0, 105.0), Vec3::new(0.0, 0.0, -1.0));
+        let record = ball.hit(&ray, f64::INFINITY, 0.001).unwrap();
+
+        let mut total = Color::new(0.0, 0.0, 0.0);
+        let samples = 20000;
+        for _ in 0..samples {
+            let (attenuation, scattered, success) = record.material.scatter(&ray, record);
+            if success {
+                total = total + attenuation;
+                // Leaving outwards from the surface of the ball, close to the entry
+                assert_relative_eq!(100.0, scattered.origin.length(), epsilon = 1e-6);
+                assert!(scattered.direction.dot(scattered.origin) >= 0.0);
+                assert!(scattered.origin.z > 99.0);
+            }
+        }
+        // Reflecting the albedo overall
+        assert_relative_eq!(0.8, total.y / samples as f64, epsilon = 0.03);
+    }
+}
@@ -1,7 +1,8 @@
 use core::fmt;
-use rand::Rng;
 use std::ops::{Add, Div, Mul, Neg, Sub};
 
+use crate::random;
+
 pub type Point3 = Vec3;
 
 #[derive(Clone, Copy)]
@@ -94,18 +95,27 @@ impl Div<f64> for Vec3 {
     }
 }
 
+impl Div<Vec3> for Vec3 {
+    type Output = Vec3;
+    fn div(self, vector: Vec3) -> Self::Output {
+        Vec3 {
+            x: self.x / vector.x,
+            y: self.y / vector.y,
+            z: self.z / vector.z,
+        }
+    }
+}
+
 impl Vec3 {
     pub fn new(x: f64, y: f64, z: f64) -> Vec3 {
         Vec3 { x, y, z }
     }
 
     pub fn random() -> Vec3 {
-        let mut rng = rand::thread_rng();
-
         Vec3 {
-            x: rng.gen_range(-1.0..1.0),
-            y: rng.gen_range(-1.0..1.0),
-            z: rng.gen_range(-1.0..1.0),
+            x: random::random_range(-1.0, 1.0),
+            y: random::random_range(-1.0, 1.0),
+            z: random::random_range(-1.0, 1.0),
         }
     }
 
@@ -114,20 +124,20 @@ impl Vec3 {
         while vector.length_squared() > 1.0 {
             vector = Self::random();
         }
-        return vector;
+        vector
     }
 
     pub fn random_unit_vector() -> Vec3 {
-        return Self::random_in_unit_sphere().unit_vector();
+        Self::random_in_unit_sphere().unit_vector()
     }
 
     pub fn random_in_hemishpere(normal: Vec3) -> Vec3 {
         let in_unit_sphere = Self::random_in_unit_sphere();
 
         if in_unit_sphere.dot(normal) > 0.0 {
-            return in_unit_sphere;
+            in_unit_sphere
         } else {
-            return -in_unit_sphere;
+            -in_unit_sphere
         }
     }
 
@@ -149,11 +159,11 @@ impl Vec3 {
 
     pub fn near_zero(&self) -> bool {
         const TOLERANCE: f64 = 1e-6;
-        return self.length() < TOLERANCE;
+        self.length() < TOLERANCE
     }
 
     pub fn reflect(self, normal: Vec3) -> Vec3 {
-        return self - 2.0 * self.dot(normal) * normal;
+        self - 2.0 * self.dot(normal) * normal
     }
 
     pub fn refract(self, normal: Vec3, etai_over_etat: f64) -> Vec3 {
@@ -161,15 +171,15 @@ impl Vec3 {
         let r_out_perp = etai_over_etat * (self + cos_theta * normal);
         let r_out_parallel = -(1.0 - r_out_perp.length_squared()).abs().sqrt() * normal;
 
-        return r_out_parallel + r_out_perp;
+        r_out_parallel + r_out_perp
     }
 
     pub fn cross_product(self, other: Vec3) -> Vec3 {
-        return Vec3::new(
+        Vec3::new(
             self.y * other.z - self.z * other.y,
             self.z * other.x - self.x * other.z,
             self.x * other.y - self.y * other.x,
-        );
+        )
     }
 }
 
@@ -243,6 +253,16 @@ mod tests {
         assert_vec3_equal!(expected, result);
     }
 
+    #[test]
+    fn hadamard_division() {
+        let vector1 = Vec3::new(6.0, 10.0, 12.0);
+        let vector2 = Vec3::new(6.0, 5.0, 4.0);
+        let result = vector1 / vector2;
+        let expected = Vec3::new(1.0, 2.0, 3.0);
+
+        assert_vec3_equal!(expected, result);
+    }
+
     #[test]
     fn length() {
         let vector = Vec3::new(1.0, 2.0, 3.0);
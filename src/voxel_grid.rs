@@ -0,0 +1,251 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, Read},
+    ops::{Add, Mul},
+};
+
+use crate::{color::Color, vec3::Vec3};
+
+/// Dense grid of voxels holding a density, and optionally a temperature in kelvins and an emitted
+/// radiance, interpolated trilinearly between the voxel centers.
+pub struct VoxelGrid {
+    pub nx: usize,
+    pub ny: usize,
+    pub nz: usize,
+    density: Vec<f64>,
+    temperature: Option<Vec<f64>>,
+    emission: Option<Vec<Color>>,
+}
+
+impl VoxelGrid {
+    /// Grid of `density` values, x varying fastest then y then z.
+    pub fn new(nx: usize, ny: usize, nz: usize, density: Vec<f64>) -> VoxelGrid {
+        assert_eq!(nx * ny * nz, density.len());
+        VoxelGrid {
+            nx,
+            ny,
+            nz,
+            density,
+            temperature: None,
+            emission: None,
+        }
+    }
+
+    /// Reads a header line `nx ny nz channel...` naming the channels among `density`,
+    /// `temperature` and `emission` (red, green and blue), then the channels of every voxel in
+    /// that order, x varying fastest then y then z. `#` starts a comment.
+    pub fn read(input: &mut impl Read) -> io::Result<VoxelGrid> {
+        let mut text = String::new();
+        input.read_to_string(&mut text)?;
+        let mut lines = text
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or_default().trim())
+            .filter(|line| !line.is_empty());
+
+        let header = lines.next().ok_or_else(|| invalid("the grid is empty"))?;
+        let mut fields = header.split_whitespace();
+        let mut size = || -> io::Result<usize> {
+            fields
+                .next()
+                .and_then(|value| value.parse().ok())
+                .filter(|size| *size > 0)
+                .ok_or_else(|| invalid("expected a header 'nx ny nz channel...'"))
+        };
+        let (nx, ny, nz) = (size()?, size()?, size()?);
+        let channels: Vec<&str> = fields.collect();
+        for channel in &channels {
+            if !["density", "temperature", "emission"].contains(channel) {
+                return Err(invalid(&format!("unknown channel '{}'", channel)));
+            }
+        }
+        if !channels.contains(&"density") {
+            return Err(invalid("the grid has no density channel"));
+        }
+
+        let values: Vec<f64> = lines
+            .flat_map(str::split_whitespace)
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map_err(|_| invalid("invalid voxel value"))?;
+        let stride: usize = channels
+            .iter()
+            .map(|channel| if *channel == "emission" { 3 } else { 1 })
+            .sum();
+        if values.len() != nx * ny * nz * stride {
+            return Err(invalid(&format!(
+                "expected {} values for {}x{}x{} voxels, got {}",
+                nx * ny * nz * stride,
+                nx,
+                ny,
+                nz,
+                values.len()
+            )));
+        }
+
+        let mut density = Vec::with_capacity(nx * ny * nz);
+        let mut temperature = Vec::new();
+        let mut emission = Vec::new();
+        for voxel in values.chunks(stride) {
+            let mut voxel = voxel.iter();
+            for channel in &channels {
+                let mut next = || *voxel.next().unwrap();
+                match *channel {
+                    "density" => density.push(next()),
+                    "temperature" => temperature.push(next()),
+                    _ => emission.push(Color::new(next(), next(), next())),
+                }
+            }
+        }
+
+        // Tracking takes densities over the majorant as probabilities
+        if density
+            .iter()
+            .any(|density| *density < 0.0 || density.is_nan())
+        {
+            return Err(invalid("densities cannot be negative"));
+        }
+
+        let mut grid = VoxelGrid::new(nx, ny, nz, density);
+        if channels.contains(&"temperature") {
+            grid = grid.with_temperature(temperature);
+        }
+        if channels.contains(&"emission") {
+            grid = grid.with_emission(emission);
+        }
+        Ok(grid)
+    }
+
+    pub fn load(path: &str) -> io::Result<VoxelGrid> {
+        VoxelGrid::read(&mut BufReader::new(File::open(path)?))
+            .map_err(|error| io::Error::new(error.kind(), format!("{}: {}", path, error)))
+    }
+
+    pub fn with_temperature(mut self, temperature: Vec<f64>) -> VoxelGrid {
+        assert_eq!(self.density.len(), temperature.len());
+        self.temperature = Some(temperature);
+        self
+    }
+
+    pub fn with_emission(mut self, emission: Vec<Color>) -> VoxelGrid {
+        assert_eq!(self.density.len(), emission.len());
+        self.emission = Some(emission);
+        self
+    }
+
+    /// Largest density of the grid, which bounds the interpolated ones.
+    pub fn max_density(&self) -> f64 {
+        self.density.iter().copied().fold(0.0, f64::max)
+    }
+
+    /// Density at a point of the unit cube spanned by the grid.
+    pub fn density(&self, p: Vec3) -> f64 {
+        self.interpolate(&self.density, p)
+    }
+
+    /// Temperature at a point of the unit cube, 0 without temperature channel.
+    pub fn temperature(&self, p: Vec3) -> f64 {
+        match &self.temperature {
+            Some(temperature) => self.interpolate(temperature, p),
+            None => 0.0,
+        }
+    }
+
+    /// Emitted radiance at a point of the unit cube, black without emission channel.
+    pub fn emission(&self, p: Vec3) -> Color {
+        match &self.emission {
+            Some(emission) => self.interpolate(emission, p),
+            None => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    /// Trilinear interpolation between the centers of the voxels, clamped at the faces.
+    fn interpolate<T>(&self, values: &[T], p: Vec3) -> T
+    where
+        T: Copy + Add<Output = T> + Mul<f64, Output = T>,
+    {
+        let axis = |coordinate: f64, size: usize| {
+            let x = (coordinate * size as f64 - 0.5).clamp(0.0, (size - 1) as f64);
+            let index = (x as usize).min(size - 1);
+            (index, (index + 1).min(size - 1), x - index as f64)
+        };
+        let (x0, x1, fx) = axis(p.x, self.nx);
+        let (y0, y1, fy) = axis(p.y, self.ny);
+        let (z0, z1, fz) = axis(p.z, self.nz);
+
+        let value = |x: usize, y: usize, z: usize| values[(z * self.ny + y) * self.nx + x];
+        let lerp = |a: T, b: T, t: f64| a * (1.0 - t) + b * t;
+
+        let y0_value = lerp(
+            lerp(value(x0, y0, z0), value(x1, y0, z0), fx),
+            lerp(value(x0, y0, z1), value(x1, y0, z1), fx),
+            fz,
+        );
+        let y1_value = lerp(
+            lerp(value(x0, y1, z0), value(x1, y1, z0), fx),
+            lerp(value(x0, y1, z1), value(x1, y1, z1), fx),
+            fz,
+        );
+        lerp(y0_value, y1_value, fy)
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::*;
+
+    const GRID: &str = "# two voxels along x, one along y and z
+2 1 1 temperature density emission
+1000  0.0   1 0 0
+3000  2.0   0 0 1 # hot voxel";
+
+    #[test]
+    fn read_channels() {
+        let grid = VoxelGrid::read(&mut GRID.as_bytes()).unwrap();
+        assert_eq!((2, 1, 1), (grid.nx, grid.ny, grid.nz));
+        assert_eq!(2.0, grid.max_density());
+
+        // Voxel centers
+        let first = Vec3::new(0.25, 0.5, 0.5);
+        let second = Vec3::new(0.75, 0.5, 0.5);
+        assert_relative_eq!(0.0, grid.density(first));
+        assert_relative_eq!(3000.0, grid.temperature(second));
+        assert_relative_eq!(1.0, grid.emission(second).z);
+    }
+
+    #[test]
+    fn trilinear() {
+        let grid = VoxelGrid::read(&mut GRID.as_bytes()).unwrap();
+        assert_relative_eq!(1.0, grid.density(Vec3::new(0.5, 0.5, 0.5)));
+        assert_relative_eq!(0.5, grid.density(Vec3::new(0.375, 0.1, 0.9)));
+        assert_relative_eq!(0.5, grid.emission(Vec3::new(0.5, 0.0, 1.0)).x);
+
+        // Values are held beyond the outer voxel centers
+        assert_relative_eq!(0.0, grid.density(Vec3::new(0.0, 0.5, 0.5)));
+        assert_relative_eq!(2.0, grid.density(Vec3::new(1.0, 0.5, 0.5)));
+
+        let cube = VoxelGrid::new(2, 2, 2, vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0]);
+        assert_relative_eq!(3.5, cube.density(Vec3::new(0.5, 0.5, 0.5)));
+        assert_relative_eq!(0.0, cube.temperature(Vec3::new(0.5, 0.5, 0.5)));
+    }
+
+    #[test]
+    fn read_invalid() {
+        for text in [
+            "",
+            "2 1 density",
+            "1 1 1 temperature\n300",
+            "1 1 1 density smoke\n1 2",
+            "2 1 1 density\n1",
+            "1 1 1 density\nthick",
+            "2 1 1 density\n1 -0.5",
+            "1 1 1 density\nNaN",
+        ] {
+            assert!(VoxelGrid::read(&mut text.as_bytes()).is_err(), "{}", text);
+        }
+    }
+}